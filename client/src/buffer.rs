@@ -0,0 +1,244 @@
+#![deny(warnings)]
+// bounded on-disk queue of failed reports, replayed in order once the server is back
+// file format: [u32 le len][StatRequest pb] ...
+use prost::Message;
+use std::collections::{HashSet, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::future::Future;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use stat_common::server_status::StatRequest;
+
+const BATCH: usize = 100;
+
+pub struct Buffer {
+    path: PathBuf,
+    max: usize,
+    // (seq, report), seq tells apart reports within the same second
+    queue: Mutex<VecDeque<(u64, StatRequest)>>,
+    seq: AtomicU64,
+    replaying: AtomicBool,
+}
+
+fn encode(stat: &StatRequest, buf: &mut Vec<u8>) {
+    let data = stat.encode_to_vec();
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&data);
+}
+
+fn decode(mut data: &[u8]) -> Vec<StatRequest> {
+    let mut items = Vec::new();
+    while data.len() >= 4 {
+        let len = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        // truncated tail, eg. killed while writing
+        let Some(frame) = data.get(4..4 + len) else {
+            break;
+        };
+        match StatRequest::decode(frame) {
+            Ok(o) => items.push(o),
+            Err(err) => error!("buffer decode error => {err:?}"),
+        }
+        data = &data[4 + len..];
+    }
+    items
+}
+
+impl Buffer {
+    pub fn open(path: &str, max: usize) -> anyhow::Result<Self> {
+        let mut items = match fs::read(path) {
+            Ok(data) => decode(&data),
+            Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        items.sort_by_key(|o| o.latest_ts);
+        if items.len() > max {
+            items.drain(..items.len() - max);
+        }
+        let o = Self {
+            path: PathBuf::from(path),
+            max: max.max(1),
+            seq: AtomicU64::new(items.len() as u64),
+            queue: Mutex::new((0..).zip(items).collect()),
+            replaying: AtomicBool::new(false),
+        };
+        o.save(&o.queue.lock().unwrap())?;
+        Ok(o)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    fn save(&self, queue: &VecDeque<(u64, StatRequest)>) -> anyhow::Result<()> {
+        let mut buf = Vec::new();
+        for (_, o) in queue {
+            encode(o, &mut buf);
+        }
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, buf)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    pub fn push(&self, mut stat: StatRequest) {
+        stat.backfill = true;
+        // static, already known by the server
+        stat.sys_info = None;
+        stat.ip_info = None;
+
+        let mut queue = self.queue.lock().unwrap();
+        // reports may fail out of order
+        let idx = queue.partition_point(|(_, o)| o.latest_ts <= stat.latest_ts);
+        let mut buf = Vec::new();
+        encode(&stat, &mut buf);
+        queue.insert(idx, (self.seq.fetch_add(1, Ordering::Relaxed), stat));
+
+        let res = if queue.len() > self.max {
+            // drop the oldest, leave some room to avoid rewriting the file on every push
+            let n = queue.len() - self.max + self.max / 10;
+            warn!("report buffer full, drop {n} oldest reports");
+            queue.drain(..n);
+            self.save(&queue)
+        } else {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .and_then(|mut f: File| f.write_all(&buf))
+                .map_err(anyhow::Error::new)
+        };
+        if let Err(err) = res {
+            error!("report buffer write `{}` error => {err:?}", self.path.display());
+        }
+    }
+
+    /// send buffered reports in order, stop at the first failure
+    pub async fn replay<F, Fut>(&self, send: F)
+    where
+        F: Fn(StatRequest) -> Fut,
+        Fut: Future<Output = anyhow::Result<()>>,
+    {
+        if self.replaying.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        loop {
+            let batch: Vec<(u64, StatRequest)> = self.queue.lock().unwrap().iter().take(BATCH).cloned().collect();
+            if batch.is_empty() {
+                break;
+            }
+            let mut sent = HashSet::new();
+            let mut failed = false;
+            for (seq, o) in batch {
+                if let Err(err) = send(o).await {
+                    warn!("replay report error => {err:?}");
+                    failed = true;
+                    break;
+                }
+                sent.insert(seq);
+            }
+
+            // pushes may have reordered or dropped items meanwhile
+            let mut queue = self.queue.lock().unwrap();
+            queue.retain(|(seq, _)| !sent.contains(seq));
+            if let Err(err) = self.save(&queue) {
+                error!("report buffer write `{}` error => {err:?}", self.path.display());
+            }
+            info!("replay {} reports, {} left", sent.len(), queue.len());
+            if failed {
+                break;
+            }
+        }
+        self.replaying.store(false, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat(ts: u64) -> StatRequest {
+        StatRequest {
+            name: "h1".to_string(),
+            latest_ts: ts,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_buffer() {
+        let path = std::env::temp_dir().join(format!("stat_client_buffer_{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let buffer = Buffer::open(path, 10).unwrap();
+        for ts in [3, 1, 2] {
+            buffer.push(stat(ts));
+        }
+        // reopen, sorted by ts
+        let buffer = Buffer::open(path, 10).unwrap();
+        assert_eq!(buffer.len(), 3);
+
+        // drop the oldest when full
+        for ts in 4..=12 {
+            buffer.push(stat(ts));
+        }
+        assert_eq!(buffer.len(), 10);
+        assert_eq!(buffer.queue.lock().unwrap()[0].1.latest_ts, 3);
+
+        let sent = Mutex::new(Vec::new());
+        buffer
+            .replay(|o| {
+                let ok = o.latest_ts < 6;
+                if ok {
+                    sent.lock().unwrap().push(o.latest_ts);
+                }
+                async move {
+                    if ok {
+                        Ok(())
+                    } else {
+                        anyhow::bail!("offline")
+                    }
+                }
+            })
+            .await;
+        assert_eq!(*sent.lock().unwrap(), vec![3, 4, 5]);
+        assert_eq!(buffer.len(), 7);
+        assert!(buffer.queue.lock().unwrap().iter().all(|(_, o)| o.backfill));
+
+        // reports within the same second
+        buffer.push(stat(7));
+        buffer
+            .replay(|o| async move {
+                if o.latest_ts < 7 {
+                    Ok(())
+                } else {
+                    anyhow::bail!("offline")
+                }
+            })
+            .await;
+        assert_eq!(buffer.len(), 7);
+
+        let first = Mutex::new(true);
+        buffer
+            .replay(|_| {
+                let ok = std::mem::take(&mut *first.lock().unwrap());
+                async move {
+                    if ok {
+                        Ok(())
+                    } else {
+                        anyhow::bail!("offline")
+                    }
+                }
+            })
+            .await;
+        assert_eq!(buffer.len(), 6);
+        assert_eq!(buffer.queue.lock().unwrap()[0].1.latest_ts, 7);
+
+        buffer.replay(|_| async { Ok(()) }).await;
+        assert_eq!(Buffer::open(path, 10).unwrap().len(), 0);
+        let _ = fs::remove_file(path);
+    }
+}
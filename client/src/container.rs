@@ -0,0 +1,256 @@
+#![deny(warnings)]
+// `--containers`, docker / podman containers over the engine api socket
+// cpu & memory from the cgroup v2 of the container, network from its net namespace
+use anyhow::{bail, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use stat_common::server_status::{ContainerInfo, StatRequest};
+
+use crate::probe::http::parse_response;
+use crate::status::parse_net_dev;
+
+const SAMPLE_PERIOD: Duration = Duration::from_secs(5);
+const TIMEOUT: Duration = Duration::from_secs(3);
+const SOCKETS: [&str; 2] = ["/var/run/docker.sock", "/run/podman/podman.sock"];
+
+static G_CONTAINERS: LazyLock<Mutex<Vec<ContainerInfo>>> = LazyLock::new(Mutex::default);
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Summary {
+    id: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+struct State {
+    status: String,
+    pid: u32,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+struct Config {
+    image: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Inspect {
+    id: String,
+    name: String,
+    #[serde(default)]
+    restart_count: u32,
+    #[serde(default)]
+    state: State,
+    #[serde(default)]
+    config: Config,
+}
+
+/// GET over the unix socket, http/1.0 keeps the body unchunked
+fn get<T: DeserializeOwned>(sock: &Path, path: &str) -> Result<T> {
+    let mut stream = UnixStream::connect(sock)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    stream.write_all(format!("GET {path} HTTP/1.0\r\nHost: localhost\r\n\r\n").as_bytes())?;
+
+    let mut resp = Vec::new();
+    stream.read_to_end(&mut resp)?;
+    let (status, body) = parse_response(&resp)?;
+    if status != 200 {
+        bail!("GET {path} => http status {status}");
+    }
+    Ok(serde_json::from_slice(&body)?)
+}
+
+fn socket(sock: &str) -> Option<PathBuf> {
+    if !sock.is_empty() {
+        return Some(PathBuf::from(sock));
+    }
+    let rootless = std::env::var("XDG_RUNTIME_DIR")
+        .ok()
+        .map(|dir| format!("{dir}/podman/podman.sock"));
+    SOCKETS
+        .iter()
+        .map(ToString::to_string)
+        .chain(rootless)
+        .map(PathBuf::from)
+        .find(|p| p.exists())
+}
+
+// `0::/system.slice/docker-<id>.scope` of /proc/<pid>/cgroup
+fn cgroup_dir(pid: u32) -> Option<PathBuf> {
+    let content = fs::read_to_string(format!("/proc/{pid}/cgroup")).ok()?;
+    let path = content.lines().find_map(|l| l.strip_prefix("0::"))?;
+    Some(Path::new("/sys/fs/cgroup").join(path.trim_start_matches('/')))
+}
+
+fn read_kv(content: &str, key: &str) -> Option<u64> {
+    content.lines().find_map(|l| {
+        let (k, v) = l.split_once(' ')?;
+        k.eq(key).then(|| v.trim().parse().ok()).flatten()
+    })
+}
+
+/// (cpu usage µs, memory without page cache, memory limit, 0 if unlimited)
+fn cgroup_stat(dir: &Path) -> (u64, u64, u64) {
+    let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap_or_default();
+    let usage = read_kv(&read("cpu.stat"), "usage_usec").unwrap_or(0);
+    let inactive_file = read_kv(&read("memory.stat"), "inactive_file").unwrap_or(0);
+    let memory = read("memory.current")
+        .trim()
+        .parse::<u64>()
+        .unwrap_or(0)
+        .saturating_sub(inactive_file);
+    // `max` when unlimited
+    let limit = read("memory.max").trim().parse::<u64>().unwrap_or(0);
+    (usage, memory, limit)
+}
+
+/// (rx, tx) bytes of the container namespace, lo excluded
+fn net_bytes(pid: u32) -> (u64, u64) {
+    fs::read_to_string(format!("/proc/{pid}/net/dev"))
+        .map(|content| {
+            parse_net_dev(&content)
+                .iter()
+                .filter(|o| o.name != "lo")
+                .fold((0, 0), |(rx, tx), o| (rx + o.rx_bytes, tx + o.tx_bytes))
+        })
+        .unwrap_or_default()
+}
+
+#[derive(Default)]
+struct Collector {
+    // id => (cpu usage µs, sampled at)
+    pre_cpu: HashMap<String, (u64, Instant)>,
+}
+
+impl Collector {
+    #[allow(clippy::cast_precision_loss)]
+    fn collect(&mut self, sock: &Path) -> Result<Vec<ContainerInfo>> {
+        let list: Vec<Summary> = get(sock, "/containers/json?all=1")?;
+        let mut cpu = HashMap::new();
+        let mut containers = Vec::new();
+        for summary in list {
+            let o: Inspect = match get(sock, &format!("/containers/{}/json", summary.id)) {
+                Ok(o) => o,
+                // removed in the meantime
+                Err(err) => {
+                    debug!("inspect container {} => {err}", summary.id);
+                    continue;
+                }
+            };
+            let mut info = ContainerInfo {
+                name: o.name.trim_start_matches('/').to_string(),
+                id: o.id.chars().take(12).collect(),
+                image: o.config.image,
+                running: o.state.status == "running",
+                state: o.state.status,
+                restart_count: o.restart_count,
+                ..Default::default()
+            };
+            if info.running && o.state.pid > 0 {
+                if let Some(dir) = cgroup_dir(o.state.pid) {
+                    let (usage, memory, limit) = cgroup_stat(&dir);
+                    let now = Instant::now();
+                    if let Some((pre, ts)) = self.pre_cpu.get(&o.id) {
+                        let elapsed = now.duration_since(*ts).as_micros().max(1) as f64;
+                        info.cpu = (usage.saturating_sub(*pre) as f64 * 1000.0 / elapsed).round() / 10.0;
+                    }
+                    cpu.insert(o.id.clone(), (usage, now));
+                    info.memory = memory;
+                    info.memory_limit = limit;
+                }
+                (info.net_rx, info.net_tx) = net_bytes(o.state.pid);
+            }
+            containers.push(info);
+        }
+        self.pre_cpu = cpu;
+        containers.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(containers)
+    }
+}
+
+pub fn start_container_collect_t() {
+    thread::spawn(|| {
+        let mut collector = Collector::default();
+        loop {
+            let args = crate::remote::args();
+            let containers = if args.containers {
+                match socket(&args.container_sock) {
+                    Some(sock) => collector.collect(&sock).unwrap_or_else(|err| {
+                        warn!("collect containers from {} => {err}", sock.display());
+                        Vec::new()
+                    }),
+                    None => Vec::new(),
+                }
+            } else {
+                Vec::new()
+            };
+            if let Ok(mut o) = G_CONTAINERS.lock() {
+                *o = containers;
+            }
+            thread::sleep(SAMPLE_PERIOD);
+        }
+    });
+}
+
+pub fn sample(stat: &mut StatRequest) {
+    if let Ok(o) = G_CONTAINERS.lock() {
+        stat.containers.clone_from(&o);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    #[test]
+    fn test_get() {
+        let dir = std::env::temp_dir().join(format!("ssr-container-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let sock = dir.join("docker.sock");
+        let _ = fs::remove_file(&sock);
+        let listener = UnixListener::bind(&sock).unwrap();
+        thread::spawn(move || {
+            for (stream, body) in listener.incoming().zip([
+                r#"[{"Id":"abc"}]"#,
+                r#"{"Id":"abcdef0123456789","Name":"/web","RestartCount":2,"State":{"Status":"exited","Pid":0},"Config":{"Image":"nginx:1"}}"#,
+            ]) {
+                let mut stream = stream.unwrap();
+                let mut buf = [0u8; 1024];
+                let n = stream.read(&mut buf).unwrap();
+                assert!(buf[..n].starts_with(b"GET /containers/"));
+                let _ = write!(stream, "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{body}");
+            }
+        });
+
+        let o = Collector::default().collect(&sock).unwrap();
+        assert_eq!(o.len(), 1);
+        assert_eq!((o[0].name.as_str(), o[0].id.as_str()), ("web", "abcdef012345"));
+        assert!(!o[0].running && o[0].state == "exited" && o[0].restart_count == 2 && o[0].image == "nginx:1");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_cgroup_stat() {
+        let dir = std::env::temp_dir().join(format!("ssr-cgroup-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("cpu.stat"), "usage_usec 1500\nuser_usec 1000\n").unwrap();
+        fs::write(dir.join("memory.current"), "1048576\n").unwrap();
+        fs::write(dir.join("memory.stat"), "anon 1000\ninactive_file 4096\n").unwrap();
+        fs::write(dir.join("memory.max"), "max\n").unwrap();
+        assert_eq!(cgroup_stat(&dir), (1500, 1_048_576 - 4096, 0));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
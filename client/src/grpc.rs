@@ -1,33 +1,66 @@
 // #![allow(unused)]
+use std::collections::VecDeque;
+use std::future::Future;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
-use tonic::{metadata::MetadataValue, Request};
+use tokio::sync::mpsc;
+use tokio::time::{sleep, sleep_until, Instant};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::{interceptor::InterceptedService, Interceptor};
+use tonic::{Code, Request, Status, Streaming};
 use tower::timeout::Timeout;
 use url::Url;
 
 use stat_common::server_status::server_status_client::ServerStatusClient;
-use stat_common::server_status::StatRequest;
+use stat_common::server_status::{ConfigRequest, Control, StatRequest};
 use tonic::transport::Channel;
 use tonic::transport::{ClientTlsConfig,Identity,Certificate};
+use crate::buffer::Buffer;
 use crate::sample_all;
+use crate::open_buffer;
+use crate::remote;
 use crate::Args;
 
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+const KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+struct Auth {
+    token: MetadataValue<Ascii>,
+    ssr_auth: &'static str,
+}
+
+impl Interceptor for Auth {
+    fn call(&mut self, mut req: Request<()>) -> Result<Request<()>, Status> {
+        req.metadata_mut().insert("authorization", self.token.clone());
+        req.metadata_mut()
+            .insert("ssr-auth", MetadataValue::from_static(self.ssr_auth));
+        Ok(req)
+    }
+}
+
+type Client = ServerStatusClient<InterceptedService<Timeout<Channel>, Auth>>;
 
 pub async fn report(args: &Args, stat_base: &mut StatRequest) -> anyhow::Result<()> {
     let auth_user: String;
-    let ssr_auth: &[u8];
+    let ssr_auth: &'static str;
     if args.gid.is_empty() {
         auth_user = args.user.clone();
-        ssr_auth = b"single";
+        ssr_auth = "single";
     } else {
         auth_user = args.gid.clone();
-        ssr_auth = b"group";
+        ssr_auth = "group";
     }
     let token = MetadataValue::try_from(format!("{}@_@{}", auth_user, args.pass))?;
 
     let addr = args.addr.replace("grpcs://", "https://");
+    let endpoint = Channel::from_shared(addr.clone())?
+        .http2_keep_alive_interval(KEEPALIVE_INTERVAL)
+        .keep_alive_timeout(KEEPALIVE_TIMEOUT);
     let channel: Channel;
     if args.mtls {
         // === mTLS 模式 ===
@@ -45,42 +78,205 @@ pub async fn report(args: &Args, stat_base: &mut StatRequest) -> anyhow::Result<
             .domain_name(domain_name)
             .ca_certificate(ca)
             .identity(client_identity);
-            
-        channel = Channel::from_shared(addr)?
+
+        channel = endpoint
             .tls_config(tls_config)?
             .connect().await?;
     } else if addr.starts_with("https://") {
         // TLS
         let tls_config = ClientTlsConfig::new();
-        channel = Channel::from_shared(addr)?.tls_config(tls_config)?.connect().await?;
+        channel = endpoint.tls_config(tls_config)?.connect().await?;
     } else {
-        channel = Channel::from_shared(addr)?.connect().await?;
+        channel = endpoint.connect().await?;
     }
 
     let timeout_channel = Timeout::new(channel, Duration::from_millis(3000));
-    let grpc_client = ServerStatusClient::with_interceptor(timeout_channel, move |mut req: Request<()>| {
-        req.metadata_mut().insert("authorization", token.clone());
-        req.metadata_mut()
-            .insert("ssr-auth", MetadataValue::try_from(ssr_auth).unwrap());
-        Ok(req)
-    });
+    let grpc_client = ServerStatusClient::with_interceptor(timeout_channel, Auth { token, ssr_auth });
+    let buffer = open_buffer(args);
+    // next buffered sample while disconnected
+    let mut next = Instant::now() + Duration::from_secs(args.report_interval);
+
+    loop {
+        match report_stream(args, stat_base, &grpc_client, buffer.as_ref(), &mut next).await {
+            Err(status) if status.code() == Code::Unimplemented => {
+                warn!("server does not support ReportStream, fallback to unary report");
+                report_unary(stat_base, &grpc_client, buffer.as_ref());
+            }
+            Err(status) => {
+                error!("grpc report stream status => {status:?}");
+            }
+            Ok(()) => {
+                warn!("grpc report stream closed");
+            }
+        }
+
+        // reconnect, keep sampling meanwhile
+        let retry = sleep(Duration::from_secs(remote::args().report_interval));
+        buffer_while(retry, stat_base, buffer.as_ref(), &mut next).await;
+    }
+}
+
+/// drive `fut` while the server is unreachable, samples go to the buffer at the report interval meanwhile
+async fn buffer_while<F: Future>(
+    fut: F,
+    stat_base: &mut StatRequest,
+    buffer: Option<&Arc<Buffer>>,
+    next: &mut Instant,
+) -> F::Output {
+    let Some(buffer) = buffer else {
+        return fut.await;
+    };
+    tokio::pin!(fut);
+    loop {
+        tokio::select! {
+            biased;
+            res = &mut fut => return res,
+            () = sleep_until(*next) => {
+                let args = remote::args();
+                *next = Instant::now() + Duration::from_secs(args.report_interval);
+                buffer.push(sample_all(&args, stat_base));
+            }
+        }
+    }
+}
+
+fn replay(client: &Client, buffer: Option<&Arc<Buffer>>) {
+    if let Some(buffer) = buffer {
+        let (client, buffer) = (client.clone(), buffer.clone());
+        tokio::spawn(async move {
+            buffer
+                .replay(|o| {
+                    let mut client = client.clone();
+                    async move {
+                        client.report(Request::new(o)).await?;
+                        Ok(())
+                    }
+                })
+                .await;
+        });
+    }
+}
+
+async fn fetch_config(client: &Client, args: &Args) {
+    let request = Request::new(ConfigRequest { name: args.user.clone() });
+    match client.clone().get_config(request).await {
+        Ok(resp) => remote::apply(resp.get_ref()),
+        Err(status) => warn!("fetch remote config status => {status:?}"),
+    }
+}
+
+/// one long lived stream, the server pushes `Control` back on it
+async fn report_stream(
+    args: &Args,
+    stat_base: &mut StatRequest,
+    client: &Client,
+    buffer: Option<&Arc<Buffer>>,
+    next: &mut Instant,
+) -> Result<(), Status> {
+    let (tx, rx) = mpsc::channel(4);
+    let mut client_t = client.clone();
+    let connect = client_t.report_stream(Request::new(ReceiverStream::new(rx)));
+    let mut controls = buffer_while(connect, stat_base, buffer, next).await?.into_inner();
+    info!("grpc report stream connected");
+    replay(client, buffer);
+
+    // sent but not acked by the server yet, queued or in flight
+    let mut unacked = VecDeque::new();
+    let res = stream_loop(args, stat_base, client, &tx, &mut controls, &mut unacked).await;
+    if let Some(buffer) = buffer {
+        for o in unacked {
+            buffer.push(o);
+        }
+    }
+    res
+}
+
+async fn stream_loop(
+    args: &Args,
+    stat_base: &mut StatRequest,
+    client: &Client,
+    tx: &mpsc::Sender<StatRequest>,
+    controls: &mut Streaming<Control>,
+    unacked: &mut VecDeque<StatRequest>,
+) -> Result<(), Status> {
+    let mut disabled = false;
+    // pushed right away, the fetched ClientConfig only follows the version change
+    let mut interval = 0;
+    // sys_info & ip_info with the first sample, then only on change or request
+    let mut send_extra = true;
+    let mut ip_info = None;
+    let (mut sent, mut acked) = (0_u64, 0_u64);
+    let mut next = Instant::now();
+    loop {
+        tokio::select! {
+            ctl = controls.message() => {
+                let Some(ctl) = ctl? else {
+                    return Ok(());
+                };
+                trace!("grpc control => {ctl:?}");
+                while acked < ctl.acked.min(sent) {
+                    unacked.pop_front();
+                    acked += 1;
+                }
+                if ctl.config_version != remote::version() {
+                    fetch_config(client, args).await;
+                }
+                if disabled && !ctl.disabled {
+                    send_extra = true;
+                }
+                disabled = ctl.disabled;
+                interval = ctl.interval;
+                send_extra |= ctl.request_sys_info;
+                continue;
+            }
+            () = sleep_until(next) => {}
+        }
+        let cur = remote::args();
+        next = Instant::now() + Duration::from_secs(if interval > 0 { interval } else { cur.report_interval });
+        if disabled {
+            continue;
+        }
+
+        let mut stat_rt = sample_all(&cur, stat_base);
+        if send_extra || stat_rt.ip_info != ip_info {
+            send_extra = false;
+            ip_info.clone_from(&stat_rt.ip_info);
+        } else {
+            stat_rt.sys_info = None;
+            stat_rt.ip_info = None;
+        }
+        unacked.push_back(stat_rt.clone());
+        sent += 1;
+        if tx.send(stat_rt).await.is_err() {
+            return Ok(());
+        }
+    }
+}
 
+/// one request per sample, for servers without ReportStream
+fn report_unary(stat_base: &mut StatRequest, grpc_client: &Client, buffer: Option<&Arc<Buffer>>) -> ! {
     loop {
-        let stat_rt = sample_all(args, stat_base);
+        let args = remote::args();
+        let stat_rt = sample_all(&args, stat_base);
         let mut client = grpc_client.clone();
+        let buffer = buffer.cloned();
 
         tokio::spawn(async move {
-            let request = tonic::Request::new(stat_rt);
+            let request = tonic::Request::new(stat_rt.clone());
             match client.report(request).await {
                 Ok(resp) => {
                     info!("grpc report resp => {resp:?}");
+                    replay(&client, buffer.as_ref());
                 }
                 Err(status) => {
                     error!("grpc report status => {status:?}");
+                    if let Some(buffer) = buffer {
+                        buffer.push(stat_rt);
+                    }
                 }
             }
         });
 
         thread::sleep(Duration::from_secs(args.report_interval));
     }
-}
\ No newline at end of file
+}
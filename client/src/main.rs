@@ -8,6 +8,7 @@ use once_cell::sync::Lazy;
 use prost::Message;
 use std::net::ToSocketAddrs;
 use std::process;
+use std::sync::Arc;
 use std::sync::Mutex;
 use std::thread;
 use std::time::Duration;
@@ -18,11 +19,17 @@ use std::thread::sleep;
 use stat_common::server_status::{IpInfo, StatRequest, SysInfo};
 type GenericError = Box<dyn std::error::Error + Send + Sync>;
 type Result<T> = std::result::Result<T, GenericError>;
+mod buffer;
+#[cfg(target_os = "linux")]
+mod container;
 mod geoip;
 mod grpc;
+mod probe;
+mod remote;
 mod status;
 mod sys_info;
 mod vnstat;
+mod watch;
 
 static CU: &str = "cu.tz.cloudcpp.com:80";
 static CT: &str = "ct.tz.cloudcpp.com:80";
@@ -56,6 +63,13 @@ pub struct Args {
         help = "vnstat month rotate 1-28"
     )]
     vnstat_mr: u32,
+    #[arg(
+        long = "traffic-file",
+        env = "SSR_TRAFFIC_FILE",
+        default_value = "traffic.json",
+        help = "total & monthly traffic counter of --vnstat, imported from vnstat when missing, relative to the working dir"
+    )]
+    traffic_file: String,
     #[arg(
         long = "interval",
         env = "SSR_INTERVAL",
@@ -87,6 +101,29 @@ pub struct Args {
     cm_addr: String,
     #[arg(long = "cu",  env = "SSR_CU_ADDR", default_value = CU, help = "China Unicom probe addr")]
     cu_addr: String,
+    #[arg(
+        long = "probe",
+        env = "SSR_PROBE",
+        value_delimiter = ',',
+        help = "latency probe, name=host:port, repeatable, default: --cu/--ct/--cm"
+    )]
+    probe: Vec<String>,
+    #[arg(
+        long = "watch",
+        env = "SSR_WATCH",
+        value_delimiter = ',',
+        help = "watch process or systemd unit, [name=]nginx | [name=]re:<cmdline regex> | [name=]systemd:<unit>, repeatable"
+    )]
+    watch: Vec<String>,
+    #[arg(long = "containers", env = "SSR_CONTAINERS", help = "report docker/podman containers, default:false")]
+    containers: bool,
+    #[arg(
+        long = "container-sock",
+        env = "SSR_CONTAINER_SOCK",
+        default_value = "",
+        help = "docker/podman api socket, default: auto detect"
+    )]
+    container_sock: String,
     #[arg(long = "sys-info", help = "show sys info, default:false")]
     sys_info: bool,
     #[arg(long = "ip-info", help = "show ip info, default:false")]
@@ -170,10 +207,47 @@ pub struct Args {
         help = "exclude iface"
     )]
     exclude_iface: Vec<String>,
+    #[arg(
+        long = "mount",
+        env = "SSR_MOUNT",
+        value_delimiter = ',',
+        help = "mount point list, eg: /,/data, default: all"
+    )]
+    mount: Vec<String>,
+    #[arg(
+        long = "exclude-mount",
+        env = "SSR_EXCLUDE_MOUNT",
+        value_delimiter = ',',
+        help = "exclude mount points and everything below, eg: /boot,/snap"
+    )]
+    exclude_mount: Vec<String>,
+    #[arg(
+        long = "fs",
+        env = "SSR_FS",
+        value_delimiter = ',',
+        help = "file system types, eg: ext4,xfs, default: common disk file systems"
+    )]
+    fs: Vec<String>,
+    #[arg(long = "exclude-fs", env = "SSR_EXCLUDE_FS", value_delimiter = ',', help = "exclude file system types")]
+    exclude_fs: Vec<String>,
     #[arg(long, env = "SSR_PROXY", default_value = "", help = "proxy")]
     proxy: String,
     #[arg(long, env = "SSR_NO_PROXY", default_value = "", help = "no proxy, eg: ip-api.com")]
     no_proxy: String,
+    #[arg(
+        long = "buffer-file",
+        env = "SSR_BUFFER_FILE",
+        default_value = "",
+        help = "buffer failed reports in this file and replay them later, default:disabled"
+    )]
+    buffer_file: String,
+    #[arg(
+        long = "buffer-max",
+        env = "SSR_BUFFER_MAX",
+        default_value_t = 3600,
+        help = "max buffered reports"
+    )]
+    buffer_max: usize,
 }
 
 impl Args {
@@ -190,6 +264,21 @@ impl Args {
         }
         false
     }
+
+    #[must_use]
+    pub fn skip_mount(&self, mount_point: &str, fs: &str) -> bool {
+        let fs = fs.to_lowercase();
+        if !sys_info::expect_fs(&self.fs, &fs) || self.exclude_fs.iter().any(|o| fs.eq(&o.to_lowercase())) {
+            return true;
+        }
+        if !self.mount.is_empty() && !self.mount.iter().any(|o| mount_point.eq(o)) {
+            return true;
+        }
+        self.exclude_mount.iter().any(|o| {
+            let o = o.trim_end_matches('/');
+            mount_point.eq(o) || mount_point.strip_prefix(o).is_some_and(|s| s.starts_with('/'))
+        })
+    }
 }
 
 fn sample_all(args: &Args, stat_base: &StatRequest) -> StatRequest {
@@ -217,6 +306,57 @@ fn sample_all(args: &Args, stat_base: &StatRequest) -> StatRequest {
     stat_rt
 }
 
+pub fn open_buffer(args: &Args) -> Option<Arc<buffer::Buffer>> {
+    if args.buffer_file.is_empty() {
+        return None;
+    }
+    match buffer::Buffer::open(&args.buffer_file, args.buffer_max) {
+        Ok(o) => {
+            eprintln!("report buffer `{}`, {} pending", args.buffer_file, o.len());
+            Some(Arc::new(o))
+        }
+        Err(err) => {
+            error!("open report buffer `{}` error => {err:?}", args.buffer_file);
+            None
+        }
+    }
+}
+
+async fn http_send(client: &reqwest::Client, args: &Args, stat: &StatRequest) -> anyhow::Result<()> {
+    let body_data: Vec<u8>;
+    let mut content_type = "application/octet-stream";
+    if args.json {
+        let data = serde_json::to_string(stat)?;
+        trace!("json_str => {:?}", serde_json::to_string(&data)?);
+        body_data = data.into();
+        content_type = "application/json";
+    } else {
+        body_data = stat.encode_to_vec();
+        // content_type = "application/octet-stream";
+    }
+    // byte 581, json str 1281
+    // dbg!(&body_data.len());
+
+    let (auth_user, ssr_auth) = if args.gid.is_empty() {
+        (&args.user, "single")
+    } else {
+        (&args.gid, "group")
+    };
+
+    let resp = client
+        .post(&args.addr)
+        .basic_auth(auth_user, Some(&args.pass))
+        .timeout(Duration::from_secs(3))
+        .header(header::CONTENT_TYPE.as_str(), content_type)
+        .header("ssr-auth", ssr_auth)
+        .body(body_data)
+        .send()
+        .await?;
+    info!("report resp => {resp:?}");
+    resp.error_for_status()?;
+    Ok(())
+}
+
 fn http_report(args: &Args, stat_base: &mut StatRequest) -> Result<()> {
     let mut domain = args.addr.split('/').collect::<Vec<&str>>()[2].to_owned();
     if !domain.contains(':') {
@@ -265,54 +405,35 @@ fn http_report(args: &Args, stat_base: &mut StatRequest) -> Result<()> {
     }
 
     let http_client = http_client_builder.build()?;
+    let buffer = open_buffer(args);
+    tokio::spawn(refresh_remote_config(http_client.clone(), args.clone()));
     loop {
-        let stat_rt = sample_all(args, stat_base);
-
-        let body_data: Option<Vec<u8>>;
-        let mut content_type = "application/octet-stream";
-        if args.json {
-            let data = serde_json::to_string(&stat_rt)?;
-            trace!("json_str => {:?}", serde_json::to_string(&data)?);
-            body_data = Some(data.into());
-            content_type = "application/json";
-        } else {
-            let buf = stat_rt.encode_to_vec();
-            body_data = Some(buf);
-            // content_type = "application/octet-stream";
-        }
-        // byte 581, json str 1281
-        // dbg!(&body_data.as_ref().unwrap().len());
+        let args = remote::args();
+        let stat_rt = sample_all(&args, stat_base);
 
         let client = http_client.clone();
-        let url = args.addr.clone();
-        let auth_pass = args.pass.clone();
-        let auth_user: String;
-        let ssr_auth: &str;
-        if args.gid.is_empty() {
-            auth_user = args.user.clone();
-            ssr_auth = "single";
-        } else {
-            auth_user = args.gid.clone();
-            ssr_auth = "group";
-        }
+        let args_1 = args.clone();
+        let buffer = buffer.clone();
 
         // http
         tokio::spawn(async move {
-            match client
-                .post(&url)
-                .basic_auth(auth_user, Some(auth_pass))
-                .timeout(Duration::from_secs(3))
-                .header(header::CONTENT_TYPE.as_str(), content_type)
-                .header("ssr-auth", ssr_auth)
-                .body(body_data.unwrap())
-                .send()
-                .await
-            {
-                Ok(resp) => {
-                    info!("report resp => {resp:?}");
+            let args = args_1;
+            match http_send(&client, &args, &stat_rt).await {
+                Ok(()) => {
+                    if let Some(buffer) = buffer {
+                        buffer
+                            .replay(|o| {
+                                let (client, args) = (client.clone(), args.clone());
+                                async move { http_send(&client, &args, &o).await }
+                            })
+                            .await;
+                    }
                 }
                 Err(err) => {
                     error!("report error => {err:?}");
+                    if let Some(buffer) = buffer {
+                        buffer.push(stat_rt);
+                    }
                 }
             }
         });
@@ -321,6 +442,17 @@ fn http_report(args: &Args, stat_base: &mut StatRequest) -> Result<()> {
     }
 }
 
+async fn refresh_remote_config(client: reqwest::Client, args: Args) {
+    let mut interval = time::interval(remote::REFRESH_INTERVAL);
+    loop {
+        interval.tick().await;
+        match remote::fetch_http(&client, &args).await {
+            Ok(cfg) => remote::apply(&cfg),
+            Err(err) => warn!("fetch remote config error => {err:?}"),
+        }
+    }
+}
+
 async fn refresh_ip_info(args: &Args) {
     // refresh/1 hour
     let mut interval = time::interval(time::Duration::from_secs(3600));
@@ -379,12 +511,19 @@ async fn main() -> Result<()> {
         o.sys_info = Some(sys_info);
     }
 
+    // group mode, register with sys id by default
+    if !args.gid.is_empty() && args.user.eq("h1") {
+        args.user = sys_id;
+    }
+    remote::init(&args);
+
     // use native
     #[cfg(all(feature = "native", not(feature = "sysinfo"), target_os = "linux"))]
     {
         eprintln!("feature native enabled");
         status::start_cpu_percent_collect_t();
-        status::start_net_speed_collect_t(&args);
+        status::start_net_speed_collect_t();
+        status::start_disk_io_collect_t();
     }
 
     // use sysinfo
@@ -392,10 +531,14 @@ async fn main() -> Result<()> {
     {
         eprintln!("feature sysinfo enabled");
         sys_info::start_cpu_percent_collect_t();
-        sys_info::start_net_speed_collect_t(&args);
+        sys_info::start_net_speed_collect_t();
+        sys_info::start_disk_io_collect_t();
     }
 
-    status::start_all_ping_collect_t(&args);
+    probe::start_all_ping_collect_t();
+    watch::start_watch_collect_t();
+    #[cfg(target_os = "linux")]
+    container::start_container_collect_t();
     let (ipv4, ipv6) = status::get_network(&args);
     eprintln!("get_network (ipv4, ipv6) => ({ipv4}, {ipv6})");
 
@@ -418,9 +561,6 @@ async fn main() -> Result<()> {
     };
     if !args.gid.is_empty() {
         stat_base.gid = args.gid.clone();
-        if stat_base.name.eq("h1") {
-            stat_base.name = sys_id;
-        }
         if args.alias.eq("unknown") {
             args.alias = stat_base.name.clone();
         } else {
@@ -0,0 +1,102 @@
+use anyhow::{anyhow, bail, Result};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use super::TIMEOUT;
+
+const TYPE_A: u16 = 1;
+const CLASS_IN: u16 = 1;
+
+fn query(id: u16, name: &str) -> Result<Vec<u8>> {
+    // id, flags (RD), qdcount 1, an/ns/ar 0
+    let mut packet = id.to_be_bytes().to_vec();
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            bail!("invalid dns name `{name}`");
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&TYPE_A.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(packet)
+}
+
+/// answer count of the response to `id`
+fn parse_response(id: u16, resp: &[u8]) -> Result<u16> {
+    if resp.len() < 12 || resp[..2] != id.to_be_bytes() || resp[2] & 0x80 == 0 {
+        bail!("invalid dns response");
+    }
+    match resp[3] & 0x0f {
+        0 => Ok(u16::from_be_bytes([resp[6], resp[7]])),
+        3 => bail!("NXDOMAIN"),
+        rcode => bail!("dns rcode {rcode}"),
+    }
+}
+
+fn server_addr(server: &str) -> Result<SocketAddr> {
+    if let Ok(ip) = server.trim_matches(['[', ']']).parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, 53));
+    }
+    let server = if server.contains(':') {
+        server.to_string()
+    } else {
+        format!("{server}:53")
+    };
+    server
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("can't get addr info of `{server}`"))
+}
+
+fn query_server(name: &str, server: &str) -> Result<()> {
+    let server = server_addr(server)?;
+    let socket = UdpSocket::bind(if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+    socket.connect(server)?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+    let id = fastrand::u16(..);
+    socket.send(&query(id, name)?)?;
+
+    let mut buf = [0u8; 1500];
+    let n = socket.recv(&mut buf)?;
+    if parse_response(id, &buf[..n])? == 0 {
+        bail!("no answer for `{name}`");
+    }
+    Ok(())
+}
+
+pub fn check(name: &str, server: Option<&str>) -> Result<Duration> {
+    let instant = Instant::now();
+    match server {
+        Some(server) => query_server(name, server)?,
+        None => {
+            if (name, 0).to_socket_addrs()?.next().is_none() {
+                bail!("no answer for `{name}`");
+            }
+        }
+    }
+    Ok(instant.elapsed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query() {
+        let packet = query(0x1234, "a.example.com.").unwrap();
+        assert_eq!(&packet[..4], &[0x12, 0x34, 0x01, 0x00]);
+        assert_eq!(&packet[12..], b"\x01a\x07example\x03com\x00\x00\x01\x00\x01");
+        assert!(query(1, "a..com").is_err());
+
+        let mut resp = packet.clone();
+        resp[2] |= 0x80;
+        resp[7] = 2;
+        assert_eq!(parse_response(0x1234, &resp).unwrap(), 2);
+        resp[3] = 3;
+        assert_eq!(parse_response(0x1234, &resp).unwrap_err().to_string(), "NXDOMAIN");
+        assert!(parse_response(0x4321, &resp).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::{anyhow, bail, Result};
+use once_cell::sync::Lazy;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::borrow::Cow;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::time::Instant;
+use url::{Position, Url};
+
+use super::{Sample, HTTP_TIMEOUT};
+
+const MAX_RESPONSE: u64 = 256 * 1024;
+
+static TLS_CONFIG: Lazy<Arc<ClientConfig>> = Lazy::new(|| {
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .expect("tls protocol versions")
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Arc::new(config)
+});
+
+trait Stream: Read + Write {}
+impl<T: Read + Write> Stream for T {}
+
+/// (status, body) of a raw http/1.1 response, chunked bodies are decoded
+pub(crate) fn parse_response(resp: &[u8]) -> Result<(u16, Cow<'_, [u8]>)> {
+    let line_end = resp
+        .windows(2)
+        .position(|w| w == b"\r\n")
+        .ok_or_else(|| anyhow!("invalid http response"))?;
+    let status = std::str::from_utf8(&resp[..line_end])?
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| anyhow!("invalid http status line"))?;
+    let Some(pos) = resp.windows(4).position(|w| w == b"\r\n\r\n") else {
+        return Ok((status, Cow::Borrowed(&[][..])));
+    };
+    let (head, body) = (&resp[..pos], &resp[pos + 4..]);
+    let chunked = String::from_utf8_lossy(head).lines().skip(1).any(|line| {
+        line.split_once(':').is_some_and(|(k, v)| {
+            k.trim().eq_ignore_ascii_case("transfer-encoding") && v.to_ascii_lowercase().contains("chunked")
+        })
+    });
+    if chunked {
+        return Ok((status, Cow::Owned(decode_chunked(body)?)));
+    }
+    Ok((status, Cow::Borrowed(body)))
+}
+
+// the response may be cut at MAX_RESPONSE, keep what was read
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>> {
+    let mut o = Vec::with_capacity(body.len());
+    while let Some(line_end) = body.windows(2).position(|w| w == b"\r\n") {
+        let line = std::str::from_utf8(&body[..line_end])?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| anyhow!("invalid chunk size `{size}`"))?;
+        if size == 0 {
+            break;
+        }
+        body = &body[line_end + 2..];
+        let n = size.min(body.len());
+        o.extend_from_slice(&body[..n]);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+    Ok(o)
+}
+
+pub fn check(addr: SocketAddr, url: &Url, status: Option<u16>, body: Option<&str>) -> Result<Sample> {
+    let host = url.host_str().unwrap_or_default();
+    let instant = Instant::now();
+    let mut tcp = TcpStream::connect_timeout(&addr, HTTP_TIMEOUT)?;
+    tcp.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    tcp.set_write_timeout(Some(HTTP_TIMEOUT))?;
+
+    let mut tls_time = None;
+    let mut stream: Box<dyn Stream> = if url.scheme() == "https" {
+        let server_name = ServerName::try_from(host.trim_matches(['[', ']']).to_string())?;
+        let mut conn = ClientConnection::new(TLS_CONFIG.clone(), server_name)?;
+        let handshake = Instant::now();
+        while conn.is_handshaking() {
+            conn.complete_io(&mut tcp)?;
+        }
+        tls_time = Some(handshake.elapsed());
+        Box::new(StreamOwned::new(conn, tcp))
+    } else {
+        Box::new(tcp)
+    };
+
+    let host_header = &url[Position::BeforeHost..Position::BeforePath];
+    let path = &url[Position::BeforePath..Position::AfterQuery];
+    // single write, the unbuffered stream would send every fragment on its own
+    let request = format!(
+        "GET {path} HTTP/1.1\r\nHost: {host_header}\r\nUser-Agent: stat_client/{}\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    stream.write_all(request.as_bytes())?;
+    stream.flush()?;
+
+    let mut resp = Vec::new();
+    match stream.as_mut().take(MAX_RESPONSE).read_to_end(&mut resp) {
+        Ok(_) => {}
+        // servers often skip close_notify
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof && !resp.is_empty() => {}
+        Err(e) => return Err(e.into()),
+    }
+    let time = instant.elapsed();
+
+    let (code, resp_body) = parse_response(&resp)?;
+    match status {
+        Some(expect) if code != expect => bail!("http status {code}, expect {expect}"),
+        None if !(200..400).contains(&code) => bail!("http status {code}"),
+        _ => {}
+    }
+    if let Some(expect) = body {
+        if !resp_body.windows(expect.len()).any(|w| w == expect.as_bytes()) {
+            bail!("http body does not contain `{expect}`");
+        }
+    }
+    Ok(Sample { time, tls_time })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_check() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut buf = [0u8; 1024];
+                let n = stream.read(&mut buf).unwrap();
+                assert!(buf[..n].starts_with(b"GET /health?a=1 HTTP/1.1\r\n"));
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nall ok");
+            }
+        });
+
+        let url = Url::parse(&format!("http://{addr}/health?a=1")).unwrap();
+        let o = check(addr, &url, Some(200), Some("ok")).unwrap();
+        assert!(o.tls_time.is_none());
+        let err = check(addr, &url, None, Some("down")).unwrap_err();
+        assert!(err.to_string().contains("down"));
+
+        assert_eq!(parse_response(b"HTTP/1.0 503 Busy\r\n\r\n").unwrap().0, 503);
+        assert!(parse_response(b"SSH-2.0-OpenSSH\r\n").is_err());
+
+        // the expectation may span chunks
+        let resp = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4;x=1\r\nall \r\n2\r\nok\r\n0\r\n\r\n";
+        assert_eq!(&*parse_response(resp).unwrap().1, b"all ok");
+        // truncated at MAX_RESPONSE
+        let resp = b"HTTP/1.1 200 OK\r\ntransfer-encoding: gzip, chunked\r\n\r\n10\r\nall";
+        assert_eq!(&*parse_response(resp).unwrap().1, b"all");
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n").is_err());
+    }
+}
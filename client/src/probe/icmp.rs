@@ -0,0 +1,102 @@
+use anyhow::{bail, Result};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io::{ErrorKind, Read};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, Instant};
+
+use super::TIMEOUT;
+
+const ECHO_REQUEST_V4: u8 = 8;
+const ECHO_REPLY_V4: u8 = 0;
+const ECHO_REQUEST_V6: u8 = 128;
+const ECHO_REPLY_V6: u8 = 129;
+const PAYLOAD: &[u8] = b"stat_client ping";
+
+static SEQ: AtomicU16 = AtomicU16::new(0);
+
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum = data
+        .chunks(2)
+        .map(|c| u32::from(u16::from_be_bytes([c[0], c.get(1).copied().unwrap_or(0)])))
+        .sum::<u32>();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn echo_request(ty: u8, id: u16, seq: u16) -> Vec<u8> {
+    let mut packet = vec![ty, 0, 0, 0];
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(PAYLOAD);
+    // icmpv6 checksum covers a pseudo header, the kernel fills it in
+    if ty == ECHO_REQUEST_V4 {
+        let sum = checksum(&packet);
+        packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    }
+    packet
+}
+
+// unprivileged `ping` socket (linux net.ipv4.ping_group_range, macos), raw socket needs root
+fn open(domain: Domain, protocol: Protocol) -> Result<Socket> {
+    match Socket::new(domain, Type::DGRAM, Some(protocol)) {
+        Ok(s) => Ok(s),
+        Err(_) => Ok(Socket::new(domain, Type::RAW, Some(protocol))?),
+    }
+}
+
+pub fn check(ip: IpAddr) -> Result<Duration> {
+    let (domain, protocol, request, reply) = match ip {
+        IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4, ECHO_REQUEST_V4, ECHO_REPLY_V4),
+        IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6, ECHO_REQUEST_V6, ECHO_REPLY_V6),
+    };
+    let socket = open(domain, protocol)?;
+    socket.connect(&SocketAddr::new(ip, 0).into())?;
+
+    // the kernel rewrites the id of datagram sockets, match on seq & payload
+    let seq = SEQ.fetch_add(1, Ordering::Relaxed);
+    let packet = echo_request(request, fastrand::u16(..), seq);
+    let instant = Instant::now();
+    socket.send(&packet)?;
+
+    let mut buf = [0u8; 1500];
+    loop {
+        let Some(left) = TIMEOUT.checked_sub(instant.elapsed()).filter(|d| !d.is_zero()) else {
+            bail!("icmp timeout");
+        };
+        socket.set_read_timeout(Some(left))?;
+        let n = match (&socket).read(&mut buf) {
+            Ok(n) => n,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => bail!("icmp timeout"),
+            Err(e) => return Err(e.into()),
+        };
+        let mut data = &buf[..n];
+        // raw ipv4 sockets (and macos) include the ip header
+        if ip.is_ipv4() && data.first().is_some_and(|b| b >> 4 == 4) {
+            data = data.get(usize::from(data[0] & 0x0f) * 4..).unwrap_or_default();
+        }
+        if data.len() >= 8 + PAYLOAD.len()
+            && data[0] == reply
+            && data[6..8] == seq.to_be_bytes()
+            && &data[8..8 + PAYLOAD.len()] == PAYLOAD
+        {
+            return Ok(instant.elapsed());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_echo_request() {
+        let packet = echo_request(ECHO_REQUEST_V4, 1, 2);
+        assert_eq!((packet[0], &packet[4..8]), (8, &[0, 1, 0, 2][..]));
+        // a valid checksum sums to zero
+        assert_eq!(checksum(&packet), 0);
+        assert_eq!(&echo_request(ECHO_REQUEST_V6, 1, 2)[..4], &[128, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,488 @@
+#![deny(warnings)]
+// latency & loss probes, `--probe name=target`, the carrier probes (cu/ct/cm) by default
+// the list is re-read every round, it can be changed by remote config
+// target:
+//   host:port                   tcp connect, a refused connection still counts as reachable
+//   tcp://host:port             tcp connect, refused is lost
+//   icmp://host                 icmp echo, unprivileged datagram socket, raw socket as fallback
+//   http(s)://host/path#status=200&body=ok
+//                               GET, expect the status (default 2xx/3xx) and a body substring
+//   dns://name, dns://server[:port]/name
+//                               resolve with the system resolver, or query `server` for A records
+use anyhow::{anyhow, bail, Result};
+use once_cell::sync::Lazy;
+use std::collections::VecDeque;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use url::Url;
+
+use stat_common::server_status::{ProbeResult, StatRequest};
+
+use crate::Args;
+
+mod dns;
+pub(crate) mod http;
+mod icmp;
+mod tcp;
+
+const SAMPLE_PERIOD: u64 = 1000; //ms
+const TIMEOUT: Duration = Duration::from_millis(1000);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+const WINDOW: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    Tcp {
+        addr: String,
+        refused_ok: bool,
+    },
+    Icmp {
+        host: String,
+    },
+    Http {
+        url: Url,
+        status: Option<u16>,
+        body: Option<String>,
+    },
+    Dns {
+        name: String,
+        server: Option<String>,
+    },
+}
+
+impl Kind {
+    pub fn parse(s: &str) -> Result<Self> {
+        let Some((scheme, rest)) = s.split_once("://") else {
+            return Ok(Self::Tcp {
+                addr: s.to_string(),
+                refused_ok: true,
+            });
+        };
+        match scheme {
+            "tcp" => Ok(Self::Tcp {
+                addr: rest.to_string(),
+                refused_ok: false,
+            }),
+            "icmp" => Ok(Self::Icmp {
+                host: rest.trim_matches(['[', ']']).to_string(),
+            }),
+            "http" | "https" => {
+                let mut url = Url::parse(s)?;
+                if url.host_str().is_none() {
+                    bail!("missing host");
+                }
+                let (mut status, mut body) = (None, None);
+                for (k, v) in url::form_urlencoded::parse(url.fragment().unwrap_or_default().as_bytes()) {
+                    match k.as_ref() {
+                        "status" => status = Some(v.parse()?),
+                        "body" if v.is_empty() => bail!("empty http body expectation"),
+                        "body" => body = Some(v.to_string()),
+                        _ => bail!("unknown http expectation `{k}`"),
+                    }
+                }
+                url.set_fragment(None);
+                Ok(Self::Http { url, status, body })
+            }
+            "dns" => {
+                let (server, name) = match rest.split_once('/') {
+                    Some((server, name)) => (Some(server.to_string()), name),
+                    None => (None, rest),
+                };
+                if name.is_empty() {
+                    bail!("missing name");
+                }
+                Ok(Self::Dns {
+                    name: name.to_string(),
+                    server,
+                })
+            }
+            _ => bail!("unknown probe kind `{scheme}`"),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Tcp { .. } => "tcp",
+            Self::Icmp { .. } => "icmp",
+            Self::Http { .. } => "http",
+            Self::Dns { .. } => "dns",
+        }
+    }
+
+    /// `addr` caches the resolved address between rounds
+    fn check(&self, addr: &mut Option<SocketAddr>) -> Result<Sample> {
+        let res = match self {
+            Self::Tcp {
+                addr: target,
+                refused_ok,
+            } => tcp::check(resolve(addr, target.as_str())?, *refused_ok).map(Sample::from),
+            Self::Icmp { host } => icmp::check(resolve(addr, (host.as_str(), 0))?.ip()).map(Sample::from),
+            Self::Http { url, status, body } => {
+                let host = url.host_str().unwrap_or_default();
+                let port = url.port_or_known_default().unwrap_or_default();
+                http::check(resolve(addr, (host, port))?, url, *status, body.as_deref())
+            }
+            Self::Dns { name, server } => dns::check(name, server.as_deref()).map(Sample::from),
+        };
+        if res.is_err() {
+            // re-resolve next round, the address may have changed
+            *addr = None;
+        }
+        res
+    }
+}
+
+fn resolve<A: ToSocketAddrs>(cache: &mut Option<SocketAddr>, target: A) -> Result<SocketAddr> {
+    if let Some(addr) = cache {
+        return Ok(*addr);
+    }
+    let addr = target
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("can't get addr info"))?;
+    *cache = Some(addr);
+    Ok(addr)
+}
+
+#[derive(Debug)]
+pub struct Sample {
+    pub time: Duration,
+    // tls handshake, https only
+    pub tls_time: Option<Duration>,
+}
+
+impl From<Duration> for Sample {
+    fn from(time: Duration) -> Self {
+        Self { time, tls_time: None }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub name: String,
+    pub addr: String,
+    pub kind: Kind,
+}
+
+impl Target {
+    /// `name=target`, or `target` named after itself
+    pub fn parse(s: &str) -> Result<Self> {
+        let (name, addr) = match s.split_once('=') {
+            // `=` in the http fragment
+            Some((name, addr)) if !name.contains("://") => (name.trim(), addr.trim()),
+            _ => (s.trim(), s.trim()),
+        };
+        if name.is_empty() || addr.is_empty() {
+            bail!("expect name=target");
+        }
+        Ok(Self {
+            name: name.to_string(),
+            addr: addr.to_string(),
+            kind: Kind::parse(addr)?,
+        })
+    }
+}
+
+pub fn targets(args: &Args) -> Vec<Target> {
+    if args.disable_ping {
+        return Vec::new();
+    }
+    if args.probe.is_empty() {
+        return [
+            ("10010", &args.cu_addr),
+            ("189", &args.ct_addr),
+            ("10086", &args.cm_addr),
+        ]
+        .into_iter()
+        .map(|(name, addr)| Target {
+            name: name.to_string(),
+            addr: addr.clone(),
+            kind: Kind::Tcp {
+                addr: addr.clone(),
+                refused_ok: true,
+            },
+        })
+        .collect();
+    }
+    args.probe
+        .iter()
+        .filter_map(|s| {
+            Target::parse(s)
+                .map_err(|err| error!("invalid probe `{s}` => {err}"))
+                .ok()
+        })
+        .collect()
+}
+
+// last `WINDOW` results, None for lost
+#[derive(Debug, Default)]
+struct Window(VecDeque<Option<f64>>);
+
+impl Window {
+    fn push(&mut self, v: Option<f64>) {
+        if self.0.len() >= WINDOW {
+            self.0.pop_front();
+        }
+        self.0.push_back(v);
+    }
+
+    fn lost_rate(&self) -> u32 {
+        // not enough samples yet
+        if self.0.len() <= 30 {
+            return 0;
+        }
+        let lost = self.0.iter().filter(|o| o.is_none()).count();
+        u32::try_from(lost * 100 / self.0.len()).unwrap_or(100)
+    }
+
+    /// nearest rank over the successful samples
+    fn percentiles(&self, ps: &[f64]) -> Vec<f64> {
+        let mut v: Vec<f64> = self.0.iter().flatten().copied().collect();
+        if v.is_empty() {
+            return vec![0.0; ps.len()];
+        }
+        v.sort_by(f64::total_cmp);
+        ps.iter()
+            .map(|p| {
+                let rank = (p * v.len() as f64).ceil() as usize;
+                v[rank.clamp(1, v.len()) - 1]
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct PingData {
+    pub target: Target,
+    pub lost_rate: u32,
+    pub ping_time: u32,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub tls_time: f64,
+    pub error: String,
+}
+
+impl PingData {
+    fn new(target: Target) -> Self {
+        Self {
+            target,
+            lost_rate: 0,
+            ping_time: 0,
+            p50: 0.0,
+            p90: 0.0,
+            p99: 0.0,
+            tls_time: 0.0,
+            error: String::new(),
+        }
+    }
+}
+
+// in `targets` order, a probe thread exits once its data is dropped from here
+static G_PROBES: Lazy<Mutex<Vec<Arc<Mutex<PingData>>>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+fn alive(data: &Arc<Mutex<PingData>>) -> bool {
+    Arc::strong_count(data) > 1
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+fn start_ping_collect_t(data: Arc<Mutex<PingData>>) {
+    let target = data.lock().unwrap().target.clone();
+
+    thread::spawn(move || {
+        let mut window = Window::default();
+        let mut addr = None;
+        while alive(&data) {
+            let instant = Instant::now();
+            let res = target.kind.check(&mut addr);
+            let elapsed = instant.elapsed();
+
+            window.push(res.as_ref().ok().map(|o| ms(o.time)));
+            let [p50, p90, p99] = window.percentiles(&[0.5, 0.9, 0.99])[..] else {
+                unreachable!()
+            };
+            if let Ok(mut o) = data.lock() {
+                o.lost_rate = window.lost_rate();
+                o.p50 = p50;
+                o.p90 = p90;
+                o.p99 = p99;
+                match &res {
+                    Ok(sample) => {
+                        o.ping_time = u32::try_from(sample.time.as_millis()).unwrap_or(u32::MAX);
+                        o.tls_time = sample.tls_time.map_or(0.0, ms);
+                        o.error.clear();
+                    }
+                    Err(err) => {
+                        o.ping_time = u32::try_from(elapsed.as_millis()).unwrap_or(u32::MAX);
+                        o.error = err.to_string();
+                    }
+                }
+            }
+            if let Err(err) = res {
+                debug!("probe `{}` => {err}", target.addr);
+            }
+
+            thread::sleep(Duration::from_millis(SAMPLE_PERIOD));
+        }
+        info!("probe `{}` stopped", target.addr);
+    });
+}
+
+/// keep one thread per target, following changes of the target list
+pub fn start_all_ping_collect_t() {
+    thread::spawn(|| loop {
+        let want = targets(&crate::remote::args());
+        {
+            let mut probes = G_PROBES.lock().unwrap();
+            if !probes
+                .iter()
+                .map(|o| o.lock().unwrap().target.clone())
+                .eq(want.iter().cloned())
+            {
+                let mut old = std::mem::take(&mut *probes);
+                for target in want {
+                    if let Some(idx) = old.iter().position(|o| o.lock().unwrap().target == target) {
+                        probes.push(old.swap_remove(idx));
+                        continue;
+                    }
+                    info!("probe `{}` => {:?}", target.name, target.kind);
+                    let data = Arc::new(Mutex::new(PingData::new(target)));
+                    start_ping_collect_t(data.clone());
+                    probes.push(data);
+                }
+            }
+        }
+        thread::sleep(Duration::from_millis(SAMPLE_PERIOD));
+    });
+}
+
+pub fn sample(stat: &mut StatRequest) {
+    let probes = G_PROBES.lock().unwrap();
+    stat.probes = probes
+        .iter()
+        .map(|o| {
+            let o = o.lock().unwrap();
+            ProbeResult {
+                name: o.target.name.clone(),
+                target: o.target.addr.clone(),
+                lost_rate: o.lost_rate.into(),
+                time: o.ping_time.into(),
+                kind: o.target.kind.name().to_string(),
+                p50: o.p50,
+                p90: o.p90,
+                p99: o.p99,
+                tls_time: o.tls_time,
+                error: o.error.clone(),
+            }
+        })
+        .collect();
+
+    // legacy fields, still shown by the default theme
+    for o in &stat.probes {
+        let (ping, time) = match o.name.as_str() {
+            "10010" => (&mut stat.ping_10010, &mut stat.time_10010),
+            "189" => (&mut stat.ping_189, &mut stat.time_189),
+            "10086" => (&mut stat.ping_10086, &mut stat.time_10086),
+            _ => continue,
+        };
+        *ping = o.lost_rate;
+        *time = o.time;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_targets() {
+        let o = Target::parse("fra = fra.example.com:80").unwrap();
+        assert_eq!((o.name.as_str(), o.addr.as_str()), ("fra", "fra.example.com:80"));
+        assert_eq!(
+            o.kind,
+            Kind::Tcp {
+                addr: "fra.example.com:80".to_string(),
+                refused_ok: true
+            }
+        );
+        assert_eq!(Target::parse("1.1.1.1:53").unwrap().name, "1.1.1.1:53");
+        assert!(Target::parse("fra=").is_err());
+        assert!(Target::parse("fra=udp://1.1.1.1:53").is_err());
+
+        let o = Target::parse("web=https://example.com/health?a=1#status=204&body=ok").unwrap();
+        let Kind::Http { url, status, body } = o.kind else {
+            panic!("{o:?}")
+        };
+        assert_eq!(url.as_str(), "https://example.com/health?a=1");
+        assert_eq!((status, body.as_deref()), (Some(204), Some("ok")));
+        assert!(Target::parse("web=https://example.com/#body=").is_err());
+        // unnamed, `=` only in the fragment
+        assert_eq!(
+            Target::parse("http://example.com/#status=200").unwrap().kind.name(),
+            "http"
+        );
+
+        assert_eq!(
+            Kind::parse("dns://1.1.1.1/example.com").unwrap(),
+            Kind::Dns {
+                name: "example.com".to_string(),
+                server: Some("1.1.1.1".to_string())
+            }
+        );
+        assert_eq!(
+            Kind::parse("icmp://[::1]").unwrap(),
+            Kind::Icmp {
+                host: "::1".to_string()
+            }
+        );
+
+        let args = Args::parse_from(["stat_client"]);
+        let names: Vec<String> = targets(&args).into_iter().map(|o| o.name).collect();
+        assert_eq!(names, vec!["10010", "189", "10086"]);
+
+        let args = Args::parse_from([
+            "stat_client",
+            "--probe",
+            "fra=fra.example.com:80,=x",
+            "--probe",
+            "ams:443",
+        ]);
+        assert_eq!(targets(&args).len(), 2);
+
+        let args = Args::parse_from(["stat_client", "--probe", "ams:443", "--disable-ping"]);
+        assert!(targets(&args).is_empty());
+    }
+
+    #[test]
+    fn test_window() {
+        let mut w = Window::default();
+        assert_eq!(w.percentiles(&[0.5]), vec![0.0]);
+        for i in 1..=150 {
+            w.push(if i % 10 == 0 { None } else { Some(f64::from(i % 10)) });
+        }
+        assert_eq!(w.0.len(), WINDOW);
+        assert_eq!(w.lost_rate(), 10);
+        assert_eq!(w.percentiles(&[0.5, 0.9, 0.99]), vec![5.0, 9.0, 9.0]);
+    }
+
+    #[test]
+    fn test_check_tcp() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let kind = Kind::parse(&format!("tcp://{addr}")).unwrap();
+        let mut cache = None;
+        assert!(kind.check(&mut cache).is_ok());
+        assert!(cache.is_some());
+
+        drop(listener);
+        assert!(kind.check(&mut cache).is_err());
+        assert!(cache.is_none());
+        assert!(Kind::parse(&addr).unwrap().check(&mut cache).is_ok());
+    }
+}
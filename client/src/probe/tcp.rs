@@ -0,0 +1,19 @@
+use anyhow::Result;
+use std::io::ErrorKind::ConnectionRefused;
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+use super::TIMEOUT;
+
+pub fn check(addr: SocketAddr, refused_ok: bool) -> Result<Duration> {
+    let instant = Instant::now();
+    match TcpStream::connect_timeout(&addr, TIMEOUT) {
+        Ok(s) => {
+            let _ = s.shutdown(Shutdown::Both);
+        }
+        // the host answered, good enough for a network probe
+        Err(e) if refused_ok && e.kind() == ConnectionRefused => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(instant.elapsed())
+}
@@ -0,0 +1,176 @@
+#![deny(warnings)]
+// server pushed config, applied on top of the cli args without restart
+use once_cell::sync::OnceCell;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use stat_common::server_status::ClientConfig;
+
+use crate::Args;
+
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+static BASE: OnceCell<Args> = OnceCell::new();
+static CURRENT: RwLock<Option<(String, Arc<Args>)>> = RwLock::new(None);
+
+pub fn init(args: &Args) {
+    let _ = BASE.set(args.clone());
+    *CURRENT.write().unwrap() = Some((String::new(), Arc::new(args.clone())));
+}
+
+/// effective args, read them on every use to pick up changes
+pub fn args() -> Arc<Args> {
+    CURRENT.read().unwrap().as_ref().map(|(_, o)| o.clone()).unwrap()
+}
+
+pub fn version() -> String {
+    CURRENT
+        .read()
+        .unwrap()
+        .as_ref()
+        .map(|(v, _)| v.clone())
+        .unwrap_or_default()
+}
+
+fn merge(base: &Args, cfg: &ClientConfig) -> Args {
+    let mut o = base.clone();
+    if let Some(v) = cfg.interval.filter(|v| *v > 0) {
+        o.report_interval = v;
+    }
+    if let Some(v) = cfg.disable_ping {
+        o.disable_ping = v;
+    }
+    if let Some(v) = cfg.disable_tupd {
+        o.disable_tupd = v;
+    }
+    if let Some(v) = cfg.vnstat {
+        o.vnstat = v;
+    }
+    if let Some(v) = cfg.vnstat_mr.filter(|v| (1..=28).contains(v)) {
+        o.vnstat_mr = v;
+    }
+    if let Some(v) = &cfg.iface {
+        o.iface = v.items.iter().filter(|e| !e.trim().is_empty()).cloned().collect();
+    }
+    if let Some(v) = &cfg.exclude_iface {
+        o.exclude_iface = v.items.iter().filter(|e| !e.trim().is_empty()).cloned().collect();
+    }
+    if let Some(v) = &cfg.mount {
+        o.mount = v.items.iter().filter(|e| !e.trim().is_empty()).cloned().collect();
+    }
+    if let Some(v) = &cfg.exclude_mount {
+        o.exclude_mount = v.items.iter().filter(|e| !e.trim().is_empty()).cloned().collect();
+    }
+    if let Some(v) = &cfg.fs {
+        o.fs = v.items.iter().filter(|e| !e.trim().is_empty()).cloned().collect();
+    }
+    if let Some(v) = &cfg.exclude_fs {
+        o.exclude_fs = v.items.iter().filter(|e| !e.trim().is_empty()).cloned().collect();
+    }
+    if let Some(v) = &cfg.watch {
+        o.watch = v.items.iter().filter(|e| !e.trim().is_empty()).cloned().collect();
+    }
+    if let Some(v) = cfg.containers {
+        o.containers = v;
+    }
+    if let Some(v) = &cfg.probes {
+        o.probe = v.items.iter().filter(|e| !e.trim().is_empty()).cloned().collect();
+    }
+    if let Some(v) = &cfg.cu_addr {
+        o.cu_addr.clone_from(v);
+    }
+    if let Some(v) = &cfg.ct_addr {
+        o.ct_addr.clone_from(v);
+    }
+    if let Some(v) = &cfg.cm_addr {
+        o.cm_addr.clone_from(v);
+    }
+    o
+}
+
+pub fn apply(cfg: &ClientConfig) {
+    if cfg.version == version() {
+        return;
+    }
+    let Some(base) = BASE.get() else {
+        return;
+    };
+    info!("apply remote config {cfg:?}");
+    *CURRENT.write().unwrap() = Some((cfg.version.clone(), Arc::new(merge(base, cfg))));
+}
+
+/// GET /client/config next to the report url
+pub async fn fetch_http(client: &reqwest::Client, args: &Args) -> anyhow::Result<ClientConfig> {
+    let mut url = url::Url::parse(&args.addr)?.join("client/config")?;
+    url.query_pairs_mut().append_pair("name", &args.user);
+    let (auth_user, ssr_auth) = if args.gid.is_empty() {
+        (&args.user, "single")
+    } else {
+        (&args.gid, "group")
+    };
+    let cfg = client
+        .get(url)
+        .basic_auth(auth_user, Some(&args.pass))
+        .timeout(Duration::from_secs(5))
+        .header("ssr-auth", ssr_auth)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<ClientConfig>()
+        .await?;
+    Ok(cfg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use stat_common::server_status::StringList;
+
+    #[test]
+    fn test_merge() {
+        let base = Args::parse_from(["stat_client", "--interval", "2", "-e", "lo,docker"]);
+        let cfg = ClientConfig {
+            interval: Some(5),
+            disable_tupd: Some(true),
+            iface: Some(StringList {
+                items: vec!["eth0".to_string(), String::new()],
+            }),
+            ..Default::default()
+        };
+        let o = merge(&base, &cfg);
+        assert_eq!(o.report_interval, 5);
+        assert!(o.disable_tupd && !o.disable_ping);
+        assert_eq!(o.iface, vec!["eth0"]);
+        assert_eq!(o.exclude_iface, vec!["lo", "docker"]);
+
+        let o = merge(&base, &ClientConfig::default());
+        assert_eq!(o.report_interval, 2);
+        assert!(!o.disable_tupd);
+    }
+
+    #[test]
+    fn test_skip_mount() {
+        let base = Args::parse_from(["stat_client", "--exclude-mount", "/boot/"]);
+        assert!(!base.skip_mount("/", "ext4"));
+        assert!(base.skip_mount("/boot", "ext4") && base.skip_mount("/boot/efi", "vfat"));
+        assert!(!base.skip_mount("/bootstrap", "xfs"));
+        assert!(base.skip_mount("/run", "tmpfs"));
+
+        let cfg = ClientConfig {
+            mount: Some(StringList {
+                items: vec!["/".to_string(), "/run".to_string()],
+            }),
+            fs: Some(StringList {
+                items: vec!["ext4".to_string(), "tmpfs".to_string()],
+            }),
+            exclude_fs: Some(StringList {
+                items: vec!["EXT4".to_string()],
+            }),
+            ..Default::default()
+        };
+        let o = merge(&base, &cfg);
+        assert!(o.skip_mount("/", "ext4") && o.skip_mount("/data", "xfs"));
+        assert!(!o.skip_mount("/run", "tmpfs"));
+    }
+}
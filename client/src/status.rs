@@ -1,26 +1,27 @@
 #![allow(unused)]
 #![allow(clippy::cast_precision_loss, clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::similar_names, clippy::many_single_char_names)]
-use once_cell::sync::OnceCell;
 use regex::Regex;
-use std::collections::HashMap;
-use std::collections::LinkedList;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
-use std::io::ErrorKind::ConnectionRefused;
 use std::net::TcpStream;
 use std::net::{Shutdown, ToSocketAddrs};
-use std::process::Command;
+use std::path::Path;
 use std::str;
 use std::sync::Arc;
 use std::sync::{LazyLock, Mutex};
 use std::thread;
-use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use crate::probe;
 use crate::vnstat;
+use crate::watch;
+#[cfg(target_os = "linux")]
+use crate::container;
 use crate::Args;
-use stat_common::server_status::{DiskInfo, StatRequest};
+use stat_common::server_status::{CpuTimes, DiskInfo, DiskIo, NetIfInfo, Psi, SensorInfo, StatRequest, TcpStates};
 
 const SAMPLE_PERIOD: u64 = 1000; //ms
 const TIMEOUT_MS: u64 = 1000;
@@ -77,24 +78,81 @@ pub fn get_memory() -> (u64, u64, u64, u64) {
     (mem_total, mem_used, swap_total, swap_free)
 }
 
-macro_rules! exec_shell_cmd_fetch_u32 {
-    ($shell_cmd:expr) => {{
-        let a = &Command::new("/bin/sh")
-            .args(&["-c", $shell_cmd])
-            .output()
-            .expect("failed to execute process")
-            .stdout;
-        str::from_utf8(a).unwrap().trim().parse::<u32>().unwrap()
-    }};
+/// counts socket lines of /proc/net/{tcp,udp}{,6} by the hex `st` column
+pub fn count_sockets(content: &str, states: &mut [u32; 16]) {
+    for line in content.lines().skip(1) {
+        if let Some(st) = line
+            .split_whitespace()
+            .nth(3)
+            .and_then(|s| u8::from_str_radix(s, 16).ok())
+        {
+            states[usize::from(st & 0x0f)] += 1;
+        }
+    }
+}
+
+fn count_sockets_of(files: [&str; 2]) -> [u32; 16] {
+    let mut states = [0; 16];
+    for file in files {
+        if let Ok(content) = fs::read_to_string(file) {
+            count_sockets(&content, &mut states);
+        }
+    }
+    states
 }
 
-pub fn tupd() -> (u32, u32, u32, u32) {
-    let t = exec_shell_cmd_fetch_u32!("ss -t | wc -l") - 1;
-    let u = exec_shell_cmd_fetch_u32!("ss -u | wc -l") - 1;
-    let p = exec_shell_cmd_fetch_u32!("ps -ef | wc -l") - 2;
-    let d = exec_shell_cmd_fetch_u32!("ps -eLf | wc -l") - 2;
+pub fn tcp_states(st: &[u32; 16]) -> TcpStates {
+    TcpStates {
+        established: st[0x01],
+        syn_sent: st[0x02],
+        syn_recv: st[0x03] + st[0x0c],
+        fin_wait1: st[0x04],
+        fin_wait2: st[0x05],
+        time_wait: st[0x06],
+        close: st[0x07],
+        close_wait: st[0x08],
+        last_ack: st[0x09],
+        listen: st[0x0a],
+        closing: st[0x0b],
+    }
+}
+
+/// (processes, threads) from /proc/<pid>/task
+pub fn count_tasks() -> (u32, u32) {
+    let (mut p, mut d) = (0, 0);
+    let Ok(dir) = fs::read_dir("/proc") else {
+        return (p, d);
+    };
+    for entry in dir.flatten() {
+        if !entry.file_name().to_string_lossy().bytes().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        // exited in the meantime
+        let Ok(tasks) = fs::read_dir(entry.path().join("task")) else {
+            continue;
+        };
+        p += 1;
+        d += tasks.count() as u32;
+    }
+    (p, d)
+}
 
-    (t, u, p, d)
+/// tcp / udp like `ss -t` / `ss -u` (connected sockets), processes & threads, tcp states
+pub fn tupd(stat: &mut StatRequest) {
+    let tcp = count_sockets_of(["/proc/net/tcp", "/proc/net/tcp6"]);
+    let udp = count_sockets_of(["/proc/net/udp", "/proc/net/udp6"]);
+    let states = tcp_states(&tcp);
+    stat.tcp = states.established
+        + states.syn_sent
+        + states.fin_wait1
+        + states.fin_wait2
+        + states.close_wait
+        + states.last_ack
+        + states.closing;
+    // 07 unconnected
+    stat.udp = udp.iter().sum::<u32>() - udp[0x07];
+    (stat.process, stat.thread) = count_tasks();
+    stat.tcp_states = Some(states);
 }
 
 static TRAFFIC_REGEX: &str =
@@ -128,43 +186,127 @@ pub fn get_sys_traffic(args: &Args) -> (u64, u64) {
     (network_in, network_out)
 }
 
-static DF_CMD:&str = "df -Tlm --total -t ext4 -t ext3 -t ext2 -t reiserfs -t jfs -t ntfs -t fat32 -t btrfs -t fuseblk -t zfs -t simfs -t xfs";
-pub fn get_hdd(stat: &mut StatRequest) {
-    let (mut hdd_total, mut hdd_used) = (0, 0);
-    let a = &Command::new("/bin/sh")
-        .args(["-c", DF_CMD])
-        .output()
-        .expect("failed to execute df")
-        .stdout;
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FsUsage {
+    pub total: u64,
+    pub used: u64,
+    // available to unprivileged users
+    pub free: u64,
+    pub inodes_total: u64,
+    pub inodes_free: u64,
+}
 
-    let _ = str::from_utf8(a).map(|content| {
-        let vs = content.lines().collect::<Vec<&str>>();
+/// bytes & inodes like df, btrfs & co report 0 inodes
+#[cfg(unix)]
+pub fn get_fs_usage(path: &str) -> Option<FsUsage> {
+    rustix::fs::statvfs(path).ok().map(|o| FsUsage {
+        total: o.f_blocks * o.f_frsize,
+        used: o.f_blocks.saturating_sub(o.f_bfree) * o.f_frsize,
+        free: o.f_bavail * o.f_frsize,
+        inodes_total: o.f_files,
+        inodes_free: o.f_ffree,
+    })
+}
+#[cfg(not(unix))]
+pub fn get_fs_usage(_path: &str) -> Option<FsUsage> {
+    None
+}
 
-        for (idx, s) in vs.iter().enumerate() {
-            // header
-            if idx == 0 {
-                continue;
-            }
+/// (total, free) inodes of the file system mounted at `path`
+pub fn get_inodes(path: &str) -> Option<(u64, u64)> {
+    get_fs_usage(path).map(|o| (o.inodes_total, o.inodes_free))
+}
 
-            let vec = (*s).split_whitespace().collect::<Vec<&str>>();
-            if idx == vs.len() - 1 {
-                // total
-                stat.hdd_total = vec[2].parse::<u64>().unwrap();
-                stat.hdd_used = vec[3].parse::<u64>().unwrap();
-            } else {
-                let di = DiskInfo {
-                    name: vec[0].to_string(),
-                    mount_point: vec[6].to_string(),
-                    file_system: vec[1].to_string(),
-                    // TODO: fix this
-                    total: vec[2].parse::<u64>().unwrap() * 1024 * 1024,
-                    used: vec[3].parse::<u64>().unwrap() * 1024 * 1024,
-                    free: vec[4].parse::<u64>().unwrap() * 1024 * 1024,
-                };
-                stat.disks.push(di);
-            }
+#[derive(Debug, PartialEq, Eq)]
+pub struct Mount {
+    // major:minor, shared by bind mounts
+    pub dev: String,
+    pub source: String,
+    pub mount_point: String,
+    pub fs: String,
+}
+
+// `\040` => ` `
+fn unescape_octal(s: &str) -> String {
+    let b = s.as_bytes();
+    let mut out = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        let oct = s
+            .get(i + 1..i + 4)
+            .filter(|o| b[i] == b'\\' && o.bytes().all(|c| (b'0'..=b'7').contains(&c)))
+            .and_then(|o| u8::from_str_radix(o, 8).ok());
+        if let Some(c) = oct {
+            out.push(c);
+            i += 4;
+        } else {
+            out.push(b[i]);
+            i += 1;
         }
-    });
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// 36 35 98:0 /mnt1 /mnt/parent rw,noatime master:1 - ext3 /dev/root rw,errors=continue
+pub fn parse_mountinfo(content: &str) -> Vec<Mount> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            // optional fields end with `-`
+            let sep = fields.iter().skip(6).position(|s| *s == "-")? + 6;
+            Some(Mount {
+                dev: (*fields.get(2)?).to_string(),
+                mount_point: unescape_octal(fields.get(4)?),
+                fs: (*fields.get(sep + 1)?).to_string(),
+                source: unescape_octal(fields.get(sep + 2)?),
+            })
+        })
+        .collect()
+}
+
+// network file systems, `df -l` skips them, statvfs may hang on a dead server
+fn is_remote(source: &str, fs: &str) -> bool {
+    source.contains(':')
+        || source.starts_with("//")
+        || matches!(
+            fs,
+            "nfs" | "nfs4" | "cifs" | "smb3" | "smbfs" | "afs" | "coda" | "ceph" | "glusterfs" | "9p" | "fuse.sshfs"
+        )
+}
+
+// all local file systems, filtered by `Args::skip_mount`
+pub fn get_hdd(args: &Args, stat: &mut StatRequest) {
+    let mounts = fs::read_to_string("/proc/self/mountinfo")
+        .map(|s| parse_mountinfo(&s))
+        .unwrap_or_default();
+
+    let mut seen = HashSet::new();
+    for o in mounts {
+        if is_remote(&o.source, &o.fs) || args.skip_mount(&o.mount_point, &o.fs) {
+            continue;
+        }
+        // bind mounts & btrfs subvolumes count once, like df
+        if !seen.insert(o.dev) {
+            continue;
+        }
+        let Some(usage) = get_fs_usage(&o.mount_point).filter(|u| u.total > 0) else {
+            continue;
+        };
+        stat.hdd_total += usage.total / 1024 / 1024;
+        stat.hdd_used += usage.used / 1024 / 1024;
+        stat.disks.push(DiskInfo {
+            name: o.source,
+            mount_point: o.mount_point,
+            file_system: o.fs,
+            total: usage.total,
+            used: usage.used,
+            free: usage.free,
+            inodes_total: usage.inodes_total,
+            inodes_used: usage.inodes_total.saturating_sub(usage.inodes_free),
+            inodes_free: usage.inodes_free,
+        });
+    }
 }
 
 #[derive(Debug, Default)]
@@ -175,43 +317,68 @@ pub struct NetSpeed {
     pub nettx: u64,
     pub avgrx: u64,
     pub avgtx: u64,
+    pub ifaces: Vec<NetIfInfo>,
+}
+
+// face |bytes packets errs drop fifo frame compressed multicast|bytes packets errs drop fifo colls carrier compressed
+pub fn parse_net_dev(content: &str) -> Vec<NetIfInfo> {
+    content
+        .lines()
+        .filter_map(|l| {
+            let (name, data) = l.split_once(':')?;
+            let v = data
+                .split_whitespace()
+                .map(|s| s.parse::<u64>().unwrap_or(0))
+                .collect::<Vec<_>>();
+            if v.len() < 16 {
+                return None;
+            }
+            Some(NetIfInfo {
+                name: name.trim().to_string(),
+                rx_bytes: v[0],
+                rx_packets: v[1],
+                rx_errors: v[2],
+                rx_drop: v[3],
+                tx_bytes: v[8],
+                tx_packets: v[9],
+                tx_errors: v[10],
+                tx_drop: v[11],
+                ..Default::default()
+            })
+        })
+        .collect()
 }
 
 pub static G_NET_SPEED: LazyLock<Arc<Mutex<NetSpeed>>> = LazyLock::new(|| Arc::new(Mutex::default()));
 
 #[allow(unused)]
-pub fn start_net_speed_collect_t(args: &Args) {
-    let args_1 = args.clone();
+pub fn start_net_speed_collect_t() {
     thread::spawn(move || loop {
-        let _ = File::open("/proc/net/dev").map(|file| {
-            let buf_reader = BufReader::new(file);
-            let (mut avgrx, mut avgtx) = (0, 0);
-            for line in buf_reader.lines() {
-                let l = line.unwrap();
-                let v: Vec<&str> = l.split(':').collect();
-                if v.len() < 2 {
-                    continue;
-                }
-
-                // spec iface
-                if args_1.skip_iface(v[0]) {
-                    continue;
-                }
-
-                let v1: Vec<&str> = v[1].split_whitespace().collect();
-                avgrx += v1[0].parse::<u64>().unwrap();
-                avgtx += v1[8].parse::<u64>().unwrap();
-            }
+        let args = crate::remote::args();
+        let _ = fs::read_to_string("/proc/net/dev").map(|content| {
+            let mut ifaces = parse_net_dev(&content);
+            // spec iface
+            ifaces.retain(|o| !args.skip_iface(&o.name));
+            let avgrx = ifaces.iter().map(|o| o.rx_bytes).sum::<u64>();
+            let avgtx = ifaces.iter().map(|o| o.tx_bytes).sum::<u64>();
 
             let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as f64;
 
             if let Ok(mut t) = G_NET_SPEED.lock() {
                 t.diff = now - t.clock;
                 t.clock = now;
-                t.netrx = ((avgrx - t.avgrx) as f64 / t.diff) as u64;
-                t.nettx = ((avgtx - t.avgtx) as f64 / t.diff) as u64;
+                // the iface list may change with the pushed config
+                t.netrx = (avgrx.saturating_sub(t.avgrx) as f64 / t.diff) as u64;
+                t.nettx = (avgtx.saturating_sub(t.avgtx) as f64 / t.diff) as u64;
                 t.avgrx = avgrx;
                 t.avgtx = avgtx;
+                for o in &mut ifaces {
+                    if let Some(pre) = t.ifaces.iter().find(|pre| pre.name == o.name) {
+                        o.rx_rate = (o.rx_bytes.saturating_sub(pre.rx_bytes) as f64 / t.diff) as u64;
+                        o.tx_rate = (o.tx_bytes.saturating_sub(pre.tx_bytes) as f64 / t.diff) as u64;
+                    }
+                }
+                t.ifaces = ifaces;
 
                 // dbg!(&t);
             }
@@ -220,47 +387,291 @@ pub fn start_net_speed_collect_t(args: &Args) {
     });
 }
 
+#[derive(Debug, Default)]
+pub struct CpuStat {
+    pub cores: Vec<f64>,
+    pub times: Option<CpuTimes>,
+}
+
+pub static G_CPU_STAT: LazyLock<Mutex<CpuStat>> = LazyLock::new(Mutex::default);
+
+// `cpu` and `cpuN` lines of /proc/stat: user nice system idle iowait irq softirq steal guest guest_nice
+pub fn parse_proc_stat(content: &str) -> Vec<Vec<u64>> {
+    content
+        .lines()
+        .filter(|l| l.starts_with("cpu"))
+        .map(|l| {
+            l.split_whitespace()
+                .skip(1)
+                .map(|s| s.parse::<u64>().unwrap_or(0))
+                .collect()
+        })
+        .collect()
+}
+
+// guest time is already counted in user
+pub fn calc_cpu_times(pre: &[u64], cur: &[u64]) -> CpuTimes {
+    let delta = (0..8)
+        .map(|i| cur.get(i).unwrap_or(&0).saturating_sub(*pre.get(i).unwrap_or(&0)) as f64)
+        .collect::<Vec<_>>();
+    let total = delta.iter().sum::<f64>().max(1.0);
+    let pct = |i: usize| (delta[i] * 1000.0 / total).round() / 10.0;
+    CpuTimes {
+        user: pct(0),
+        nice: pct(1),
+        system: pct(2),
+        idle: pct(3),
+        iowait: pct(4),
+        irq: pct(5),
+        softirq: pct(6),
+        steal: pct(7),
+    }
+}
+
+pub fn core_usage(t: &CpuTimes) -> f64 {
+    ((100.0 - t.idle - t.iowait) * 10.0).round().max(0.0) / 10.0
+}
+
 pub static G_CPU_PERCENT: LazyLock<Arc<Mutex<f64>>> = LazyLock::new(|| Arc::new(Mutex::default()));
 #[allow(unused)]
 pub fn start_cpu_percent_collect_t() {
-    let mut pre_cpu: Vec<u64> = vec![0, 0, 0, 0];
+    let mut pre_cpus: Vec<Vec<u64>> = Vec::new();
     thread::spawn(move || loop {
-        let _ = File::open("/proc/stat").map(|file| {
-            let mut buf_reader = BufReader::new(file);
-            let mut buf = String::new();
-            let _ = buf_reader.read_line(&mut buf).map(|_| {
-                let cur_cpu = buf
-                    .split_whitespace()
-                    .enumerate()
-                    .filter(|&(idx, _)| idx > 0 && idx < 5)
-                    .map(|(_, e)| e.parse::<u64>().unwrap())
-                    .collect::<Vec<_>>();
-
-                let pre: u64 = pre_cpu.iter().sum();
-                let cur: u64 = cur_cpu.iter().sum();
-                let mut st = cur - pre;
-                if st == 0 {
-                    st = 1;
-                }
+        let _ = fs::read_to_string("/proc/stat").map(|buf| {
+            let cur_cpus = parse_proc_stat(&buf);
+            if cur_cpus.first().is_none_or(|o| o.len() < 4) {
+                return;
+            }
+            let pre_cpu = pre_cpus.first().map_or(&[0_u64; 4][..], |o| &o[..4]);
+            let cur_cpu = &cur_cpus[0][..4];
+
+            let pre: u64 = pre_cpu.iter().sum();
+            let cur: u64 = cur_cpu.iter().sum();
+            let mut st = cur.saturating_sub(pre);
+            if st == 0 {
+                st = 1;
+            }
 
-                let res = 100.0 - (100.0 * (cur_cpu[3] - pre_cpu[3]) as f64 / st as f64);
+            let res = 100.0 - (100.0 * cur_cpu[3].saturating_sub(pre_cpu[3]) as f64 / st as f64);
 
-                // dbg!(&pre_cpu);
-                // dbg!(&cur_cpu);
+            if let Ok(mut cpu_percent) = G_CPU_PERCENT.lock() {
+                *cpu_percent = res.round();
+            }
 
-                pre_cpu = cur_cpu;
+            let times = cur_cpus
+                .iter()
+                .enumerate()
+                .map(|(idx, cur)| calc_cpu_times(pre_cpus.get(idx).map_or(&[], Vec::as_slice), cur))
+                .collect::<Vec<_>>();
+            if let Ok(mut o) = G_CPU_STAT.lock() {
+                o.times = times.first().copied();
+                o.cores = times.iter().skip(1).map(core_usage).collect();
+            }
 
-                if let Ok(mut cpu_percent) = G_CPU_PERCENT.lock() {
-                    *cpu_percent = res.round();
-                    // dbg!(cpu_percent);
-                }
-            });
+            pre_cpus = cur_cpus;
         });
 
         thread::sleep(Duration::from_millis(SAMPLE_PERIOD));
     });
 }
 
+pub static G_DISK_IO: LazyLock<Mutex<Vec<DiskIo>>> = LazyLock::new(Mutex::default);
+
+// major minor name | reads merged sectors ms | writes merged sectors ms | in_flight io_ms ...
+// (name, [reads, read sectors, writes, write sectors, io_ms])
+pub fn parse_diskstats(content: &str) -> Vec<(String, [u64; 5])> {
+    content
+        .lines()
+        .filter_map(|l| {
+            let v = l.split_whitespace().collect::<Vec<_>>();
+            if v.len() < 13 {
+                return None;
+            }
+            let n = |i: usize| v[i].parse::<u64>().unwrap_or(0);
+            Some((v[2].to_string(), [n(3), n(5), n(7), n(9), n(12)]))
+        })
+        // loop & ram disks, devices never used
+        .filter(|(name, o)| !name.starts_with("loop") && !name.starts_with("ram") && o[0] + o[2] > 0)
+        .collect()
+}
+
+pub fn calc_disk_io(name: &str, pre: &[u64; 5], cur: &[u64; 5], secs: f64) -> DiskIo {
+    let d = |i: usize| cur[i].saturating_sub(pre[i]) as f64 / secs;
+    DiskIo {
+        name: name.to_string(),
+        read_rate: (d(1) * 512.0) as u64,
+        write_rate: (d(3) * 512.0) as u64,
+        read_iops: (d(0) * 10.0).round() / 10.0,
+        write_iops: (d(2) * 10.0).round() / 10.0,
+        util: (d(4) / 10.0).min(100.0).round(),
+    }
+}
+
+#[allow(unused)]
+pub fn start_disk_io_collect_t() {
+    let mut pre: HashMap<String, [u64; 5]> = HashMap::new();
+    let mut clock = SystemTime::now();
+    thread::spawn(move || loop {
+        let _ = fs::read_to_string("/proc/diskstats").map(|content| {
+            let now = SystemTime::now();
+            let secs = now.duration_since(clock).unwrap_or_default().as_secs_f64().max(0.001);
+            clock = now;
+
+            let cur = parse_diskstats(&content);
+            let io = cur
+                .iter()
+                .filter_map(|(name, o)| Some(calc_disk_io(name, pre.get(name)?, o, secs)))
+                .collect::<Vec<_>>();
+            if let Ok(mut t) = G_DISK_IO.lock() {
+                *t = io;
+            }
+            pre = cur.into_iter().collect();
+        });
+
+        thread::sleep(Duration::from_millis(SAMPLE_PERIOD));
+    });
+}
+
+// hwmon `*_input` files, millidegree celsius / rpm
+fn read_sensors_hwmon(root: &Path) -> Vec<SensorInfo> {
+    let read = |p: &Path| fs::read_to_string(p).map(|s| s.trim().to_string()).ok();
+    let num = |p: &Path| read(p).and_then(|s| s.parse::<i64>().ok());
+    let mut chips: Vec<_> = fs::read_dir(root)
+        .map(|d| d.flatten().map(|e| e.path()).collect())
+        .unwrap_or_default();
+    chips.sort();
+
+    let mut sensors = Vec::new();
+    for chip in chips {
+        let chip_name = read(&chip.join("name")).unwrap_or_else(|| chip.file_name().unwrap().to_string_lossy().into());
+        let mut inputs: Vec<String> = fs::read_dir(&chip)
+            .map(|d| {
+                d.flatten()
+                    .filter_map(|e| e.file_name().into_string().ok())
+                    .filter(|s| (s.starts_with("temp") || s.starts_with("fan")) && s.ends_with("_input"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        inputs.sort_by_key(|s| (s.starts_with("fan"), s.len(), s.clone()));
+        for input in inputs {
+            // temp1_input => temp1
+            let id = input.trim_end_matches("_input");
+            let Some(v) = num(&chip.join(&input)) else {
+                continue;
+            };
+            let label = read(&chip.join(format!("{id}_label"))).unwrap_or_else(|| id.to_string());
+            let mut o = SensorInfo {
+                name: format!("{chip_name}/{label}"),
+                ..Default::default()
+            };
+            if id.starts_with("fan") {
+                o.fan = u32::try_from(v).ok();
+            } else {
+                o.temp = Some(v as f64 / 1000.0);
+                o.temp_crit = num(&chip.join(format!("{id}_crit")))
+                    .filter(|v| *v > 0)
+                    .map(|v| v as f64 / 1000.0);
+            }
+            sensors.push(o);
+        }
+    }
+    sensors
+}
+
+// thermal zones, eg. raspberry pi & arm boards without hwmon
+fn read_sensors_thermal(root: &Path) -> Vec<SensorInfo> {
+    let mut zones: Vec<_> = fs::read_dir(root)
+        .map(|d| {
+            d.flatten()
+                .map(|e| e.path())
+                .filter(|p| {
+                    p.file_name()
+                        .is_some_and(|s| s.to_string_lossy().starts_with("thermal_zone"))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    zones.sort();
+    zones
+        .iter()
+        .filter_map(|zone| {
+            let v = fs::read_to_string(zone.join("temp")).ok()?.trim().parse::<i64>().ok()?;
+            let name = fs::read_to_string(zone.join("type")).map_or_else(
+                |_| zone.file_name().unwrap().to_string_lossy().to_string(),
+                |s| s.trim().to_string(),
+            );
+            Some(SensorInfo {
+                name,
+                temp: Some(v as f64 / 1000.0),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+pub fn get_sensors() -> Vec<SensorInfo> {
+    let sensors = read_sensors_hwmon(Path::new("/sys/class/hwmon"));
+    if sensors.iter().any(|o| o.temp.is_some()) {
+        return sensors;
+    }
+    let mut thermal = read_sensors_thermal(Path::new("/sys/class/thermal"));
+    thermal.extend(sensors);
+    thermal
+}
+
+// some avg10=0.17 avg60=0.21 avg300=0.13 total=19104166
+// full avg10=0.04 avg60=0.08 avg300=0.06 total=12545280
+pub fn parse_psi(content: &str) -> Option<Psi> {
+    let mut o = Psi::default();
+    let mut found = false;
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        let (avg10, avg60, avg300, total) = match fields.next() {
+            Some("some") => (
+                &mut o.some_avg10,
+                &mut o.some_avg60,
+                &mut o.some_avg300,
+                &mut o.some_total,
+            ),
+            Some("full") => (
+                &mut o.full_avg10,
+                &mut o.full_avg60,
+                &mut o.full_avg300,
+                &mut o.full_total,
+            ),
+            _ => continue,
+        };
+        for (k, v) in fields.filter_map(|s| s.split_once('=')) {
+            match k {
+                "avg10" => *avg10 = v.parse().unwrap_or_default(),
+                "avg60" => *avg60 = v.parse().unwrap_or_default(),
+                "avg300" => *avg300 = v.parse().unwrap_or_default(),
+                "total" => *total = v.parse().unwrap_or_default(),
+                _ => {}
+            }
+        }
+        found = true;
+    }
+    found.then_some(o)
+}
+
+// psi needs linux 4.20+ with CONFIG_PSI, oom_kill 4.13+
+pub fn get_pressure(stat: &mut StatRequest) {
+    let psi = |resource: &str| {
+        fs::read_to_string(format!("/proc/pressure/{resource}"))
+            .ok()
+            .and_then(|s| parse_psi(&s))
+    };
+    stat.psi_cpu = psi("cpu");
+    stat.psi_memory = psi("memory");
+    stat.psi_io = psi("io");
+    stat.oom_kill = fs::read_to_string("/proc/vmstat").ok().and_then(|s| {
+        s.lines()
+            .find_map(|l| l.strip_prefix("oom_kill "))
+            .and_then(|v| v.trim().parse().ok())
+    });
+}
+
 static ONLINE_IPV4: u8 = 1;
 static ONLINE_IPV6: u8 = 2;
 pub fn get_network(args: &Args) -> (bool, bool) {
@@ -287,94 +698,6 @@ pub fn get_network(args: &Args) -> (bool, bool) {
     network.into()
 }
 
-#[derive(Debug, Default)]
-pub struct PingData {
-    pub probe_uri: String,
-    pub lost_rate: u32,
-    pub ping_time: u32,
-}
-
-fn start_ping_collect_t(data: &Arc<Mutex<PingData>>) {
-    let mut package_list: LinkedList<i32> = LinkedList::new();
-    let mut package_lost: u32 = 0;
-    let pt = &*data.lock().unwrap();
-    let addr = pt
-        .probe_uri
-        .to_socket_addrs()
-        .unwrap()
-        .next()
-        .expect("can't get addr info");
-    info!("{} => {:?}", pt.probe_uri, addr);
-
-    let ping_data = data.clone();
-    thread::spawn(move || loop {
-        if package_list.len() > 100 && package_list.pop_front().unwrap() == 0 {
-            package_lost -= 1;
-        }
-
-        let instant = Instant::now();
-        match TcpStream::connect_timeout(&addr, Duration::from_millis(TIMEOUT_MS)) {
-            Ok(s) => {
-                let _ = s.shutdown(Shutdown::Both);
-                package_list.push_back(1);
-            }
-            Err(e) => {
-                // error!("{:?}", e);
-                if e.kind() == ConnectionRefused {
-                    package_list.push_back(1);
-                } else {
-                    package_lost += 1;
-                    package_list.push_back(0);
-                }
-            }
-        }
-        let time_cost_ms = instant.elapsed().as_millis();
-
-        if let Ok(mut o) = ping_data.lock() {
-            o.ping_time = u32::try_from(time_cost_ms).unwrap_or(u32::MAX);
-            if package_list.len() > 30 {
-                o.lost_rate = package_lost * 100 / u32::try_from(package_list.len()).unwrap_or(u32::MAX);
-            }
-        }
-
-        thread::sleep(Duration::from_millis(SAMPLE_PERIOD));
-    });
-}
-
-pub static G_PING_10010: OnceCell<Arc<Mutex<PingData>>> = OnceCell::new();
-pub static G_PING_189: OnceCell<Arc<Mutex<PingData>>> = OnceCell::new();
-pub static G_PING_10086: OnceCell<Arc<Mutex<PingData>>> = OnceCell::new();
-
-pub fn start_all_ping_collect_t(args: &Args) {
-    G_PING_10010
-        .set(Arc::new(Mutex::new(PingData {
-            probe_uri: args.cu_addr.clone(),
-            lost_rate: 0,
-            ping_time: 0,
-        })))
-        .unwrap();
-    G_PING_189
-        .set(Arc::new(Mutex::new(PingData {
-            probe_uri: args.ct_addr.clone(),
-            lost_rate: 0,
-            ping_time: 0,
-        })))
-        .unwrap();
-    G_PING_10086
-        .set(Arc::new(Mutex::new(PingData {
-            probe_uri: args.cm_addr.clone(),
-            lost_rate: 0,
-            ping_time: 0,
-        })))
-        .unwrap();
-
-    if !args.disable_ping {
-        start_ping_collect_t(G_PING_10010.get().unwrap());
-        start_ping_collect_t(G_PING_189.get().unwrap());
-        start_ping_collect_t(G_PING_10086.get().unwrap());
-    }
-}
-
 pub fn sample(args: &Args, stat: &mut StatRequest) {
     stat.version = env!("CARGO_PKG_VERSION").to_string();
     stat.vnstat = args.vnstat;
@@ -392,20 +715,24 @@ pub fn sample(args: &Args, stat: &mut StatRequest) {
     stat.swap_total = swap_total;
     stat.swap_used = swap_total - swap_free;
 
-    get_hdd(stat);
+    get_hdd(args, stat);
+    stat.sensors = get_sensors();
+    get_pressure(stat);
 
-    let (t, u, p, d) = if args.disable_tupd { (0, 0, 0, 0) } else { tupd() };
-    stat.tcp = t;
-    stat.udp = u;
-    stat.process = p;
-    stat.thread = d;
+    if !args.disable_tupd {
+        tupd(stat);
+    }
 
     if args.vnstat {
-        let (network_in, network_out, m_network_in, m_network_out) = vnstat::get_traffic(args).unwrap();
-        stat.network_in = network_in;
-        stat.network_out = network_out;
-        stat.last_network_in = network_in - m_network_in;
-        stat.last_network_out = network_out - m_network_out;
+        match vnstat::get_traffic(args) {
+            Ok((network_in, network_out, m_network_in, m_network_out)) => {
+                stat.network_in = network_in;
+                stat.network_out = network_out;
+                stat.last_network_in = network_in - m_network_in;
+                stat.last_network_out = network_out - m_network_out;
+            }
+            Err(err) => error!("vnstat traffic => {err}"),
+        }
     } else {
         let (network_in, network_out) = get_sys_traffic(args);
         stat.network_in = network_in;
@@ -415,24 +742,163 @@ pub fn sample(args: &Args, stat: &mut StatRequest) {
     if let Ok(o) = G_CPU_PERCENT.lock() {
         stat.cpu = *o;
     }
+    if let Ok(o) = G_CPU_STAT.lock() {
+        stat.cpu_cores.clone_from(&o.cores);
+        stat.cpu_times = o.times;
+    }
+    if let Ok(o) = G_DISK_IO.lock() {
+        stat.disk_io.clone_from(&o);
+    }
 
     if let Ok(o) = G_NET_SPEED.lock() {
         stat.network_rx = o.netrx;
         stat.network_tx = o.nettx;
+        stat.ifaces.clone_from(&o.ifaces);
+    }
+    probe::sample(stat);
+    watch::sample(stat);
+    #[cfg(target_os = "linux")]
+    container::sample(stat);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mountinfo() {
+        let content = r"22 1 252:1 / / rw,relatime shared:1 - ext4 /dev/vda1 rw
+23 22 0:21 / /proc rw,nosuid,nodev,noexec,relatime shared:12 - proc proc rw
+40 22 252:1 /srv/data /mnt/my\040data rw,relatime shared:1 - ext4 /dev/vda1 rw
+41 22 0:45 / /mnt/nas rw,relatime - nfs4 10.0.0.2:/export rw
+42 22 0:46 / /data rw,relatime shared:20 master:3 - xfs /dev/vdb rw
+";
+        let o = parse_mountinfo(content);
+        assert_eq!(o.len(), 5);
+        assert_eq!(
+            o[2],
+            Mount {
+                dev: "252:1".to_string(),
+                source: "/dev/vda1".to_string(),
+                mount_point: "/mnt/my data".to_string(),
+                fs: "ext4".to_string(),
+            }
+        );
+        // optional fields before `-`
+        assert_eq!((o[4].fs.as_str(), o[4].source.as_str()), ("xfs", "/dev/vdb"));
+        assert!(is_remote(&o[3].source, &o[3].fs) && !is_remote(&o[4].source, &o[4].fs));
+        assert_eq!(unescape_octal(r"a\134b\0c\999"), r"a\b\0c\999");
     }
-    {
-        let o = &*G_PING_10010.get().unwrap().lock().unwrap();
-        stat.ping_10010 = o.lost_rate.into();
-        stat.time_10010 = o.ping_time.into();
+
+    #[test]
+    fn test_count_sockets() {
+        let content = r"  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:0050 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 100 1 0 100 0 0 10 0
+   1: 0100007F:0050 0100007F:C350 01 00000000:00000000 00:00000000 00000000     0        0 101 1 0 20 4 30 10 -1
+   2: 0100007F:0050 0100007F:C351 06 00000000:00000000 03:00001770 00000000     0        0 0 3 0
+   3: 0100007F:0050 0100007F:C352 0C 00000000:00000000 00:00000000 00000000     0        0 0 3 0
+   4: 0100007F:C353 0100007F:0050 01 00000000:00000000 00:00000000 00000000     0        0 102 1 0 20 4 30 10 -1
+";
+        let mut st = [0; 16];
+        count_sockets(content, &mut st);
+        let o = tcp_states(&st);
+        assert_eq!((o.listen, o.established, o.time_wait, o.syn_recv), (1, 2, 1, 1));
+
+        let mut stat = StatRequest::default();
+        tupd(&mut stat);
+        assert!(stat.process > 0 && stat.thread >= stat.process && stat.tcp_states.is_some());
     }
-    {
-        let o = &*G_PING_189.get().unwrap().lock().unwrap();
-        stat.ping_189 = o.lost_rate.into();
-        stat.time_189 = o.ping_time.into();
+
+    #[test]
+    fn test_parse_psi() {
+        let o = parse_psi(
+            "some avg10=0.17 avg60=0.21 avg300=0.13 total=19104166\nfull avg10=0.04 avg60=0.08 avg300=0.06 total=12545280\n",
+        )
+        .unwrap();
+        assert_eq!((o.some_avg10, o.some_avg300, o.some_total), (0.17, 0.13, 19_104_166));
+        assert_eq!((o.full_avg60, o.full_total), (0.08, 12_545_280));
+        // cpu before linux 5.13 has no `full` line
+        let o = parse_psi("some avg10=1.50 avg60=0.00 avg300=0.00 total=100\n").unwrap();
+        assert_eq!((o.some_avg10, o.full_avg10), (1.5, 0.0));
+        assert!(parse_psi("").is_none());
     }
-    {
-        let o = &*G_PING_10086.get().unwrap().lock().unwrap();
-        stat.ping_10086 = o.lost_rate.into();
-        stat.time_10086 = o.ping_time.into();
+
+    #[test]
+    fn test_sensors() {
+        let root = std::env::temp_dir().join(format!("ssr-sensors-{}", std::process::id()));
+        let files = [
+            ("hwmon/hwmon0/name", "coretemp\n"),
+            ("hwmon/hwmon0/temp1_input", "45000\n"),
+            ("hwmon/hwmon0/temp1_label", "Package id 0\n"),
+            ("hwmon/hwmon0/temp1_crit", "100000\n"),
+            ("hwmon/hwmon0/temp10_input", "41000\n"),
+            ("hwmon/hwmon0/temp2_input", "43500\n"),
+            ("hwmon/hwmon1/name", "nct6775\n"),
+            ("hwmon/hwmon1/fan2_input", "1200\n"),
+            ("thermal/thermal_zone0/type", "cpu-thermal\n"),
+            ("thermal/thermal_zone0/temp", "52123\n"),
+            ("thermal/cooling_device0/type", "fan\n"),
+        ];
+        for (path, content) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        let o = read_sensors_hwmon(&root.join("hwmon"));
+        let names: Vec<_> = o.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["coretemp/Package id 0", "coretemp/temp2", "coretemp/temp10", "nct6775/fan2"]);
+        assert_eq!((o[0].temp, o[0].temp_crit, o[0].fan), (Some(45.0), Some(100.0), None));
+        assert_eq!((o[3].temp, o[3].fan), (None, Some(1200)));
+
+        let o = read_sensors_thermal(&root.join("thermal"));
+        assert_eq!(o.len(), 1);
+        assert_eq!((o[0].name.as_str(), o[0].temp), ("cpu-thermal", Some(52.123)));
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_parse_net_dev() {
+        let content = r"Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo: 1024      10    0    0    0     0          0         0     1024      10    0    0    0     0       0          0
+  eth0:123456789 1000    1    2    0     0          0         0 98765    900    3    4    0     0       0          0
+";
+        let ifaces = parse_net_dev(content);
+        assert_eq!(ifaces.len(), 2);
+        let eth0 = &ifaces[1];
+        assert_eq!(eth0.name, "eth0");
+        assert_eq!((eth0.rx_bytes, eth0.rx_packets, eth0.rx_errors, eth0.rx_drop), (123_456_789, 1000, 1, 2));
+        assert_eq!((eth0.tx_bytes, eth0.tx_packets, eth0.tx_errors, eth0.tx_drop), (98765, 900, 3, 4));
+    }
+
+    #[test]
+    fn test_cpu_times() {
+        let pre = parse_proc_stat("cpu  100 0 100 700 50 0 0 50 0 0\ncpu0 100 0 100 700 50 0 0 50 0 0\nintr 1 2 3\n");
+        let cur = parse_proc_stat("cpu  200 0 150 900 150 10 10 80 20 0\ncpu0 200 0 150 900 150 10 10 80 20 0\n");
+        assert_eq!(pre.len(), 2);
+        // delta: user 100, system 50, idle 200, iowait 100, irq 10, softirq 10, steal 30 => 500
+        let t = calc_cpu_times(&pre[1], &cur[1]);
+        assert_eq!((t.user, t.system, t.idle, t.iowait, t.steal), (20.0, 10.0, 40.0, 20.0, 6.0));
+        assert!((core_usage(&t) - 40.0).abs() < f64::EPSILON);
+        // first sample, counters since boot
+        assert!((calc_cpu_times(&[], &cur[0]).idle - 900.0 * 100.0 / 1500.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_disk_io() {
+        let content = "   7       0 loop0 100 0 200 10 0 0 0 0 0 20 10 0 0 0 0
+ 253       0 vda 1000 10 20000 500 2000 20 40000 800 0 1200 1300 0 0 0 0
+ 253       1 vda1 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+";
+        let pre = parse_diskstats(content);
+        assert_eq!(pre.len(), 1);
+        assert_eq!(pre[0], ("vda".to_string(), [1000, 20000, 2000, 40000, 1200]));
+
+        let cur = [1100, 22048, 2300, 44096, 1700];
+        let o = calc_disk_io("vda", &pre[0].1, &cur, 2.0);
+        assert_eq!((o.read_rate, o.write_rate), (1024 * 512, 2048 * 512));
+        assert!((o.read_iops - 50.0).abs() < f64::EPSILON && (o.write_iops - 150.0).abs() < f64::EPSILON);
+        assert!((o.util - 25.0).abs() < f64::EPSILON);
     }
 }
@@ -12,11 +12,15 @@ use std::time::Duration;
 use sysinfo::CpuRefreshKind;
 use sysinfo::{Components, Disks, MemoryRefreshKind, Networks, RefreshKind, System};
 use std::env::consts::ARCH;
+use crate::probe;
 use crate::status;
 use crate::vnstat;
+use crate::watch;
+#[cfg(target_os = "linux")]
+use crate::container;
 use crate::Args;
 use stat_common::{
-    server_status::{DiskInfo, StatRequest, SysInfo},
+    server_status::{DiskInfo, DiskIo, NetIfInfo, SensorInfo, StatRequest, SysInfo},
     utils::bytes2human,
 };
 
@@ -45,6 +49,15 @@ pub static G_EXPECT_FS: LazyLock<Vec<&'static str>> = LazyLock::new(|| {
 });
 pub static G_CPU_PERCENT: LazyLock<Arc<Mutex<f64>>> = LazyLock::new(|| Arc::new(Mutex::default()));
 
+/// `fs_types` (`--fs`) replaces the [`G_EXPECT_FS`] substring match with exact names
+pub(crate) fn expect_fs(fs_types: &[String], fs: &str) -> bool {
+    if fs_types.is_empty() {
+        G_EXPECT_FS.iter().any(|&k| fs.contains(k))
+    } else {
+        fs_types.iter().any(|o| fs.eq(&o.to_lowercase()))
+    }
+}
+
 /// A minimal disk descriptor used by [`calc_hdd_stats`] so the logic can be
 /// tested without OS-level disk enumeration.
 #[derive(Debug)]
@@ -63,14 +76,16 @@ pub(crate) struct DiskCalcInput {
 /// On non-Windows platforms the same physical disk can appear multiple times
 /// (once per mount point); `is_windows = false` enables deduplication by disk
 /// name so each physical device is counted only once.
-pub(crate) fn calc_hdd_stats(disks: &[DiskCalcInput], si: bool, is_windows: bool) -> (u64, u64) {
+///
+/// Only file systems accepted by [`expect_fs`] with `fs_types` are counted.
+pub(crate) fn calc_hdd_stats(disks: &[DiskCalcInput], si: bool, is_windows: bool, fs_types: &[String]) -> (u64, u64) {
     let (mut total_bytes, mut avail_bytes) = (0_u64, 0_u64);
     // Dedup set is only needed on non-Windows platforms.
     let mut seen: Option<HashSet<String>> = if is_windows { None } else { Some(HashSet::new()) };
 
     for disk in disks {
         let fs = disk.fs_type.to_lowercase();
-        if G_EXPECT_FS.iter().any(|&k| fs.contains(k)) {
+        if expect_fs(fs_types, &fs) {
             if let Some(ref mut s) = seen {
                 if s.contains(&disk.name) {
                     continue;
@@ -90,6 +105,7 @@ pub(crate) fn calc_hdd_stats(disks: &[DiskCalcInput], si: bool, is_windows: bool
 
 pub fn start_cpu_percent_collect_t() {
     let mut sys = System::new_with_specifics(RefreshKind::nothing().with_cpu(CpuRefreshKind::nothing().with_cpu_usage()));
+    let mut pre_cpu: Vec<u64> = Vec::new();
     thread::spawn(move || loop {
         sys.refresh_cpu_all();
 
@@ -97,6 +113,20 @@ pub fn start_cpu_percent_collect_t() {
         if let Ok(mut cpu_percent) = G_CPU_PERCENT.lock() {
             *cpu_percent = f64::from(global_cpu.round());
         }
+        // sysinfo has no time split, /proc/stat on linux
+        let mut times = None;
+        #[cfg(target_os = "linux")]
+        if let Some(cur_cpu) = fs::read_to_string("/proc/stat")
+            .ok()
+            .and_then(|buf| status::parse_proc_stat(&buf).into_iter().next())
+        {
+            times = Some(status::calc_cpu_times(&pre_cpu, &cur_cpu));
+            pre_cpu = cur_cpu;
+        }
+        if let Ok(mut o) = status::G_CPU_STAT.lock() {
+            o.cores = sys.cpus().iter().map(|c| f64::from((c.cpu_usage() * 10.0).round()) / 10.0).collect();
+            o.times = times;
+        }
 
         thread::sleep(Duration::from_millis(SAMPLE_PERIOD));
     });
@@ -106,26 +136,52 @@ pub fn start_cpu_percent_collect_t() {
 pub struct NetSpeed {
     pub net_rx: u64,
     pub net_tx: u64,
+    pub ifaces: Vec<NetIfInfo>,
 }
 
 pub static G_NET_SPEED: LazyLock<Arc<Mutex<NetSpeed>>> = LazyLock::new(|| Arc::new(Mutex::default()));
 
-pub fn start_net_speed_collect_t(args: &Args) {
+pub fn start_net_speed_collect_t() {
     let mut networks = Networks::new_with_refreshed_list();
-    let args_1 = args.clone();
     thread::spawn(move || loop {
+        let args = crate::remote::args();
         let (mut net_rx, mut net_tx) = (0_u64, 0_u64);
+        let mut ifaces = Vec::new();
         for (name, data) in &networks {
             // spec iface
-            if args_1.skip_iface(name) {
+            if args.skip_iface(name) {
                 continue;
             }
             net_rx += data.received();
             net_tx += data.transmitted();
+            ifaces.push(NetIfInfo {
+                name: name.clone(),
+                rx_bytes: data.total_received(),
+                tx_bytes: data.total_transmitted(),
+                rx_packets: data.total_packets_received(),
+                tx_packets: data.total_packets_transmitted(),
+                rx_errors: data.total_errors_on_received(),
+                tx_errors: data.total_errors_on_transmitted(),
+                rx_rate: data.received(),
+                tx_rate: data.transmitted(),
+                ..Default::default()
+            });
+        }
+        ifaces.sort_by(|a, b| a.name.cmp(&b.name));
+        // sysinfo has no drop counters
+        #[cfg(target_os = "linux")]
+        if let Ok(content) = fs::read_to_string("/proc/net/dev") {
+            for o in status::parse_net_dev(&content) {
+                if let Some(i) = ifaces.iter_mut().find(|i| i.name == o.name) {
+                    i.rx_drop = o.rx_drop;
+                    i.tx_drop = o.tx_drop;
+                }
+            }
         }
         if let Ok(mut t) = G_NET_SPEED.lock() {
             t.net_rx = net_rx;
             t.net_tx = net_tx;
+            t.ifaces = ifaces;
         }
 
         networks.refresh(true);
@@ -133,6 +189,59 @@ pub fn start_net_speed_collect_t(args: &Args) {
     });
 }
 
+pub fn get_sensors() -> Vec<SensorInfo> {
+    // sysinfo has no fans
+    #[cfg(target_os = "linux")]
+    return status::get_sensors();
+
+    #[cfg(not(target_os = "linux"))]
+    Components::new_with_refreshed_list()
+        .iter()
+        .filter_map(|c| {
+            Some(SensorInfo {
+                name: c.label().to_string(),
+                temp: Some(f64::from(c.temperature()?)),
+                temp_crit: c.critical().map(f64::from),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+pub fn start_disk_io_collect_t() {
+    // iops & util come from /proc/diskstats
+    #[cfg(target_os = "linux")]
+    status::start_disk_io_collect_t();
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let mut disks = Disks::new_with_refreshed_list();
+        thread::spawn(move || loop {
+            disks.refresh(true);
+            let mut io: Vec<DiskIo> = Vec::new();
+            for disk in &disks {
+                let name = disk.name().to_string_lossy().to_string();
+                // a device mounted more than once
+                if io.iter().any(|o| o.name == name) {
+                    continue;
+                }
+                let usage = disk.usage();
+                io.push(DiskIo {
+                    name,
+                    read_rate: usage.read_bytes * 1000 / SAMPLE_PERIOD,
+                    write_rate: usage.written_bytes * 1000 / SAMPLE_PERIOD,
+                    ..Default::default()
+                });
+            }
+            if let Ok(mut t) = status::G_DISK_IO.lock() {
+                *t = io;
+            }
+
+            thread::sleep(Duration::from_millis(SAMPLE_PERIOD));
+        });
+    }
+}
+
 pub fn sample(args: &Args, stat: &mut StatRequest) {
     stat.version = env!("CARGO_PKG_VERSION").to_string();
     stat.vnstat = args.vnstat;
@@ -170,20 +279,28 @@ pub fn sample(args: &Args, stat: &mut StatRequest) {
     let disks = Disks::new_with_refreshed_list();
     stat.disks.clear();
 
+    let disks = disks
+        .iter()
+        .filter(|d| !args.skip_mount(&d.mount_point().to_string_lossy(), &d.file_system().to_string_lossy()))
+        .collect::<Vec<_>>();
+
     for disk in &disks {
         let fs_type = disk.file_system().to_string_lossy().to_lowercase();
         let total_space = disk.total_space();
-
-        if G_EXPECT_FS.iter().any(|&k| fs_type.contains(k)) {
-            stat.disks.push(DiskInfo {
-                name: disk.name().to_string_lossy().to_string(),
-                mount_point: disk.mount_point().to_string_lossy().to_string(),
-                file_system: fs_type,
-                total: total_space,
-                used: total_space - disk.available_space(),
-                free: disk.available_space(),
-            });
-        }
+        let mount_point = disk.mount_point().to_string_lossy().to_string();
+        let (inodes_total, inodes_free) = status::get_inodes(&mount_point).unwrap_or_default();
+
+        stat.disks.push(DiskInfo {
+            name: disk.name().to_string_lossy().to_string(),
+            mount_point,
+            file_system: fs_type,
+            total: total_space,
+            used: total_space - disk.available_space(),
+            free: disk.available_space(),
+            inodes_total,
+            inodes_used: inodes_total.saturating_sub(inodes_free),
+            inodes_free,
+        });
     }
 
     let disk_inputs: Vec<DiskCalcInput> = disks
@@ -197,32 +314,28 @@ pub fn sample(args: &Args, stat: &mut StatRequest) {
         .collect();
 
     let is_windows = cfg!(target_os = "windows");
-    let (hdd_total, hdd_used) = calc_hdd_stats(&disk_inputs, stat.si, is_windows);
+    let (hdd_total, hdd_used) = calc_hdd_stats(&disk_inputs, stat.si, is_windows, &args.fs);
     stat.hdd_total = hdd_total;
     stat.hdd_used = hdd_used;
+    stat.sensors = get_sensors();
+    #[cfg(target_os = "linux")]
+    status::get_pressure(stat);
     // t/u/p/d
-    let (t, u, p, d) = if args.disable_tupd {
-        (0, 0, 0, 0)
-    } else if "linux".eq(std::env::consts::OS) {
-        status::tupd()
-    } else {
-        // sys.processes()
-        (0, 0, 0, 0)
-    };
-    stat.tcp = t;
-    stat.udp = u;
-    stat.process = p;
-    stat.thread = d;
+    if !args.disable_tupd && "linux".eq(std::env::consts::OS) {
+        status::tupd(stat);
+    }
 
     // traffic
     if args.vnstat {
         #[cfg(target_os = "linux")]
-        {
-            let (network_in, network_out, m_network_in, m_network_out) = vnstat::get_traffic(args).unwrap();
-            stat.network_in = network_in;
-            stat.network_out = network_out;
-            stat.last_network_in = network_in - m_network_in;
-            stat.last_network_out = network_out - m_network_out;
+        match vnstat::get_traffic(args) {
+            Ok((network_in, network_out, m_network_in, m_network_out)) => {
+                stat.network_in = network_in;
+                stat.network_out = network_out;
+                stat.last_network_in = network_in - m_network_in;
+                stat.last_network_out = network_out - m_network_out;
+            }
+            Err(err) => error!("vnstat traffic => {err}"),
         }
     } else {
         let (mut network_in, mut network_out) = (0_u64, 0_u64);
@@ -243,25 +356,22 @@ pub fn sample(args: &Args, stat: &mut StatRequest) {
     if let Ok(o) = G_CPU_PERCENT.lock() {
         stat.cpu = *o;
     }
+    if let Ok(o) = status::G_CPU_STAT.lock() {
+        stat.cpu_cores.clone_from(&o.cores);
+        stat.cpu_times = o.times;
+    }
+    if let Ok(o) = status::G_DISK_IO.lock() {
+        stat.disk_io.clone_from(&o);
+    }
     if let Ok(o) = G_NET_SPEED.lock() {
         stat.network_rx = o.net_rx;
         stat.network_tx = o.net_tx;
+        stat.ifaces.clone_from(&o.ifaces);
     }
-    {
-        let o = &*status::G_PING_10010.get().unwrap().lock().unwrap();
-        stat.ping_10010 = o.lost_rate.into();
-        stat.time_10010 = o.ping_time.into();
-    }
-    {
-        let o = &*status::G_PING_189.get().unwrap().lock().unwrap();
-        stat.ping_189 = o.lost_rate.into();
-        stat.time_189 = o.ping_time.into();
-    }
-    {
-        let o = &*status::G_PING_10086.get().unwrap().lock().unwrap();
-        stat.ping_10086 = o.lost_rate.into();
-        stat.time_10086 = o.ping_time.into();
-    }
+    probe::sample(stat);
+    watch::sample(stat);
+    #[cfg(target_os = "linux")]
+    container::sample(stat);
 }
 
 pub fn collect_sys_info(args: &Args) -> SysInfo {
@@ -468,7 +578,7 @@ mod tests {
     fn test_si_units_basic() {
         // 1 GB (SI) = 1_000_000_000 bytes; used = 500 MB
         let disks = vec![disk("/dev/sda1", "apfs", 1_000_000_000, 500_000_000)];
-        let (total, used) = calc_hdd_stats(&disks, true, false);
+        let (total, used) = calc_hdd_stats(&disks, true, false, &[]);
         assert_eq!(total, 1000);
         assert_eq!(used, 500);
     }
@@ -479,7 +589,7 @@ mod tests {
     fn test_iec_units_basic() {
         // 1 GiB = 1_073_741_824 bytes = 1024 MiB; used = 512 MiB
         let disks = vec![disk("/dev/sda1", "ext4", 1_073_741_824, 536_870_912)];
-        let (total, used) = calc_hdd_stats(&disks, false, false);
+        let (total, used) = calc_hdd_stats(&disks, false, false, &[]);
         assert_eq!(total, 1024);
         assert_eq!(used, 512);
     }
@@ -493,7 +603,7 @@ mod tests {
             disk("/dev/sda1", "ext4", 1_073_741_824, 536_870_912),
             disk("tmpfs", "tmpfs", 536_870_912, 536_870_912),
         ];
-        let (total, used) = calc_hdd_stats(&disks, false, false);
+        let (total, used) = calc_hdd_stats(&disks, false, false, &[]);
         assert_eq!(total, 1024);
         assert_eq!(used, 512);
     }
@@ -504,7 +614,7 @@ mod tests {
             disk("tmpfs", "tmpfs", 1_073_741_824, 1_073_741_824),
             disk("devtmpfs", "devtmpfs", 1_073_741_824, 1_073_741_824),
         ];
-        let (total, used) = calc_hdd_stats(&disks, false, false);
+        let (total, used) = calc_hdd_stats(&disks, false, false, &[]);
         assert_eq!(total, 0);
         assert_eq!(used, 0);
     }
@@ -519,7 +629,7 @@ mod tests {
             disk("/dev/sda", "ext4", 1_073_741_824, 536_870_912),
             disk("/dev/sda", "ext4", 1_073_741_824, 536_870_912),
         ];
-        let (total, used) = calc_hdd_stats(&disks, false, false);
+        let (total, used) = calc_hdd_stats(&disks, false, false, &[]);
         assert_eq!(total, 1024);
         assert_eq!(used, 512);
     }
@@ -530,7 +640,7 @@ mod tests {
             disk("/dev/sda1", "ext4", 1_073_741_824, 536_870_912),
             disk("/dev/sdb1", "xfs", 1_073_741_824, 0),
         ];
-        let (total, used) = calc_hdd_stats(&disks, false, false);
+        let (total, used) = calc_hdd_stats(&disks, false, false, &[]);
         assert_eq!(total, 2048);
         assert_eq!(used, 1536);
     }
@@ -545,7 +655,7 @@ mod tests {
             disk("C:", "ntfs", 1_073_741_824, 536_870_912),
             disk("C:", "ntfs", 1_073_741_824, 536_870_912),
         ];
-        let (total, used) = calc_hdd_stats(&disks, false, true);
+        let (total, used) = calc_hdd_stats(&disks, false, true, &[]);
         assert_eq!(total, 2048);
         assert_eq!(used, 1024);
     }
@@ -554,7 +664,7 @@ mod tests {
 
     #[test]
     fn test_empty_disk_list() {
-        let (total, used) = calc_hdd_stats(&[], false, false);
+        let (total, used) = calc_hdd_stats(&[], false, false, &[]);
         assert_eq!(total, 0);
         assert_eq!(used, 0);
     }
@@ -562,7 +672,19 @@ mod tests {
     #[test]
     fn test_fully_used_disk() {
         let disks = vec![disk("/dev/sda1", "ext4", 1_073_741_824, 0)];
-        let (total, used) = calc_hdd_stats(&disks, false, false);
+        let (total, used) = calc_hdd_stats(&disks, false, false, &[]);
+        assert_eq!(total, 1024);
+        assert_eq!(used, 1024);
+    }
+
+    #[test]
+    fn test_custom_fs_types() {
+        // an explicit list replaces the built-in one
+        let disks = vec![
+            disk("/dev/sda1", "ext4", 1_073_741_824, 536_870_912),
+            disk("tmpfs", "tmpfs", 1_073_741_824, 0),
+        ];
+        let (total, used) = calc_hdd_stats(&disks, false, false, &["tmpfs".to_string()]);
         assert_eq!(total, 1024);
         assert_eq!(used, 1024);
     }
@@ -571,7 +693,7 @@ mod tests {
     fn test_case_insensitive_fs_match() {
         // Filesystem strings from the OS may be uppercase or mixed case.
         let disks = vec![disk("/dev/sda1", "EXT4", 1_073_741_824, 536_870_912)];
-        let (total, used) = calc_hdd_stats(&disks, false, false);
+        let (total, used) = calc_hdd_stats(&disks, false, false, &[]);
         assert_eq!(total, 1024);
         assert_eq!(used, 512);
     }
@@ -1,11 +1,97 @@
-use anyhow::Result;
-use chrono::{Datelike, Local};
+// `--vnstat`, total & monthly traffic kept in `--traffic-file`, survives reboots without vnstat
+// the vnstat database is imported once when the file does not exist yet
+use anyhow::{bail, Result};
+use chrono::{Datelike, Local, Months, NaiveDate};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 use std::process::Command;
-use std::str;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
 
+use crate::status::parse_net_dev;
 use crate::Args;
 
+const SAVE_PERIOD: Duration = Duration::from_secs(60);
+
+static G_COUNTER: LazyLock<Mutex<Option<Counter>>> = LazyLock::new(Mutex::default);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Counter {
+    // /proc/sys/kernel/random/boot_id, interface counters restart from 0 on reboot
+    pub boot_id: String,
+    // first day of the current month, `--vnstat-mr`
+    pub period: NaiveDate,
+    pub rx: u64,
+    pub tx: u64,
+    // rx / tx when the period started
+    pub period_rx: u64,
+    pub period_tx: u64,
+    // last seen interface counters
+    pub ifaces: HashMap<String, (u64, u64)>,
+    #[serde(skip)]
+    saved_at: Option<Instant>,
+}
+
+/// first day of the month period containing `today`, `day` in 1..=28
+pub fn period_start(today: NaiveDate, day: u32) -> NaiveDate {
+    let start = today.with_day(day).unwrap_or(today);
+    if today.day() >= day {
+        start
+    } else {
+        start - Months::new(1)
+    }
+}
+
+impl Counter {
+    fn new(boot_id: &str, period: NaiveDate, ifaces: &[(String, u64, u64)]) -> Self {
+        let (rx, tx) = ifaces.iter().fold((0, 0), |(rx, tx), o| (rx + o.1, tx + o.2));
+        Self {
+            boot_id: boot_id.to_string(),
+            period,
+            rx,
+            tx,
+            period_rx: rx,
+            period_tx: tx,
+            ifaces: ifaces.iter().map(|(name, rx, tx)| (name.clone(), (*rx, *tx))).collect(),
+            saved_at: None,
+        }
+    }
+
+    /// adds the traffic since the last sample, returns whether a new period started
+    pub fn update(&mut self, boot_id: &str, period: NaiveDate, ifaces: &[(String, u64, u64)]) -> bool {
+        let rebooted = self.boot_id != boot_id;
+        let delta = |cur: u64, pre: Option<u64>| match pre {
+            Some(pre) if !rebooted && cur >= pre => cur - pre,
+            // new interface, reboot, or counters reset with the interface (eg. ppp reconnect)
+            _ => cur,
+        };
+        for (name, rx, tx) in ifaces {
+            let pre = self.ifaces.get(name);
+            self.rx += delta(*rx, pre.map(|o| o.0));
+            self.tx += delta(*tx, pre.map(|o| o.1));
+        }
+        self.ifaces = ifaces.iter().map(|(name, rx, tx)| (name.clone(), (*rx, *tx))).collect();
+        self.boot_id = boot_id.to_string();
+
+        if self.period == period {
+            return false;
+        }
+        self.period = period;
+        self.period_rx = self.rx;
+        self.period_tx = self.tx;
+        true
+    }
+
+    fn save(&mut self, path: &str) -> Result<()> {
+        let tmp = Path::new(path).with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Date {
     pub year: i32,
@@ -70,12 +156,12 @@ pub struct VnstatJson {
     pub interfaces: Vec<Iface>,
 }
 
-fn calc_traffic(j: &VnstatJson, mr: bool, args: &Args) -> (u64, u64, u64, u64) {
+fn calc_traffic(j: &VnstatJson, mr: bool, args: &Args) -> Result<(u64, u64, u64, u64)> {
     let mut v1 = false;
     if j.jsonversion.eq("1") {
         v1 = true;
     } else if !j.jsonversion.eq("2") {
-        panic!("vnstat version number must be 1 or 2");
+        bail!("vnstat version number must be 1 or 2");
     }
 
     let local_now = Local::now();
@@ -96,7 +182,7 @@ fn calc_traffic(j: &VnstatJson, mr: bool, args: &Args) -> (u64, u64, u64, u64) {
         if mr {
             // month rotate, v2 only
             if v1 {
-                panic!("The parameter --json d 31 is not supported in v1.15");
+                bail!("The parameter --json d 31 is not supported in v1.15");
             } else if cur_day >= args.vnstat_mr {
                 for d in &iface.traffic.day {
                     if d.date.year == cur_year && d.date.month == cur_month && d.date.day >= args.vnstat_mr {
@@ -142,51 +228,117 @@ fn calc_traffic(j: &VnstatJson, mr: bool, args: &Args) -> (u64, u64, u64, u64) {
     }
 
     let factor: u64 = if v1 { 1024 } else { 1 };
-    (
+    Ok((
         network_in * factor,
         network_out * factor,
         m_network_in * factor,
         m_network_out * factor,
-    )
+    ))
 }
 
+/// (total rx, total tx, month rx, month tx) of a vnstat installation
+fn import_vnstat(args: &Args) -> Result<(u64, u64, u64, u64)> {
+    let mr = args.vnstat_mr > 1;
+    let vnstat_args: &[&str] = if mr { &["--json", "d", "32"] } else { &["--json", "m"] };
+    let out = Command::new("vnstat").args(vnstat_args).output()?;
+    if !out.status.success() {
+        bail!("vnstat => {}", String::from_utf8_lossy(&out.stderr).trim());
+    }
+    let j: VnstatJson = serde_json::from_slice(&out.stdout)?;
+    calc_traffic(&j, mr, args)
+}
+
+fn read_ifaces(args: &Args) -> Result<Vec<(String, u64, u64)>> {
+    Ok(parse_net_dev(&fs::read_to_string("/proc/net/dev")?)
+        .into_iter()
+        .filter(|o| !args.skip_iface(&o.name))
+        .map(|o| (o.name, o.rx_bytes, o.tx_bytes))
+        .collect())
+}
+
+fn load(args: &Args, boot_id: &str, period: NaiveDate, ifaces: &[(String, u64, u64)]) -> Counter {
+    match fs::read(&args.traffic_file) {
+        Ok(data) => match serde_json::from_slice::<Counter>(&data) {
+            Ok(o) => return o,
+            Err(err) => error!("invalid traffic file `{}` => {err}, start over", args.traffic_file),
+        },
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => error!("read traffic file `{}` => {err}, start over", args.traffic_file),
+    }
+    let mut o = Counter::new(boot_id, period, ifaces);
+    match import_vnstat(args) {
+        Ok((rx, tx, m_rx, m_tx)) => {
+            info!("traffic imported from vnstat => rx {rx}, tx {tx}, month rx {m_rx}, month tx {m_tx}");
+            (o.rx, o.tx) = (rx, tx);
+            (o.period_rx, o.period_tx) = (rx.saturating_sub(m_rx), tx.saturating_sub(m_tx));
+        }
+        Err(err) => info!("traffic counted from now on, vnstat import => {err}"),
+    }
+    o
+}
+
+/// (total rx, total tx, month rx, month tx)
 pub fn get_traffic(args: &Args) -> Result<(u64, u64, u64, u64)> {
-    if args.vnstat_mr == 1 {
-        // !
-        let a = Command::new("/usr/bin/vnstat")
-            .args(["--json", "m"])
-            .output()
-            .expect("failed to execute vnstat")
-            .stdout;
-        let b = str::from_utf8(&a)?;
-        let j: VnstatJson = serde_json::from_str(b).unwrap_or_else(|e| {
-            error!("{e:?}");
-            panic!("invalid vnstat json `{b}")
-        });
-        Ok(calc_traffic(&j, false, args))
-    } else if args.vnstat_mr > 1 && args.vnstat_mr <= 28 {
-        // month rotate
-        let a = Command::new("/usr/bin/vnstat")
-            .args(["--json", "d", "32"])
-            .output()
-            .expect("failed to execute vnstat")
-            .stdout;
-        let b = str::from_utf8(&a)?;
-        let j: VnstatJson = serde_json::from_str(b).unwrap_or_else(|e| {
-            error!("{e:?}");
-            panic!("invalid vnstat json `{b}")
-        });
-        Ok(calc_traffic(&j, true, args))
-    } else {
-        panic!("invalid vnstat month rotate => `{}", args.vnstat_mr);
+    if !(1..=28).contains(&args.vnstat_mr) {
+        bail!("invalid vnstat month rotate => `{}", args.vnstat_mr);
     }
+    let boot_id = fs::read_to_string("/proc/sys/kernel/random/boot_id")
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default();
+    let period = period_start(Local::now().date_naive(), args.vnstat_mr);
+    let ifaces = read_ifaces(args)?;
+
+    let mut guard = G_COUNTER.lock().unwrap();
+    let o = guard.get_or_insert_with(|| load(args, &boot_id, period, &ifaces));
+    let rotated = o.update(&boot_id, period, &ifaces);
+    if rotated || o.saved_at.is_none_or(|t| t.elapsed() >= SAVE_PERIOD) {
+        if let Err(err) = o.save(&args.traffic_file) {
+            error!("save traffic file `{}` => {err}", args.traffic_file);
+        }
+        // failures retry with the next period too
+        o.saved_at = Some(Instant::now());
+    }
+    Ok((o.rx, o.tx, o.rx - o.period_rx, o.tx - o.period_tx))
 }
 
 #[allow(unused)]
 #[cfg(test)]
 mod tests {
+    use super::*;
     use crate::vnstat::VnstatJson;
 
+    #[test]
+    fn test_period_start() {
+        let d = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        assert_eq!(period_start(d("2024-03-15"), 1), d("2024-03-01"));
+        assert_eq!(period_start(d("2024-03-15"), 15), d("2024-03-15"));
+        assert_eq!(period_start(d("2024-03-14"), 15), d("2024-02-15"));
+        assert_eq!(period_start(d("2024-01-05"), 28), d("2023-12-28"));
+    }
+
+    #[test]
+    fn test_counter() {
+        let d = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let iface = |name: &str, rx: u64, tx: u64| (name.to_string(), rx, tx);
+        let mut o = Counter::new("b1", d("2024-03-01"), &[iface("eth0", 1000, 500)]);
+        assert_eq!((o.rx, o.tx, o.period_rx), (1000, 500, 1000));
+
+        assert!(!o.update("b1", d("2024-03-01"), &[iface("eth0", 1500, 700), iface("wg0", 10, 20)]));
+        assert_eq!((o.rx - o.period_rx, o.tx - o.period_tx), (510, 220));
+        // reboot, counters start over
+        assert!(!o.update("b2", d("2024-03-01"), &[iface("eth0", 100, 50)]));
+        assert_eq!((o.rx, o.tx), (1610, 770));
+        // interface counters reset
+        o.update("b2", d("2024-03-01"), &[iface("eth0", 30, 10)]);
+        assert_eq!((o.rx, o.tx), (1640, 780));
+        // new month
+        assert!(o.update("b2", d("2024-04-01"), &[iface("eth0", 40, 20)]));
+        assert_eq!((o.rx, o.rx - o.period_rx), (1650, 0));
+
+        let json = serde_json::to_string(&o).unwrap();
+        assert_eq!(serde_json::from_str::<Counter>(&json).unwrap(), o);
+    }
+
     #[test]
     fn test_json_v1_m() {
         let json_v1: &str = r#"{"vnstatversion":"1.15","jsonversion":"1","interfaces":[{"id":"eth0","nick":"eth0","created":{"date":{"year":2022,"month":11,"day":8}},"updated":{"date":{"year":2022,"month":11,"day":8},"time":{"hour":17,"minutes":9}},"traffic":{"total":{"rx":376720,"tx":3780},"months":[{"id":0,"date":{"year":2022,"month":11},"rx":376720,"tx":3780}]}}]}"#;
@@ -223,6 +375,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn test_json_v1_d31() {
         // v1.15 版本不支持参数 --json d 31
         assert!(true);
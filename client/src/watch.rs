@@ -0,0 +1,297 @@
+#![deny(warnings)]
+// `--watch`, processes & systemd units reported with every sample
+use anyhow::{bail, Result};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::process::Command;
+use std::sync::{LazyLock, Mutex};
+use std::thread;
+use std::time::Duration;
+use sysinfo::{Pid, Process, ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
+
+use stat_common::server_status::{ServiceInfo, StatRequest};
+
+const SAMPLE_PERIOD: Duration = Duration::from_secs(3);
+
+static G_SERVICES: LazyLock<Mutex<Vec<ServiceInfo>>> = LazyLock::new(Mutex::default);
+
+#[derive(Debug)]
+enum Target {
+    // process name
+    Process(String),
+    // full command line
+    Regex(Regex),
+    Systemd(String),
+}
+
+#[derive(Debug)]
+struct Watch {
+    name: String,
+    target: Target,
+}
+
+impl Watch {
+    /// `[name=]nginx`, `[name=]re:<cmdline regex>`, `[name=]systemd:<unit>`
+    fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        let (name, target) = match spec.split_once('=') {
+            Some((name, target))
+                if !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) =>
+            {
+                (Some(name), target)
+            }
+            _ => (None, spec),
+        };
+        let target = if let Some(re) = target.strip_prefix("re:") {
+            Target::Regex(Regex::new(re)?)
+        } else if let Some(unit) = target.strip_prefix("systemd:") {
+            if unit.contains('.') {
+                Target::Systemd(unit.to_string())
+            } else {
+                Target::Systemd(format!("{unit}.service"))
+            }
+        } else {
+            Target::Process(target.to_string())
+        };
+        if target.is_empty() {
+            bail!("empty watch target `{spec}`");
+        }
+        let name = name.map_or_else(
+            || match &target {
+                Target::Process(s) | Target::Systemd(s) => s.clone(),
+                Target::Regex(re) => re.as_str().to_string(),
+            },
+            ToString::to_string,
+        );
+        Ok(Self { name, target })
+    }
+
+    fn kind(&self) -> &'static str {
+        match self.target {
+            Target::Process(_) | Target::Regex(_) => "process",
+            Target::Systemd(_) => "systemd",
+        }
+    }
+
+    fn matches(&self, p: &Process) -> bool {
+        match &self.target {
+            Target::Process(name) => p.name().eq(name.as_str()),
+            Target::Regex(re) => {
+                let cmd = p
+                    .cmd()
+                    .iter()
+                    .map(|s| s.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                re.is_match(&cmd)
+            }
+            Target::Systemd(_) => false,
+        }
+    }
+}
+
+impl Target {
+    fn is_empty(&self) -> bool {
+        match self {
+            Self::Process(s) | Self::Systemd(s) => s.is_empty() || s == ".service",
+            Self::Regex(re) => re.as_str().is_empty(),
+        }
+    }
+}
+
+// `systemctl show` key=value lines
+fn parse_show(content: &str) -> HashMap<&str, &str> {
+    content.lines().filter_map(|l| l.split_once('=')).collect()
+}
+
+/// (state, running, pids in the unit cgroup)
+fn systemd_unit(unit: &str) -> Result<(String, bool, Vec<Pid>)> {
+    let out = Command::new("systemctl")
+        .args([
+            "show",
+            unit,
+            "-p",
+            "ActiveState",
+            "-p",
+            "SubState",
+            "-p",
+            "ControlGroup",
+            "-p",
+            "MainPID",
+        ])
+        .output()?;
+    if !out.status.success() {
+        bail!(
+            "systemctl show {unit} => {}",
+            String::from_utf8_lossy(&out.stderr).lines().next().unwrap_or_default()
+        );
+    }
+    let content = String::from_utf8_lossy(&out.stdout);
+    let props = parse_show(&content);
+    let active = props.get("ActiveState").copied().unwrap_or("unknown");
+    let sub = props.get("SubState").copied().unwrap_or_default();
+
+    let mut pids = Vec::new();
+    if let Some(cg) = props.get("ControlGroup").filter(|s| !s.is_empty()) {
+        // cgroup v2, then the v1 systemd hierarchy
+        for root in ["/sys/fs/cgroup", "/sys/fs/cgroup/systemd"] {
+            if let Ok(procs) = fs::read_to_string(format!("{root}{cg}/cgroup.procs")) {
+                pids = procs
+                    .lines()
+                    .filter_map(|s| s.trim().parse::<usize>().ok())
+                    .map(Pid::from)
+                    .collect();
+                break;
+            }
+        }
+    }
+    if pids.is_empty() {
+        if let Some(pid) = props
+            .get("MainPID")
+            .and_then(|s| s.parse::<usize>().ok())
+            .filter(|v| *v > 0)
+        {
+            pids.push(Pid::from(pid));
+        }
+    }
+    Ok((format!("{active}/{sub}"), active == "active", pids))
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn collect(sys: &System, watches: &[Watch]) -> Vec<ServiceInfo> {
+    let processes = sys.processes();
+    watches
+        .iter()
+        .map(|w| {
+            let mut o = ServiceInfo {
+                name: w.name.clone(),
+                kind: w.kind().to_string(),
+                ..Default::default()
+            };
+            let pids = match &w.target {
+                Target::Systemd(unit) => match systemd_unit(unit) {
+                    Ok((state, running, pids)) => {
+                        o.state = state;
+                        o.running = running;
+                        pids
+                    }
+                    Err(err) => {
+                        o.state = err.to_string();
+                        Vec::new()
+                    }
+                },
+                _ => {
+                    let pids = processes
+                        .values()
+                        .filter(|p| p.thread_kind().is_none() && w.matches(p))
+                        .map(Process::pid)
+                        .collect::<Vec<_>>();
+                    o.running = !pids.is_empty();
+                    o.state = if o.running { "running" } else { "stopped" }.to_string();
+                    pids
+                }
+            };
+            for p in pids.iter().filter_map(|pid| processes.get(pid)) {
+                o.cpu += f64::from(p.cpu_usage());
+                o.rss += p.memory();
+            }
+            o.cpu = (o.cpu * 10.0).round() / 10.0;
+            o.pids = pids.len() as u32;
+            o
+        })
+        .collect()
+}
+
+pub fn start_watch_collect_t() {
+    thread::spawn(|| {
+        let mut sys = System::new();
+        let mut specs: Vec<String> = Vec::new();
+        let mut watches: Vec<Watch> = Vec::new();
+        loop {
+            let args = crate::remote::args();
+            if args.watch != specs {
+                specs.clone_from(&args.watch);
+                watches = specs
+                    .iter()
+                    .filter(|s| !s.trim().is_empty())
+                    .filter_map(|s| {
+                        Watch::parse(s)
+                            .map_err(|err| error!("invalid watch `{s}` => {err}"))
+                            .ok()
+                    })
+                    .collect();
+            }
+            let services = if watches.is_empty() {
+                Vec::new()
+            } else {
+                sys.refresh_processes_specifics(
+                    ProcessesToUpdate::All,
+                    true,
+                    ProcessRefreshKind::nothing()
+                        .with_cpu()
+                        .with_memory()
+                        .with_cmd(UpdateKind::OnlyIfNotSet)
+                        .without_tasks(),
+                );
+                collect(&sys, &watches)
+            };
+            if let Ok(mut o) = G_SERVICES.lock() {
+                *o = services;
+            }
+            thread::sleep(SAMPLE_PERIOD);
+        }
+    });
+}
+
+pub fn sample(stat: &mut StatRequest) {
+    if let Ok(o) = G_SERVICES.lock() {
+        stat.services.clone_from(&o);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let o = Watch::parse("nginx").unwrap();
+        assert_eq!((o.name.as_str(), o.kind()), ("nginx", "process"));
+        let o = Watch::parse("web=systemd:nginx").unwrap();
+        assert!(matches!(&o.target, Target::Systemd(u) if u == "nginx.service"));
+        assert_eq!(o.name, "web");
+        let o = Watch::parse("fpm=re:php-fpm: pool (www|api)").unwrap();
+        assert!(matches!(&o.target, Target::Regex(re) if re.is_match("php-fpm: pool api")));
+        // `=` inside the regex is not a name
+        assert_eq!(Watch::parse("re:--port=80").unwrap().name, "--port=80");
+        assert!(Watch::parse("re:(").is_err());
+        assert!(Watch::parse("systemd:").is_err());
+
+        let props = parse_show("ActiveState=failed\nSubState=failed\nControlGroup=\nMainPID=0\n");
+        assert_eq!((props["ActiveState"], props["ControlGroup"]), ("failed", ""));
+    }
+
+    #[test]
+    fn test_collect() {
+        let mut sys = System::new();
+        sys.refresh_processes_specifics(
+            ProcessesToUpdate::All,
+            true,
+            ProcessRefreshKind::nothing()
+                .with_memory()
+                .with_cmd(UpdateKind::OnlyIfNotSet),
+        );
+        let me = sys.process(sysinfo::get_current_pid().unwrap()).unwrap();
+        let watches = [
+            Watch::parse(&format!("me={}", me.name().to_string_lossy())).unwrap(),
+            Watch::parse("none=no-such-process-xyz").unwrap(),
+        ];
+        let o = collect(&sys, &watches);
+        assert!(o[0].running && o[0].pids >= 1 && o[0].rss > 0);
+        assert!(!o[1].running && o[1].pids == 0 && o[1].state == "stopped");
+    }
+}
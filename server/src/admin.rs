@@ -0,0 +1,402 @@
+#![deny(warnings)]
+// admin api, hosts / hosts_group CRUD & tokens, persisted to `hosts_file`
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use crate::config::{self, Config, Host, HostGroup, Sidecar};
+use crate::credential::{self, Token};
+use crate::jwt;
+use crate::G_CONFIG;
+
+// runtime fields, not persisted
+const RUNTIME_KEYS: [&str; 3] = ["last_network_in", "last_network_out", "latest_ts"];
+
+static LOCK: Mutex<()> = Mutex::new(());
+
+type ApiResult = Result<Value, (StatusCode, String)>;
+
+pub trait Item: Clone + Serialize + DeserializeOwned + Send + 'static {
+    const KEY: &'static str;
+    fn key(&self) -> &str;
+    fn from_config(cfg: &Config) -> &Vec<Self>;
+    // name taken in the running config, including `[[monitor]]` pseudo hosts
+    fn exists(cfg: &Config, key: &str) -> bool;
+    fn from_sidecar(sidecar: &mut Sidecar) -> &mut Vec<Self>;
+    fn password(&self) -> &str;
+    fn set_password(&mut self, pass: String);
+    fn tokens_mut(&mut self) -> &mut Vec<Token>;
+}
+
+impl Item for Host {
+    const KEY: &'static str = "name";
+    fn key(&self) -> &str {
+        &self.name
+    }
+    fn from_config(cfg: &Config) -> &Vec<Self> {
+        &cfg.hosts
+    }
+    fn exists(cfg: &Config, key: &str) -> bool {
+        cfg.hosts_map.contains_key(key)
+    }
+    fn from_sidecar(sidecar: &mut Sidecar) -> &mut Vec<Self> {
+        &mut sidecar.hosts
+    }
+    fn password(&self) -> &str {
+        &self.password
+    }
+    fn set_password(&mut self, pass: String) {
+        self.password = pass;
+    }
+    fn tokens_mut(&mut self) -> &mut Vec<Token> {
+        &mut self.tokens
+    }
+}
+
+impl Item for HostGroup {
+    const KEY: &'static str = "gid";
+    fn key(&self) -> &str {
+        &self.gid
+    }
+    fn from_config(cfg: &Config) -> &Vec<Self> {
+        &cfg.hosts_group
+    }
+    fn exists(cfg: &Config, key: &str) -> bool {
+        cfg.hosts_group_map.contains_key(key)
+    }
+    fn from_sidecar(sidecar: &mut Sidecar) -> &mut Vec<Self> {
+        &mut sidecar.hosts_group
+    }
+    fn password(&self) -> &str {
+        &self.password
+    }
+    fn set_password(&mut self, pass: String) {
+        self.password = pass;
+    }
+    fn tokens_mut(&mut self) -> &mut Vec<Token> {
+        &mut self.tokens
+    }
+}
+
+fn bad_request<E: ToString>(err: E) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, err.to_string())
+}
+
+fn gen_password() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+fn to_toml(sidecar: &Sidecar) -> anyhow::Result<String> {
+    let mut table = toml::Table::try_from(sidecar)?;
+    for (_, list) in table.iter_mut() {
+        if let Some(items) = list.as_array_mut() {
+            for item in items.iter_mut().filter_map(toml::Value::as_table_mut) {
+                for k in RUNTIME_KEYS {
+                    item.remove(k);
+                }
+            }
+        }
+    }
+    Ok(toml::to_string(&table)?)
+}
+
+/// json merge, `key` can't be changed
+fn patch<T: Item>(item: &T, data: Value) -> Result<T, (StatusCode, String)> {
+    let Value::Object(data) = data else {
+        return Err(bad_request("invalid json object"));
+    };
+    let mut v = serde_json::to_value(item).map_err(bad_request)?;
+    for (k, val) in data {
+        if k == T::KEY {
+            if val.as_str() != Some(item.key()) {
+                return Err(bad_request(format!("`{}` can't be changed", T::KEY)));
+            }
+            continue;
+        }
+        v[k] = val;
+    }
+    serde_json::from_value(v).map_err(bad_request)
+}
+
+/// only the hash is saved, returns the plaintext password to show it once
+fn hash_password<T: Item>(item: &mut T) -> Result<Option<String>, (StatusCode, String)> {
+    if credential::is_hashed(item.password()) {
+        return Ok(None);
+    }
+    let password = item.password().to_string();
+    let hash =
+        credential::hash_password(&password).map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    item.set_password(hash);
+    Ok(Some(password))
+}
+
+/// password & token hashes are never returned, token ids are kept to revoke them
+fn redact<T: Item>(item: &T) -> ApiResult {
+    let mut v = serde_json::to_value(item).map_err(bad_request)?;
+    if let Some(o) = v.as_object_mut() {
+        o.remove("password");
+        if let Some(tokens) = o.get_mut("tokens").and_then(Value::as_array_mut) {
+            for token in tokens.iter_mut().filter_map(Value::as_object_mut) {
+                token.remove("hash");
+            }
+        }
+    }
+    Ok(v)
+}
+
+fn to_value<T: Item>(item: &T, password: Option<String>) -> ApiResult {
+    let mut v = redact(item)?;
+    if let Some(password) = password {
+        v["password"] = json!(password);
+    }
+    Ok(v)
+}
+
+/// sidecar entry, or the config.toml one to override
+fn find<T: Item>(cfg: &Config, sidecar: &mut Sidecar, key: &str) -> Result<T, (StatusCode, String)> {
+    T::from_sidecar(sidecar)
+        .iter()
+        .chain(T::from_config(cfg))
+        .find(|o| o.key() == key)
+        .cloned()
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("`{key}` not found")))
+}
+
+fn save<T: Item>(sidecar: &mut Sidecar, item: T) {
+    let list = T::from_sidecar(sidecar);
+    match list.iter_mut().find(|o| o.key() == item.key()) {
+        Some(o) => *o = item,
+        None => list.push(item),
+    }
+}
+
+/// load sidecar => modify => save => reload, rollback on reload error
+fn apply_blocking<F>(f: F) -> ApiResult
+where
+    F: FnOnce(&Config, &mut Sidecar) -> ApiResult,
+{
+    let _guard = LOCK.lock().unwrap();
+    let cfg = G_CONFIG.get().unwrap();
+    if cfg.hosts_file.is_empty() {
+        return Err((StatusCode::NOT_IMPLEMENTED, "hosts_file is not configured".to_string()));
+    }
+
+    let path = cfg.hosts_file.as_str();
+    let mut sidecar = config::load_sidecar(path).map_err(bad_request)?;
+    let resp = f(&cfg, &mut sidecar)?;
+
+    let backup = fs::read_to_string(path).ok();
+    let content = to_toml(&sidecar).map_err(bad_request)?;
+    fs::write(path, content).map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    if let Err(err) = config::reload() {
+        error!("admin api reload fail => {err:?}");
+        match backup {
+            Some(o) => fs::write(path, o),
+            None => fs::remove_file(path),
+        }
+        .unwrap_or_else(|err| error!("restore `{path}` fail => {err:?}"));
+        return Err(bad_request(err));
+    }
+    Ok(resp)
+}
+
+/// hashing, file io & reload are blocking, keep them off the async workers
+async fn apply<F>(f: F) -> Response
+where
+    F: FnOnce(&Config, &mut Sidecar) -> ApiResult + Send + 'static,
+{
+    to_response(
+        tokio::task::spawn_blocking(move || apply_blocking(f))
+            .await
+            .unwrap_or_else(|err| Err((StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))),
+    )
+}
+
+fn to_response(res: ApiResult) -> Response {
+    match res {
+        Ok(data) => Json(json!({ "code": 0, "message": "ok", "data": data })).into_response(),
+        Err((status, message)) => (status, Json(json!({ "code": 1, "message": message }))).into_response(),
+    }
+}
+
+pub async fn list<T: Item>(_claims: jwt::Claims) -> Response {
+    let cfg = G_CONFIG.get().unwrap();
+    let managed = config::load_sidecar(&cfg.hosts_file)
+        .map(|mut o| T::from_sidecar(&mut o).iter().map(|i| i.key().to_string()).collect())
+        .unwrap_or_else(|_| Vec::new());
+    let items: Result<Vec<Value>, _> = T::from_config(&cfg).iter().map(redact).collect();
+    to_response(items.map(|items| json!({ "items": items, "managed": managed })))
+}
+
+pub async fn create<T: Item>(_claims: jwt::Claims, Json(mut data): Json<Value>) -> Response {
+    if let Some(o) = data.as_object_mut() {
+        o.entry("password").or_insert_with(|| json!(""));
+    }
+    apply(move |cfg, sidecar| {
+        let mut item: T = serde_json::from_value(data).map_err(bad_request)?;
+        if item.key().is_empty() {
+            return Err(bad_request(format!("`{}` is required", T::KEY)));
+        }
+        if T::exists(cfg, item.key()) {
+            return Err((StatusCode::CONFLICT, format!("`{}` already exists", item.key())));
+        }
+        if item.password().is_empty() {
+            item.set_password(gen_password());
+        }
+        let password = hash_password(&mut item)?;
+        let v = to_value(&item, password)?;
+        T::from_sidecar(sidecar).push(item);
+        Ok(v)
+    })
+    .await
+}
+
+pub async fn update<T: Item>(_claims: jwt::Claims, Path(key): Path<String>, Json(data): Json<Value>) -> Response {
+    apply(move |cfg, sidecar| {
+        let mut item = patch(&find::<T>(cfg, sidecar, &key)?, data)?;
+        let password = hash_password(&mut item)?;
+        let v = to_value(&item, password)?;
+        save(sidecar, item);
+        Ok(v)
+    })
+    .await
+}
+
+pub async fn delete<T: Item>(_claims: jwt::Claims, Path(key): Path<String>) -> Response {
+    apply(move |_, sidecar| {
+        let list = T::from_sidecar(sidecar);
+        let n = list.len();
+        list.retain(|o| o.key() != key);
+        if n == list.len() {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("`{key}` is not managed by admin api, edit config.toml instead"),
+            ));
+        }
+        Ok(Value::Null)
+    })
+    .await
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PasswordPayload {
+    #[serde(default = "Default::default")]
+    pub password: String,
+}
+
+pub async fn rotate_password<T: Item>(
+    claims: jwt::Claims,
+    Path(key): Path<String>,
+    payload: Option<Json<PasswordPayload>>,
+) -> Response {
+    let mut password = payload.map(|Json(o)| o.password).unwrap_or_default();
+    if password.is_empty() {
+        password = gen_password();
+    }
+    update::<T>(claims, Path(key), Json(json!({ "password": password }))).await
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TokenPayload {
+    #[serde(default = "Default::default")]
+    pub comment: String,
+}
+
+/// the token is only shown once, use it as the client password
+pub async fn create_token<T: Item>(
+    _claims: jwt::Claims,
+    Path(key): Path<String>,
+    payload: Option<Json<TokenPayload>>,
+) -> Response {
+    let comment = payload.map(|Json(o)| o.comment).unwrap_or_default();
+    apply(move |cfg, sidecar| {
+        let mut item = find::<T>(cfg, sidecar, &key)?;
+        let (token, o) = credential::new_token(&comment);
+        item.tokens_mut().push(o.clone());
+        save(sidecar, item);
+        Ok(json!({ "id": o.id, "token": token, "comment": o.comment, "created": o.created }))
+    })
+    .await
+}
+
+pub async fn revoke_token<T: Item>(_claims: jwt::Claims, Path((key, id)): Path<(String, String)>) -> Response {
+    apply(move |cfg, sidecar| {
+        let mut item = find::<T>(cfg, sidecar, &key)?;
+        let tokens = item.tokens_mut();
+        let n = tokens.len();
+        tokens.retain(|o| o.id != id);
+        if n == tokens.len() {
+            return Err((StatusCode::NOT_FOUND, format!("token `{id}` not found")));
+        }
+        save(sidecar, item);
+        Ok(Value::Null)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patch() {
+        let host = Host {
+            name: "h1".to_string(),
+            password: "p1".to_string(),
+            ..Default::default()
+        };
+        let o = patch(&host, json!({"name": "h1", "disabled": true, "alias": "n1"})).unwrap();
+        assert!(o.disabled);
+        assert_eq!((o.alias.as_str(), o.password.as_str()), ("n1", "p1"));
+
+        assert!(patch(&host, json!({"name": "h2"})).is_err());
+        assert!(patch(&host, json!({"notify": "x"})).is_err());
+        assert!(patch(&host, json!([])).is_err());
+    }
+
+    #[test]
+    fn test_redact() {
+        let host = Host {
+            name: "h1".to_string(),
+            password: "p1".to_string(),
+            tokens: vec![Token {
+                id: "t1".to_string(),
+                hash: "x".repeat(64),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let v = redact(&host).unwrap();
+        assert!(v.get("password").is_none());
+        assert_eq!(v["tokens"][0]["id"], "t1");
+        assert!(v["tokens"][0].get("hash").is_none());
+        assert_eq!(to_value(&host, Some("p2".to_string())).unwrap()["password"], "p2");
+    }
+
+    #[test]
+    fn test_sidecar_toml() {
+        let sidecar = Sidecar {
+            hosts: vec![Host {
+                name: "h1".to_string(),
+                password: "p1".to_string(),
+                last_network_in: 100,
+                latest_ts: 100,
+                ..Default::default()
+            }],
+            hosts_group: Vec::new(),
+        };
+        let s = to_toml(&sidecar).unwrap();
+        assert!(!s.contains("latest_ts") && !s.contains("last_network_in"));
+        let o = toml::from_str::<Sidecar>(&s).unwrap();
+        assert_eq!(o.hosts[0].name, "h1");
+        assert_eq!(o.hosts[0].password, "p1");
+    }
+}
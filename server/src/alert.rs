@@ -0,0 +1,521 @@
+#![deny(warnings)]
+use anyhow::Result;
+use rhai::serde::to_dynamic;
+use rhai::{Array, Engine, Map, Scope, AST};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::notifier::Event;
+use crate::payload::HostStat;
+
+fn default_as_true() -> bool {
+    true
+}
+fn default_severity() -> String {
+    "warning".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Rule {
+    pub name: String,
+    // rhai expression, eg. `host.cpu > 90`
+    pub expr: String,
+    // seconds the expr must hold before firing
+    #[serde(rename = "for", default = "Default::default")]
+    pub r#for: u64,
+    #[serde(default = "default_severity")]
+    pub severity: String,
+    #[serde(default = "Default::default")]
+    pub summary: String,
+    #[serde(default = "default_as_true")]
+    pub enabled: bool,
+    #[serde(default = "default_as_true")]
+    pub recovery: bool,
+    // targets, empty means all
+    #[serde(default = "Default::default")]
+    pub hosts: Vec<String>,
+    #[serde(default = "Default::default")]
+    pub gids: Vec<String>,
+    #[serde(default = "Default::default")]
+    pub labels: Vec<String>,
+}
+
+/// `os=pi` matches the label exactly, `os` only checks the key
+fn match_labels(labels: &str, selectors: &[String]) -> bool {
+    selectors.iter().all(|sel| {
+        labels.split(';').map(str::trim).any(|kv| {
+            if sel.contains('=') {
+                kv.eq(sel)
+            } else {
+                kv.split('=').next().is_some_and(|k| k.eq(sel))
+            }
+        })
+    })
+}
+
+/// empty selectors match all hosts
+pub fn match_target(stat: &HostStat, hosts: &[String], gids: &[String], labels: &[String]) -> bool {
+    match_host(&stat.name, &stat.gid, &stat.labels, hosts, gids, labels)
+}
+
+pub fn match_host(
+    name: &str,
+    gid: &str,
+    host_labels: &str,
+    hosts: &[String],
+    gids: &[String],
+    labels: &[String],
+) -> bool {
+    (hosts.is_empty() || hosts.iter().any(|o| o == name))
+        && (gids.is_empty() || gids.iter().any(|o| o == gid))
+        && match_labels(host_labels, labels)
+}
+
+impl Rule {
+    pub fn matches(&self, stat: &HostStat) -> bool {
+        match_target(stat, &self.hosts, &self.gids, &self.labels)
+    }
+
+    fn alert(&self, since: u64, now: u64) -> Alert {
+        Alert {
+            rule: self.name.clone(),
+            severity: self.severity.clone(),
+            summary: self.summary.clone(),
+            expr: self.expr.clone(),
+            since,
+            ts: now,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub rule: String,
+    pub severity: String,
+    pub summary: String,
+    pub expr: String,
+    // pending since
+    pub since: u64,
+    pub ts: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    since: Option<u64>,
+    firing: bool,
+}
+
+/// max of a numeric field over an array of objects, 0 if empty
+fn max_of(items: Array, field: &str) -> f64 {
+    items
+        .into_iter()
+        .filter_map(|o| {
+            let v = o.try_cast::<Map>()?.get(field)?.clone();
+            #[allow(clippy::cast_precision_loss)]
+            v.as_float().ok().or_else(|| v.as_int().ok().map(|i| i as f64))
+        })
+        .fold(0.0, f64::max)
+}
+
+/// max of `used * 100 / total` over an array of objects, skips zero totals
+fn max_pct(items: Array, used: &str, total: &str) -> f64 {
+    #[allow(clippy::cast_precision_loss)]
+    let num = |o: &Map, k: &str| {
+        let v = o.get(k)?;
+        v.as_float().ok().or_else(|| v.as_int().ok().map(|i| i as f64))
+    };
+    items
+        .into_iter()
+        .filter_map(|o| {
+            let o = o.try_cast::<Map>()?;
+            let (used, total) = (num(&o, used)?, num(&o, total)?);
+            (total > 0.0).then(|| used * 100.0 / total)
+        })
+        .fold(0.0, f64::max)
+}
+
+#[derive(Default)]
+pub struct AlertEngine {
+    engine: Engine,
+    rules: Vec<(Rule, AST)>,
+    // (rule, host)
+    states: HashMap<(String, String), State>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: &[Rule]) -> Result<Self> {
+        let mut engine = Engine::new();
+        // no closures in rules, `host.disk_io.max_of("util") > 90.0`
+        engine.register_fn("max_of", max_of);
+        engine.register_fn("max_pct", max_pct);
+        let mut o = Self {
+            engine,
+            rules: Vec::new(),
+            states: HashMap::new(),
+        };
+        for rule in rules {
+            if !rule.enabled {
+                continue;
+            }
+            let ast = o
+                .engine
+                .compile_expression(&rule.expr)
+                .map_err(|err| anyhow::anyhow!("invalid alert_rule `{}` expr => {err}", rule.name))?;
+            o.rules.push((rule.clone(), ast));
+        }
+        Ok(o)
+    }
+
+    /// swap rules, keep the state of rules with the same name
+    pub fn set_rules(&mut self, rules: &[Rule]) -> Result<()> {
+        self.rules = Self::new(rules)?.rules;
+        let rules = &self.rules;
+        self.states
+            .retain(|(name, _), _| rules.iter().any(|(r, _)| r.name.eq(name)));
+        Ok(())
+    }
+
+    pub fn eval(&mut self, stat: &HostStat, now: u64) -> Vec<Event> {
+        let mut events = Vec::new();
+        if self.rules.is_empty() {
+            return events;
+        }
+
+        let host = match to_dynamic(stat.detail()) {
+            Ok(v) => v,
+            Err(err) => {
+                error!("alert to_dynamic error => {err:?}");
+                return events;
+            }
+        };
+
+        for (rule, ast) in &self.rules {
+            if !rule.matches(stat) {
+                continue;
+            }
+
+            let mut scope = Scope::new();
+            scope.push_constant("host", host.clone());
+            let hit = match self.engine.eval_ast_with_scope::<bool>(&mut scope, ast) {
+                Ok(v) => v,
+                Err(err) => {
+                    warn!("alert_rule `{}` eval error => {err:?}", rule.name);
+                    continue;
+                }
+            };
+
+            let state = self.states.entry((rule.name.clone(), stat.name.clone())).or_default();
+            if hit {
+                let since = *state.since.get_or_insert(now);
+                if !state.firing && since + rule.r#for <= now {
+                    state.firing = true;
+                    events.push(Event::AlertFiring(rule.alert(since, now)));
+                }
+            } else {
+                if state.firing && rule.recovery {
+                    events.push(Event::AlertResolved(rule.alert(state.since.unwrap_or(now), now)));
+                }
+                state.firing = false;
+                state.since = None;
+            }
+        }
+
+        events
+    }
+
+    /// resolve alerts of hosts which stopped reporting, drop the state of removed hosts
+    pub fn expire(
+        &mut self,
+        stats: &HashMap<String, Arc<HostStat>>,
+        now: u64,
+        offline_threshold: u64,
+    ) -> Vec<(Event, Arc<HostStat>)> {
+        let mut events = Vec::new();
+        let rules = &self.rules;
+        self.states.retain(|(rule_name, host), state| {
+            let Some(stat) = stats.get(host) else {
+                return false;
+            };
+            if stat.latest_ts + offline_threshold >= now {
+                return true;
+            }
+            if state.firing && stat.notify {
+                if let Some((rule, _)) = rules.iter().find(|(r, _)| r.name.eq(rule_name)) {
+                    if rule.recovery {
+                        let alert = rule.alert(state.since.unwrap_or(now), now);
+                        events.push((Event::AlertResolved(alert), Arc::clone(stat)));
+                    }
+                }
+            }
+            false
+        });
+        events
+    }
+}
+
+/// AlertFiring when a watched service or a running container stops, AlertResolved when it runs again
+pub fn service_events(pre: &HostStat, cur: &HostStat) -> Vec<Event> {
+    let alert = |rule: String, summary: String| Alert {
+        rule,
+        severity: "critical".to_string(),
+        summary,
+        expr: String::new(),
+        since: cur.latest_ts,
+        ts: cur.latest_ts,
+    };
+    let services = cur.services.iter().filter_map(|o| {
+        let was_running = pre.services.iter().find(|p| p.name == o.name).map(|p| p.running);
+        let alert = || {
+            alert(
+                format!("service:{}", o.name),
+                format!("{} `{}` {}", o.kind, o.name, o.state),
+            )
+        };
+        match (was_running, o.running) {
+            // newly watched services only alert when down
+            (Some(true) | None, false) => Some(Event::AlertFiring(alert())),
+            (Some(false), true) => Some(Event::AlertResolved(alert())),
+            _ => None,
+        }
+    });
+    let containers = cur.containers.iter().filter_map(|o| {
+        let was_running = pre.containers.iter().find(|p| p.name == o.name).map(|p| p.running);
+        let alert = || {
+            alert(
+                format!("container:{}", o.name),
+                format!("container `{}` {}", o.name, o.state),
+            )
+        };
+        match (was_running, o.running) {
+            // `docker ps -a` lists long exited ones, only alert on a stop
+            (Some(true), false) => Some(Event::AlertFiring(alert())),
+            (Some(false), true) => Some(Event::AlertResolved(alert())),
+            _ => None,
+        }
+    });
+    // `docker rm`, `docker run --rm` or compose down while running
+    let removed = pre
+        .containers
+        .iter()
+        .filter(|p| p.running && !cur.containers.iter().any(|o| o.name == p.name))
+        .map(|p| {
+            Event::AlertFiring(alert(
+                format!("container:{}", p.name),
+                format!("container `{}` removed", p.name),
+            ))
+        });
+    services.chain(containers).chain(removed).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(expr: &str, r#for: u64) -> Rule {
+        toml::from_str::<Rule>(&format!("name = \"r1\"\nexpr = \"{expr}\"\nfor = {}", r#for)).unwrap()
+    }
+
+    fn stat(cpu: f64) -> HostStat {
+        HostStat {
+            name: "h1".to_string(),
+            cpu,
+            labels: "os=pi;ndd=2022/11/25".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_for_and_recovery() {
+        let mut engine = AlertEngine::new(&[rule("host.cpu > 90.0", 60)]).unwrap();
+        assert!(engine.eval(&stat(95.0), 100).is_empty());
+        assert!(engine.eval(&stat(95.0), 159).is_empty());
+
+        let events = engine.eval(&stat(95.0), 160);
+        assert!(matches!(&events[..], [Event::AlertFiring(a)] if a.since == 100));
+        // keeps firing, no duplicate
+        assert!(engine.eval(&stat(99.0), 200).is_empty());
+
+        let events = engine.eval(&stat(10.0), 210);
+        assert!(matches!(&events[..], [Event::AlertResolved(a)] if a.rule == "r1"));
+        assert!(engine.eval(&stat(10.0), 220).is_empty());
+    }
+
+    #[test]
+    fn test_expire() {
+        let mut engine = AlertEngine::new(&[rule("host.cpu > 90.0", 0)]).unwrap();
+        let mut o = stat(95.0);
+        o.notify = true;
+        o.latest_ts = 100;
+        assert_eq!(engine.eval(&o, 100).len(), 1);
+
+        let mut stats = HashMap::from([("h1".to_string(), Arc::new(o))]);
+        assert!(engine.expire(&stats, 120, 30).is_empty());
+        let events = engine.expire(&stats, 140, 30);
+        assert!(matches!(&events[..], [(Event::AlertResolved(a), _)] if a.rule == "r1" && a.ts == 140));
+        assert!(engine.states.is_empty());
+
+        // removed host, dropped without notify
+        let mut o = stat(95.0);
+        o.latest_ts = 200;
+        assert_eq!(engine.eval(&o, 200).len(), 1);
+        stats.clear();
+        assert!(engine.expire(&stats, 201, 30).is_empty());
+        assert!(engine.states.is_empty());
+    }
+
+    #[test]
+    fn test_pending_reset() {
+        let mut engine = AlertEngine::new(&[rule("host.cpu > 90.0", 60)]).unwrap();
+        assert!(engine.eval(&stat(95.0), 100).is_empty());
+        assert!(engine.eval(&stat(10.0), 130).is_empty());
+        assert!(engine.eval(&stat(95.0), 170).is_empty());
+        assert_eq!(engine.eval(&stat(95.0), 230).len(), 1);
+    }
+
+    #[test]
+    fn test_targets() {
+        let mut r = rule("true", 0);
+        assert!(r.matches(&stat(0.0)));
+        r.labels = vec!["os=pi".to_string(), "ndd".to_string()];
+        assert!(r.matches(&stat(0.0)));
+        r.labels = vec!["os=centos".to_string()];
+        assert!(!r.matches(&stat(0.0)));
+        r.labels.clear();
+        r.gids = vec!["g1".to_string()];
+        assert!(!r.matches(&stat(0.0)));
+    }
+
+    #[test]
+    fn test_disk_io() {
+        let mut engine = AlertEngine::new(&[rule(r#"host.disk_io.max_of(\"util\") > 90.0"#, 0)]).unwrap();
+        let mut o = stat(0.0);
+        assert!(engine.eval(&o, 100).is_empty());
+        o.disk_io.push(stat_common::server_status::DiskIo {
+            name: "vda".to_string(),
+            util: 95.0,
+            read_rate: 4096,
+            ..Default::default()
+        });
+        assert_eq!(engine.eval(&o, 110).len(), 1);
+        // integer fields
+        let mut engine = AlertEngine::new(&[rule(r#"host.disk_io.max_of(\"read_rate\") > 1024.0"#, 0)]).unwrap();
+        assert_eq!(engine.eval(&o, 120).len(), 1);
+    }
+
+    #[test]
+    fn test_disks() {
+        let mut inodes = rule(r#"host.disks.max_pct(\"inodes_used\", \"inodes_total\") > 90.0"#, 0);
+        inodes.name = "r2".to_string();
+        let mut engine =
+            AlertEngine::new(&[rule(r#"host.disks.max_pct(\"used\", \"total\") > 90.0"#, 0), inodes]).unwrap();
+        let disk = |mount_point: &str, used: u64, inodes_used: u64| stat_common::server_status::DiskInfo {
+            mount_point: mount_point.to_string(),
+            total: 100,
+            used,
+            inodes_total: if inodes_used > 0 { 100 } else { 0 },
+            inodes_used,
+            ..Default::default()
+        };
+        let mut o = stat(0.0);
+        // a big empty /data next to a full /var
+        o.disks = vec![disk("/", 10, 0), disk("/data", 1, 0)];
+        assert!(engine.eval(&o, 100).is_empty());
+        o.disks.push(disk("/var", 95, 0));
+        assert_eq!(engine.eval(&o, 110).len(), 1);
+        o.disks.push(disk("/tmp", 0, 99));
+        assert_eq!(engine.eval(&o, 120).len(), 1);
+        // mount layout stays out of the public json, templates & webhooks get the detail view
+        assert!(serde_json::to_value(&o).unwrap().get("disks").is_none());
+        let v = serde_json::to_value(o.detail()).unwrap();
+        assert_eq!(v["disks"].as_array().map(Vec::len), Some(4));
+        assert!(v["ifaces"].is_array());
+    }
+
+    #[test]
+    fn test_psi() {
+        let expr = r#"host.psi_memory != () && host.psi_memory.full_avg10 > 10.0"#;
+        let mut engine = AlertEngine::new(&[rule(expr, 0)]).unwrap();
+        let mut o = stat(0.0);
+        // kernel without psi
+        assert!(engine.eval(&o, 100).is_empty());
+        o.psi_memory = Some(stat_common::server_status::Psi {
+            full_avg10: 25.0,
+            ..Default::default()
+        });
+        assert_eq!(engine.eval(&o, 110).len(), 1);
+    }
+
+    #[test]
+    fn test_sensors() {
+        let sensor = |name: &str, temp: Option<f64>, fan: Option<u32>| stat_common::server_status::SensorInfo {
+            name: name.to_string(),
+            temp,
+            fan,
+            ..Default::default()
+        };
+        let mut engine = AlertEngine::new(&[rule(r#"host.sensors.max_of(\"temp\") > 80.0"#, 0)]).unwrap();
+        let mut o = stat(0.0);
+        // fans have no temp
+        o.sensors = vec![
+            sensor("cpu_thermal/temp1", Some(62.3), None),
+            sensor("pwmfan/fan1", None, Some(3000)),
+        ];
+        assert!(engine.eval(&o, 100).is_empty());
+        o.sensors[0].temp = Some(82.5);
+        assert_eq!(engine.eval(&o, 110).len(), 1);
+    }
+
+    #[test]
+    fn test_service_events() {
+        let svc = |name: &str, running: bool| stat_common::server_status::ServiceInfo {
+            name: name.to_string(),
+            kind: "systemd".to_string(),
+            running,
+            state: if running { "active/running" } else { "failed/failed" }.to_string(),
+            ..Default::default()
+        };
+        let mut pre = stat(0.0);
+        let mut cur = stat(0.0);
+        pre.services = vec![svc("nginx", true), svc("redis", false)];
+        cur.services = vec![svc("nginx", false), svc("redis", false), svc("pg", false)];
+        let events = service_events(&pre, &cur);
+        assert!(
+            matches!(&events[..], [Event::AlertFiring(a), Event::AlertFiring(b)] if a.rule == "service:nginx" && a.summary == "systemd `nginx` failed/failed" && b.rule == "service:pg")
+        );
+
+        pre.services = vec![svc("nginx", false)];
+        cur.services = vec![svc("nginx", true)];
+        assert!(matches!(&service_events(&pre, &cur)[..], [Event::AlertResolved(_)]));
+        assert!(service_events(&cur, &cur).is_empty());
+
+        let ctr = |name: &str, state: &str| stat_common::server_status::ContainerInfo {
+            name: name.to_string(),
+            running: state == "running",
+            state: state.to_string(),
+            ..Default::default()
+        };
+        pre = stat(0.0);
+        cur = stat(0.0);
+        pre.containers = vec![ctr("web", "running")];
+        cur.containers = vec![ctr("web", "exited"), ctr("job", "exited")];
+        let events = service_events(&pre, &cur);
+        assert!(
+            matches!(&events[..], [Event::AlertFiring(a)] if a.rule == "container:web" && a.summary == "container `web` exited")
+        );
+        assert!(matches!(&service_events(&cur, &pre)[..], [Event::AlertResolved(_)]));
+
+        cur.containers = vec![ctr("job", "exited")];
+        let events = service_events(&pre, &cur);
+        assert!(
+            matches!(&events[..], [Event::AlertFiring(a)] if a.rule == "container:web" && a.summary == "container `web` removed")
+        );
+        // exited ones just disappear
+        assert!(service_events(&cur, &stat(0.0)).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_expr() {
+        assert!(AlertEngine::new(&[rule("host.cpu >", 0)]).is_err());
+    }
+}
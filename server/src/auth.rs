@@ -21,7 +21,7 @@ pub struct BasicAuth {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AdminAuth(BasicAuth);
 #[derive(Debug, Serialize, Deserialize)]
-pub struct HostAuth(BasicAuth);
+pub struct HostAuth(pub BasicAuth);
 
 impl<S> FromRequestParts<S> for BasicAuth
 where
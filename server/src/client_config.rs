@@ -0,0 +1,153 @@
+#![deny(warnings)]
+// `[[client_config]]`, pushed to clients, applied on top of their cli args
+use serde::{Deserialize, Serialize};
+
+use stat_common::server_status::{ClientConfig, StringList};
+
+use crate::alert;
+use crate::config::Config;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Rule {
+    // targets, empty means all
+    #[serde(default = "Default::default")]
+    pub hosts: Vec<String>,
+    #[serde(default = "Default::default")]
+    pub gids: Vec<String>,
+    #[serde(default = "Default::default")]
+    pub labels: Vec<String>,
+
+    pub interval: Option<u64>,
+    pub disable_ping: Option<bool>,
+    pub disable_tupd: Option<bool>,
+    pub vnstat: Option<bool>,
+    pub vnstat_mr: Option<u32>,
+    pub iface: Option<Vec<String>>,
+    pub exclude_iface: Option<Vec<String>>,
+    pub mount: Option<Vec<String>>,
+    pub exclude_mount: Option<Vec<String>>,
+    pub fs: Option<Vec<String>>,
+    pub exclude_fs: Option<Vec<String>>,
+    // [name=]nginx, [name=]re:<regex>, [name=]systemd:<unit>
+    pub watch: Option<Vec<String>>,
+    pub containers: Option<bool>,
+    pub cu_addr: Option<String>,
+    pub ct_addr: Option<String>,
+    pub cm_addr: Option<String>,
+    // name=host:port
+    pub probes: Option<Vec<String>>,
+}
+
+impl Rule {
+    fn merge_into(&self, o: &mut ClientConfig) {
+        let list = |v: &Vec<String>| StringList { items: v.clone() };
+        o.interval = self.interval.or(o.interval);
+        o.disable_ping = self.disable_ping.or(o.disable_ping);
+        o.disable_tupd = self.disable_tupd.or(o.disable_tupd);
+        o.vnstat = self.vnstat.or(o.vnstat);
+        o.vnstat_mr = self.vnstat_mr.or(o.vnstat_mr);
+        o.containers = self.containers.or(o.containers);
+        if let Some(v) = &self.iface {
+            o.iface = Some(list(v));
+        }
+        if let Some(v) = &self.exclude_iface {
+            o.exclude_iface = Some(list(v));
+        }
+        if let Some(v) = &self.mount {
+            o.mount = Some(list(v));
+        }
+        if let Some(v) = &self.exclude_mount {
+            o.exclude_mount = Some(list(v));
+        }
+        if let Some(v) = &self.fs {
+            o.fs = Some(list(v));
+        }
+        if let Some(v) = &self.exclude_fs {
+            o.exclude_fs = Some(list(v));
+        }
+        if let Some(v) = &self.watch {
+            o.watch = Some(list(v));
+        }
+        if let Some(v) = &self.probes {
+            o.probes = Some(list(v));
+        }
+        if self.cu_addr.is_some() {
+            o.cu_addr.clone_from(&self.cu_addr);
+        }
+        if self.ct_addr.is_some() {
+            o.ct_addr.clone_from(&self.ct_addr);
+        }
+        if self.cm_addr.is_some() {
+            o.cm_addr.clone_from(&self.cm_addr);
+        }
+    }
+}
+
+/// all matching rules merged in order, later ones win. None for unknown hosts
+pub fn resolve(cfg: &Config, name: &str, gid: &str) -> Option<ClientConfig> {
+    let labels = if gid.is_empty() {
+        &cfg.hosts_map.get(name)?.labels
+    } else {
+        &cfg.hosts_group_map.get(gid)?.labels
+    };
+
+    let mut o = ClientConfig::default();
+    for rule in &cfg.client_config {
+        if alert::match_host(name, gid, labels, &rule.hosts, &rule.gids, &rule.labels) {
+            rule.merge_into(&mut o);
+        }
+    }
+    // content hash, clients refetch when it changes
+    o.version = format!("{:x}", md5::compute(serde_json::to_vec(&o).unwrap_or_default()));
+    Some(o)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let cfg = crate::config::try_from_str(
+            r#"
+hosts_file = ""
+hosts = [
+  {name = "h1", password = "p1", labels = "os=pi"},
+  {name = "h2", password = "p2"},
+]
+hosts_group = [{gid = "g1", password = "pp"}]
+
+[[client_config]]
+interval = 2
+disable_tupd = true
+
+[[client_config]]
+labels = ["os=pi"]
+interval = 5
+iface = ["eth0"]
+probes = ["fra=fra.example.com:80"]
+
+[[client_config]]
+gids = ["g1"]
+vnstat = true
+"#,
+        )
+        .unwrap();
+
+        let h1 = resolve(&cfg, "h1", "").unwrap();
+        assert_eq!((h1.interval, h1.disable_tupd, h1.vnstat), (Some(5), Some(true), None));
+        assert_eq!(h1.iface.unwrap().items, vec!["eth0"]);
+        assert_eq!(h1.probes.unwrap().items, vec!["fra=fra.example.com:80"]);
+
+        let h2 = resolve(&cfg, "h2", "").unwrap();
+        assert_eq!(h2.interval, Some(2));
+        assert!(h2.iface.is_none());
+        assert_ne!(h1.version, h2.version);
+
+        let g1 = resolve(&cfg, "any", "g1").unwrap();
+        assert_eq!((g1.interval, g1.vnstat), (Some(2), Some(true)));
+
+        assert!(resolve(&cfg, "h3", "").is_none());
+        assert!(resolve(&cfg, "h3", "g2").is_none());
+    }
+}
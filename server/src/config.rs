@@ -5,9 +5,17 @@ use serde_json::Value;
 use std::collections::HashMap;
 use std::env;
 use std::fs;
+use std::sync::{Arc, RwLock};
 use uuid::Uuid;
 
+use crate::alert;
+use crate::client_config;
+use crate::credential::{self, Token};
+use crate::history;
+use crate::monitor;
 use crate::notifier;
+use crate::silence;
+use crate::{G_CONFIG, G_STATS_MGR};
 
 fn default_as_true() -> bool {
     true
@@ -24,6 +32,9 @@ fn default_workspace() -> String {
 fn default_tls_dir() -> String {
     "tls".to_string()
 }
+fn default_hosts_file() -> String {
+    "hosts.toml".to_string()
+}
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Host {
@@ -43,6 +54,8 @@ pub struct Host {
     pub disabled: bool,
     #[serde(default = "Default::default")]
     pub labels: String,
+    #[serde(default = "Default::default", skip_serializing_if = "Vec::is_empty")]
+    pub tokens: Vec<Token>,
 
     #[serde(skip_deserializing)]
     pub last_network_in: u64,
@@ -58,6 +71,9 @@ pub struct Host {
     pub gid: String,
     #[serde(default = "Default::default")]
     pub latest_ts: u64,
+    // `[[monitor]]` pseudo host
+    #[serde(skip)]
+    pub monitor: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -70,6 +86,8 @@ pub struct HostGroup {
     pub r#type: String,
     #[serde(default = "default_as_true")]
     pub notify: bool,
+    #[serde(default = "bool::default")]
+    pub disabled: bool,
     // user data
     #[serde(skip_serializing, skip_deserializing)]
     pub pos: usize,
@@ -77,6 +95,8 @@ pub struct HostGroup {
     pub weight: u64,
     #[serde(default = "Default::default")]
     pub labels: String,
+    #[serde(default = "Default::default", skip_serializing_if = "Vec::is_empty")]
+    pub tokens: Vec<Token>,
 }
 
 impl HostGroup {
@@ -89,6 +109,7 @@ impl HostGroup {
             r#type: self.r#type.clone(),
             monthstart: 1,
             notify: self.notify,
+            disabled: self.disabled,
             pos: self.pos,
             weight: self.weight,
             labels: self.labels.clone(),
@@ -127,12 +148,26 @@ pub struct Config {
     #[serde(default = "Default::default")]
     pub webhook: notifier::webhook::Config,
 
+    #[serde(default = "Default::default")]
+    pub history: history::Config,
+    #[serde(default = "Default::default")]
+    pub alert_rule: Vec<alert::Rule>,
+    #[serde(default = "Default::default")]
+    pub silence: Vec<silence::Silence>,
+    #[serde(default = "Default::default")]
+    pub client_config: Vec<client_config::Rule>,
+    #[serde(default = "Default::default")]
+    pub monitor: Vec<monitor::Monitor>,
+
     #[serde(default = "Default::default")]
     pub hosts: Vec<Host>,
     #[serde(default = "Default::default")]
     pub hosts_group: Vec<HostGroup>,
     #[serde(default = "Default::default")]
     pub group_gc: u64,
+    // admin api 管理的 hosts / hosts_group, 覆盖同名配置
+    #[serde(default = "default_hosts_file")]
+    pub hosts_file: String,
 
     // deploy
     #[serde(default = "Default::default")]
@@ -140,6 +175,12 @@ pub struct Config {
     #[serde(default = "default_workspace")]
     pub workspace: String,
 
+    // 监听配置文件变更自动重载, 也可 `kill -HUP`
+    #[serde(default = "Default::default")]
+    pub auto_reload: bool,
+    #[serde(skip)]
+    pub config_file: String,
+
     #[serde(skip_deserializing)]
     pub hosts_map: HashMap<String, Host>,
 
@@ -149,20 +190,20 @@ pub struct Config {
 
 impl Config {
     pub fn auth(&self, user: &str, pass: &str) -> bool {
-        if let Some(o) = self.hosts_map.get(user) {
-            return pass.eq(o.password.as_str());
+        if let Some(o) = self.hosts_map.get(user).filter(|o| !o.monitor) {
+            return credential::verify(&o.password, &o.tokens, pass);
         }
         false
     }
     pub fn group_auth(&self, gid: &str, pass: &str) -> bool {
         if let Some(o) = self.hosts_group_map.get(gid) {
-            return pass.eq(o.password.as_str());
+            return credential::verify(&o.password, &o.tokens, pass);
         }
         false
     }
     pub fn admin_auth(&self, user: &str, pass: &str) -> bool {
         if let (Some(u), Some(p)) = (self.admin_user.as_ref(), self.admin_pass.as_ref()) {
-            return user.eq(u.as_str()) && pass.eq(p.as_str());
+            return user.eq(u.as_str()) && credential::verify_password(p, pass);
         }
         false
     }
@@ -176,11 +217,66 @@ impl Config {
     // }
 }
 
-pub fn from_str(content: &str) -> Option<Config> {
-    let mut o = toml::from_str::<Config>(content).unwrap();
+/// current config, replaced on reload.
+/// readers hold an `Arc` snapshot, the old one is dropped once the last of them is done
+pub struct ConfigCell(RwLock<Option<Arc<Config>>>);
+
+impl ConfigCell {
+    pub const fn new() -> Self {
+        Self(RwLock::new(None))
+    }
+
+    pub fn get(&self) -> Option<Arc<Config>> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, cfg: Config) -> Arc<Config> {
+        let o = Arc::new(cfg);
+        *self.0.write().unwrap() = Some(Arc::clone(&o));
+        o
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Sidecar {
+    #[serde(default = "Default::default")]
+    pub hosts: Vec<Host>,
+    #[serde(default = "Default::default")]
+    pub hosts_group: Vec<HostGroup>,
+}
+
+pub fn load_sidecar(path: &str) -> Result<Sidecar> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(toml::from_str::<Sidecar>(&contents)?),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Sidecar::default()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// parse & check, without admin/jwt defaults
+pub fn try_from_str(content: &str) -> Result<Config> {
+    let mut o = toml::from_str::<Config>(content)?;
     o.hosts_map = HashMap::new();
 
+    // sidecar
+    if !o.hosts_file.is_empty() {
+        let sidecar = load_sidecar(&o.hosts_file)?;
+        for host in sidecar.hosts {
+            match o.hosts.iter_mut().find(|h| h.name == host.name) {
+                Some(h) => *h = host,
+                None => o.hosts.push(host),
+            }
+        }
+        for group in sidecar.hosts_group {
+            match o.hosts_group.iter_mut().find(|g| g.gid == group.gid) {
+                Some(g) => *g = group,
+                None => o.hosts_group.push(group),
+            }
+        }
+    }
+
     for (idx, host) in o.hosts.iter_mut().enumerate() {
+        credential::validate(&host.password).map_err(|err| anyhow::anyhow!("host `{}` => {err}", host.name))?;
         host.pos = idx;
         if host.alias.is_empty() {
             host.alias = host.name.clone();
@@ -193,6 +289,7 @@ pub fn from_str(content: &str) -> Option<Config> {
     }
 
     for (idx, group) in o.hosts_group.iter_mut().enumerate() {
+        credential::validate(&group.password).map_err(|err| anyhow::anyhow!("hosts_group `{}` => {err}", group.gid))?;
         group.pos = idx;
         group.weight = (10000 - (1 + idx) * 100) as u64;
         o.hosts_group_map.insert(group.gid.clone(), group.clone());
@@ -208,39 +305,91 @@ pub fn from_str(content: &str) -> Option<Config> {
         o.group_gc = 30;
     }
 
-    if o.admin_user.is_none() || o.admin_user.as_ref()?.is_empty() {
+    monitor::validate(&o)?;
+    for (idx, m) in o.monitor.iter().enumerate() {
+        o.hosts_map.insert(m.name.clone(), m.inst_host(o.hosts.len() + idx));
+    }
+
+    if let Some(pass) = o.admin_pass.as_ref() {
+        credential::validate(pass).map_err(|err| anyhow::anyhow!("admin_pass => {err}"))?;
+    }
+    alert::AlertEngine::new(&o.alert_rule)?;
+    silence::validate(&o.silence)?;
+    notifier::validate(&o)?;
+
+    Ok(o)
+}
+
+pub fn from_str(content: &str) -> Result<Config> {
+    let mut o = try_from_str(content)?;
+
+    if o.admin_user.as_ref().is_none_or(String::is_empty) {
         o.admin_user = Some("admin".to_string());
     }
-    if o.admin_pass.is_none() || o.admin_pass.as_ref()?.is_empty() {
+    if o.admin_pass.as_ref().is_none_or(String::is_empty) {
         o.admin_pass = Some(Uuid::new_v4().to_string());
     }
-    if o.jwt_secret.is_none() || o.jwt_secret.as_ref()?.is_empty() {
+    if o.jwt_secret.as_ref().is_none_or(String::is_empty) {
         o.jwt_secret = Some(Uuid::new_v4().to_string());
     }
 
-    eprintln!("✨ admin_user: {}", o.admin_user.as_ref()?);
-    eprintln!("✨ admin_pass: {}", o.admin_pass.as_ref()?);
+    eprintln!("✨ admin_user: {}", o.admin_user.as_deref().unwrap_or_default());
+    eprintln!("✨ admin_pass: {}", o.admin_pass.as_deref().unwrap_or_default());
 
-    Some(o)
+    Ok(o)
 }
 
-pub fn from_env() -> Option<Config> {
-    from_str(
-        env::var("SRV_CONF")
-            .expect("can't load config from env `SRV_CONF")
-            .as_str(),
-    )
+pub fn from_env() -> Result<Config> {
+    let contents =
+        env::var("SRV_CONF").map_err(|err| anyhow::anyhow!("can't load config from env `SRV_CONF` => {err}"))?;
+    from_str(&contents)
 }
 
-pub fn from_file(cfg: &str) -> Option<Config> {
-    fs::read_to_string(cfg)
-        .map(|contents| from_str(contents.as_str()))
-        .ok()?
+pub fn from_file(cfg: &str) -> Result<Config> {
+    let contents = fs::read_to_string(cfg).map_err(|err| anyhow::anyhow!("can't read `{cfg}` => {err}"))?;
+    let mut o = from_str(&contents)?;
+    o.config_file = cfg.to_string();
+    Ok(o)
 }
 
 pub fn test_from_file(cfg: &str) -> Result<Config> {
-    fs::read_to_string(cfg)
-        .map(|contents| toml::from_str::<Config>(&contents))
-        .unwrap()
-        .map_err(anyhow::Error::new)
+    try_from_str(&fs::read_to_string(cfg)?)
+}
+
+/// reload from `config_file` (or `SRV_CONF` in cloud mode), keep in-memory stats & traffic baselines
+pub fn reload() -> Result<()> {
+    let old = G_CONFIG.get().unwrap();
+    let contents = if old.config_file.is_empty() {
+        env::var("SRV_CONF")?
+    } else {
+        fs::read_to_string(&old.config_file)?
+    };
+    let mut o = try_from_str(&contents)?;
+    o.config_file.clone_from(&old.config_file);
+
+    // 未配置时沿用启动时生成的账号, jwt 密钥不支持热更新
+    if o.admin_user.as_ref().is_none_or(String::is_empty) {
+        o.admin_user.clone_from(&old.admin_user);
+    }
+    if o.admin_pass.as_ref().is_none_or(String::is_empty) {
+        o.admin_pass.clone_from(&old.admin_pass);
+    }
+    if o.jwt_secret.as_ref().is_some_and(|v| !v.is_empty()) && o.jwt_secret != old.jwt_secret {
+        warn!("jwt_secret changed, restart required");
+    }
+    o.jwt_secret.clone_from(&old.jwt_secret);
+    if o.http_addr != old.http_addr
+        || o.grpc_addr != old.grpc_addr
+        || o.grpc_tls != old.grpc_tls
+        || o.tls_dir != old.tls_dir
+        || o.history.enabled != old.history.enabled
+        || o.history.path != old.history.path
+    {
+        warn!("http_addr/grpc_addr/grpc_tls/tls_dir/history changed, restart required");
+    }
+
+    let cfg = G_CONFIG.set(o);
+    G_STATS_MGR.get().unwrap().reload(&cfg)?;
+    eprintln!("✨ reload config succ!");
+    Ok(())
 }
@@ -0,0 +1,201 @@
+#![deny(warnings)]
+// host / admin credentials
+// password: argon2 PHC string `$argon2id$v=19$...`, plaintext still accepted during migration
+// token: issued by admin api, only the sha256 is kept in `hosts_file`, revocable by id
+use anyhow::{anyhow, Result};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const HASH_PREFIX: &str = "$argon2";
+pub const TOKEN_PREFIX: &str = "sst_";
+const CACHE_SIZE: usize = 4096;
+// argon2 runs per PHC string allowed within FAIL_WINDOW once verification starts failing
+const MAX_FAILURES: u32 = 5;
+const FAIL_WINDOW: Duration = Duration::from_secs(60);
+
+// agents report every second, argon2 is slow on purpose.
+// PHC string => sha256 of the last password verified against it
+static VERIFIED: Lazy<Mutex<HashMap<String, [u8; 32]>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+// PHC string => recent failures, a misconfigured agent or a guesser must not pin the cpu
+static FAILED: Lazy<Mutex<HashMap<String, Failures>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+struct Failures {
+    since: Instant,
+    count: u32,
+    // sha256 of the passwords already rejected in this window
+    digests: Vec<[u8; 32]>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Token {
+    pub id: String,
+    // sha256 hex
+    pub hash: String,
+    #[serde(default = "Default::default")]
+    pub comment: String,
+    #[serde(default = "Default::default")]
+    pub created: u64,
+}
+
+fn digest(s: &str) -> [u8; 32] {
+    Sha256::digest(s.as_bytes()).into()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut s, b| {
+        let _ = write!(s, "{b:02x}");
+        s
+    })
+}
+
+pub fn is_hashed(password: &str) -> bool {
+    password.starts_with(HASH_PREFIX)
+}
+
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::encode_b64(Uuid::new_v4().as_bytes()).map_err(|err| anyhow!("{err}"))?;
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|err| anyhow!("{err}"))?;
+    Ok(hash.to_string())
+}
+
+/// hashed `password` must be a valid PHC string
+pub fn validate(password: &str) -> Result<()> {
+    if is_hashed(password) {
+        PasswordHash::new(password).map_err(|err| anyhow!("invalid password hash `{password}` => {err}"))?;
+    }
+    Ok(())
+}
+
+pub fn verify_password(stored: &str, password: &str) -> bool {
+    if !is_hashed(stored) {
+        return stored.eq(password);
+    }
+
+    let d = digest(password);
+    if VERIFIED.lock().unwrap().get(stored) == Some(&d) {
+        return true;
+    }
+    if let Some(o) = FAILED.lock().unwrap().get(stored) {
+        if o.since.elapsed() < FAIL_WINDOW && (o.count >= MAX_FAILURES || o.digests.contains(&d)) {
+            return false;
+        }
+    }
+
+    let ok = blocking(|| {
+        PasswordHash::new(stored)
+            .is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+    });
+    if ok {
+        FAILED.lock().unwrap().remove(stored);
+        let mut cache = VERIFIED.lock().unwrap();
+        if cache.len() >= CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(stored.to_string(), d);
+    } else {
+        let mut failed = FAILED.lock().unwrap();
+        if failed.len() >= CACHE_SIZE {
+            failed.retain(|_, o| o.since.elapsed() < FAIL_WINDOW);
+        }
+        if failed.get(stored).is_some_and(|o| o.since.elapsed() >= FAIL_WINDOW) {
+            failed.remove(stored);
+        }
+        let o = failed.entry(stored.to_string()).or_insert_with(|| Failures {
+            since: Instant::now(),
+            count: 0,
+            digests: Vec::new(),
+        });
+        o.count += 1;
+        o.digests.push(d);
+        log::warn!(
+            "password verification failed {} time(s) within {FAIL_WINDOW:?}",
+            o.count
+        );
+    }
+    ok
+}
+
+// keep argon2 off the async workers, the grpc interceptor & extractors call this synchronously
+fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    match tokio::runtime::Handle::try_current() {
+        Ok(h) if h.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread => tokio::task::block_in_place(f),
+        _ => f(),
+    }
+}
+
+pub fn verify_token(tokens: &[Token], token: &str) -> bool {
+    if tokens.is_empty() || !token.starts_with(TOKEN_PREFIX) {
+        return false;
+    }
+    let hash = to_hex(&digest(token));
+    tokens.iter().any(|o| o.hash == hash)
+}
+
+/// password or token
+pub fn verify(stored: &str, tokens: &[Token], password: &str) -> bool {
+    verify_token(tokens, password) || verify_password(stored, password)
+}
+
+/// returns (plaintext token, stored token)
+pub fn new_token(comment: &str) -> (String, Token) {
+    let token = format!("{TOKEN_PREFIX}{}", Uuid::new_v4().simple());
+    let o = Token {
+        id: Uuid::new_v4().simple().to_string()[..8].to_string(),
+        hash: to_hex(&digest(&token)),
+        comment: comment.to_string(),
+        created: chrono::Utc::now().timestamp() as u64,
+    };
+    (token, o)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password() {
+        let hash = hash_password("pp").unwrap();
+        assert!(is_hashed(&hash));
+        assert!(validate(&hash).is_ok());
+        assert!(verify_password(&hash, "pp"));
+        // cached
+        assert!(verify_password(&hash, "pp"));
+        assert!(!verify_password(&hash, "p"));
+        // rejected without argon2 until the window expires
+        assert!(!verify_password(&hash, "p"));
+        assert_eq!(FAILED.lock().unwrap()[&hash].count, 1);
+        for i in 0..MAX_FAILURES {
+            assert!(!verify_password(&hash, &format!("p{i}")));
+        }
+        assert_eq!(FAILED.lock().unwrap()[&hash].count, MAX_FAILURES);
+        // still cached
+        assert!(verify_password(&hash, "pp"));
+
+        assert!(verify_password("pp", "pp"));
+        assert!(!verify_password("pp", "p"));
+        assert!(validate("$argon2id$xx").is_err());
+    }
+
+    #[test]
+    fn test_token() {
+        let (token, o) = new_token("ci");
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(o.hash.len(), 64);
+        let tokens = vec![o];
+        assert!(verify_token(&tokens, &token));
+        assert!(!verify_token(&tokens, &tokens[0].hash));
+        assert!(!verify_token(&[], &token));
+        assert!(verify("pp", &tokens, &token));
+        assert!(verify("pp", &tokens, "pp"));
+    }
+}
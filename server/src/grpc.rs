@@ -1,44 +1,170 @@
 // #![allow(unused)]
 use anyhow::Result;
+use futures_util::stream::{self, Stream};
+use std::pin::Pin;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::mpsc;
 use tonic::{
     transport::{Certificate, Identity, Server, ServerTlsConfig},
-    Request, Response, Status,
+    Request, Response, Status, Streaming,
 };
 
 use stat_common::server_status;
 use stat_common::server_status::server_status_server::{ServerStatus, ServerStatusServer};
-use stat_common::server_status::StatRequest;
+use stat_common::server_status::{ClientConfig, ConfigRequest, Control, StatRequest};
 
+use crate::client_config;
 use crate::config::Config;
 use crate::G_CONFIG;
 use crate::G_STATS_MGR;
 
+// re-check control msg without samples, eg. while the client is disabled
+const CONTROL_INTERVAL: Duration = Duration::from_secs(5);
+// detect dead stream clients
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+const KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// tells a reconnected host's new stream from the old one still closing
+static STREAM_ID: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Default)]
 pub struct ServerStatusSrv {}
 
+// authenticated host name or gid, set by `check_auth`
+#[derive(Debug, Clone, Default)]
+struct Principal {
+    user: String,
+    group: bool,
+}
+
+fn report_stat(stat: &StatRequest, stream: u64) -> Result<(), Status> {
+    if let Some(mgr) = G_STATS_MGR.get() {
+        match serde_json::to_value(stat) {
+            Ok(v) => {
+                // backfill only, the client retries later
+                mgr.report(v, stream)
+                    .map_err(|err| Status::resource_exhausted(err.to_string()))?;
+            }
+            Err(err) => {
+                error!("serde_json::to_value err => {err:?}");
+            }
+        }
+    }
+    Ok(())
+}
+
 #[tonic::async_trait]
 impl ServerStatus for ServerStatusSrv {
     async fn report(&self, request: Request<StatRequest>) -> Result<Response<server_status::Response>, Status> {
-        if let Some(mgr) = G_STATS_MGR.get() {
-            match serde_json::to_value(request.get_ref()) {
-                Ok(v) => {
-                    let _ = mgr.report(v);
-                }
-                Err(err) => {
-                    error!("serde_json::to_value err => {err:?}");
-                }
-            }
-        }
+        report_stat(request.get_ref(), 0)?;
 
         Ok(Response::new(server_status::Response {
             code: 0,
             message: "ok".to_string(),
         }))
     }
+
+    type ReportStreamStream = Pin<Box<dyn Stream<Item = Result<Control, Status>> + Send>>;
+
+    async fn report_stream(
+        &self,
+        request: Request<Streaming<StatRequest>>,
+    ) -> Result<Response<Self::ReportStreamStream>, Status> {
+        let principal = request.extensions().get::<Principal>().cloned().unwrap_or_default();
+        let mut samples = request.into_inner();
+        let (tx, rx) = mpsc::channel(4);
+        let stream_id = STREAM_ID.fetch_add(1, Ordering::Relaxed) + 1;
+
+        tokio::spawn(async move {
+            // bound to the principal, group clients pick the name with the first sample
+            let (mut name, gid) = if principal.group {
+                (String::new(), principal.user)
+            } else {
+                (principal.user, String::new())
+            };
+            let mut connected = false;
+            // samples accepted on this stream, acked on the control ticks only
+            let mut accepted = 0_u64;
+            let mut last: Option<Control> = None;
+            let mut ticker = tokio::time::interval(CONTROL_INTERVAL);
+            loop {
+                let tick = tokio::select! {
+                    msg = samples.message() => match msg {
+                        Ok(Some(stat)) => {
+                            if name.is_empty() {
+                                name.clone_from(&stat.name);
+                            }
+                            // acks are a count, close rather than skip a sample
+                            if name.is_empty() || !stat.name.eq(&name) || !stat.gid.eq(&gid) {
+                                warn!("grpc stream of `{name}` got sample of `{}`@`{}`, close", stat.name, stat.gid);
+                                let _ = tx.send(Err(Status::permission_denied("sample of another host"))).await;
+                                break;
+                            }
+                            if let Err(status) = report_stat(&stat, stream_id) {
+                                warn!("grpc stream of `{name}` report error => {status:?}");
+                                let _ = tx.send(Err(status)).await;
+                                break;
+                            }
+                            accepted += 1;
+                            if !connected {
+                                connected = true;
+                                info!("grpc stream of `{name}` connected");
+                            }
+                            false
+                        }
+                        Ok(None) => break,
+                        Err(status) => {
+                            warn!("grpc stream of `{name}` error => {status:?}");
+                            break;
+                        }
+                    },
+                    _ = ticker.tick() => true,
+                };
+
+                if !connected {
+                    continue;
+                }
+                let mut ctl = G_STATS_MGR.get().unwrap().control(&name, &gid);
+                ctl.acked = if tick {
+                    accepted
+                } else {
+                    last.as_ref().map_or(0, |o| o.acked)
+                };
+                if last.as_ref() != Some(&ctl) {
+                    if tx.send(Ok(ctl.clone())).await.is_err() {
+                        break;
+                    }
+                    last = Some(ctl);
+                }
+            }
+
+            if connected {
+                info!("grpc stream of `{name}` closed");
+                G_STATS_MGR.get().unwrap().disconnect(&name, stream_id);
+            }
+        });
+
+        let controls = stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|o| (o, rx)) });
+        Ok(Response::new(Box::pin(controls)))
+    }
+
+    async fn get_config(&self, request: Request<ConfigRequest>) -> Result<Response<ClientConfig>, Status> {
+        let principal = request.extensions().get::<Principal>().cloned().unwrap_or_default();
+        let (name, gid) = if principal.group {
+            (request.get_ref().name.as_str(), principal.user.as_str())
+        } else {
+            (principal.user.as_str(), "")
+        };
+
+        client_config::resolve(&G_CONFIG.get().unwrap(), name, gid)
+            .map(Response::new)
+            .ok_or_else(|| Status::not_found("host not found"))
+    }
 }
 
-fn check_auth(req: Request<()>) -> Result<Request<()>, Status> {
+fn check_auth(mut req: Request<()>) -> Result<Request<()>, Status> {
     let mut group_auth = false;
     req.metadata().get("ssr-auth").map(|v| {
         v.to_str().map(|s| {
@@ -52,11 +178,17 @@ fn check_auth(req: Request<()>) -> Result<Request<()>, Status> {
 
             if tuple.len() == 2 {
                 if let Some(cfg) = G_CONFIG.get() {
-                    if group_auth {
-                        if cfg.group_auth(tuple[0], tuple[1]) {
-                            return Ok(req);
-                        }
-                    } else if cfg.auth(tuple[0], tuple[1]) {
+                    let auth_ok = if group_auth {
+                        cfg.group_auth(tuple[0], tuple[1])
+                    } else {
+                        cfg.auth(tuple[0], tuple[1])
+                    };
+                    if auth_ok {
+                        let principal = Principal {
+                            user: tuple[0].to_string(),
+                            group: group_auth,
+                        };
+                        req.extensions_mut().insert(principal);
                         return Ok(req);
                     }
                 }
@@ -90,6 +222,8 @@ pub async fn serv_grpc(cfg: &Config) -> anyhow::Result<()> {
 
         eprintln!("🚀 listening on grpc://{sock_addr}{proto}");
         Server::builder()
+            .http2_keepalive_interval(Some(KEEPALIVE_INTERVAL))
+            .http2_keepalive_timeout(Some(KEEPALIVE_TIMEOUT))
             .tls_config(tls)?
             .add_service(svc)
             .serve(sock_addr)
@@ -98,6 +232,8 @@ pub async fn serv_grpc(cfg: &Config) -> anyhow::Result<()> {
     } else {
         eprintln!("🚀 listening on grpc://{sock_addr}");
         Server::builder()
+            .http2_keepalive_interval(Some(KEEPALIVE_INTERVAL))
+            .http2_keepalive_timeout(Some(KEEPALIVE_TIMEOUT))
             .accept_http1(true)
             .add_service(svc)
             .serve(sock_addr)
@@ -0,0 +1,677 @@
+#![deny(warnings)]
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::payload::HostStat;
+
+// 1m / 5m / 1h
+pub const RESOLUTIONS: [u64; 3] = [60, 300, 3600];
+pub const METRICS: [&str; 18] = [
+    "cpu",
+    "load_1",
+    "load_5",
+    "load_15",
+    "memory_total",
+    "memory_used",
+    "swap_total",
+    "swap_used",
+    "hdd_total",
+    "hdd_used",
+    "network_rx",
+    "network_tx",
+    "ping_10010",
+    "ping_189",
+    "ping_10086",
+    "time_10010",
+    "time_189",
+    "time_10086",
+];
+// per named probe target, `ping_<probe>` (lost rate %) / `time_<probe>` (ms) in queries
+pub const PROBE_METRICS: [&str; 2] = ["ping", "time"];
+const METRIC_NUM: usize = METRICS.len();
+const PURGE_INTERVAL: u64 = 3600;
+const MAX_POINTS: u64 = 10000;
+
+fn default_path() -> String {
+    "history.db".to_string()
+}
+fn default_retention_1m() -> u64 {
+    2
+}
+fn default_retention_5m() -> u64 {
+    14
+}
+fn default_retention_1h() -> u64 {
+    365
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Config {
+    #[serde(default = "Default::default")]
+    pub enabled: bool,
+    #[serde(default = "default_path")]
+    pub path: String,
+    // days
+    #[serde(default = "default_retention_1m")]
+    pub retention_1m: u64,
+    #[serde(default = "default_retention_5m")]
+    pub retention_5m: u64,
+    #[serde(default = "default_retention_1h")]
+    pub retention_1h: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: default_path(),
+            retention_1m: default_retention_1m(),
+            retention_5m: default_retention_5m(),
+            retention_1h: default_retention_1h(),
+        }
+    }
+}
+
+impl Config {
+    fn retention(&self, res: u64) -> u64 {
+        let days = match res {
+            60 => self.retention_1m,
+            300 => self.retention_5m,
+            _ => self.retention_1h,
+        };
+        days * 24 * 3600
+    }
+}
+
+pub fn sample_values(stat: &HostStat) -> [f64; METRIC_NUM] {
+    #[allow(clippy::cast_precision_loss)]
+    [
+        stat.cpu,
+        stat.load_1,
+        stat.load_5,
+        stat.load_15,
+        stat.memory_total as f64,
+        stat.memory_used as f64,
+        stat.swap_total as f64,
+        stat.swap_used as f64,
+        stat.hdd_total as f64,
+        stat.hdd_used as f64,
+        stat.network_rx as f64,
+        stat.network_tx as f64,
+        stat.ping_10010,
+        stat.ping_189,
+        stat.ping_10086,
+        stat.time_10010,
+        stat.time_189,
+        stat.time_10086,
+    ]
+}
+
+/// min/sum/max of every metric within one `res` aligned time bucket
+#[derive(Debug, Clone)]
+pub struct Bucket {
+    pub ts: u64,
+    pub cnt: u64,
+    pub sum: Vec<f64>,
+    pub min: Vec<f64>,
+    pub max: Vec<f64>,
+}
+
+impl Bucket {
+    pub fn new(ts: u64, n: usize) -> Self {
+        Self {
+            ts,
+            cnt: 0,
+            sum: vec![0.0; n],
+            min: vec![f64::MAX; n],
+            max: vec![f64::MIN; n],
+        }
+    }
+
+    pub fn add(&mut self, vals: &[f64]) {
+        self.cnt += 1;
+        for (idx, v) in vals.iter().enumerate() {
+            self.sum[idx] += v;
+            self.min[idx] = self.min[idx].min(*v);
+            self.max[idx] = self.max[idx].max(*v);
+        }
+    }
+}
+
+/// (table, key columns, metrics) of host metrics or named probes
+fn table(probe: bool) -> (&'static str, &'static str, &'static [&'static str]) {
+    if probe {
+        ("probe_rollup", "res, name, probe, ts", &PROBE_METRICS)
+    } else {
+        ("rollup", "res, name, ts", &METRICS)
+    }
+}
+
+fn upsert_sql(probe: bool) -> String {
+    let (table, keys, metrics) = table(probe);
+    let cols = metrics
+        .iter()
+        .map(|m| format!("{m}_sum, {m}_min, {m}_max"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let holders = (0..keys.split(',').count() + 1 + metrics.len() * 3)
+        .map(|_| "?")
+        .collect::<Vec<_>>()
+        .join(", ");
+    let updates = metrics
+        .iter()
+        .map(|m| {
+            format!(
+                "{m}_sum = {m}_sum + excluded.{m}_sum, {m}_min = min({m}_min, excluded.{m}_min), {m}_max = max({m}_max, excluded.{m}_max)"
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "INSERT INTO {table} ({keys}, cnt, {cols}) VALUES ({holders}) \
+         ON CONFLICT({keys}) DO UPDATE SET cnt = cnt + excluded.cnt, {updates}"
+    )
+}
+
+static UPSERT_SQL: LazyLock<String> = LazyLock::new(|| upsert_sql(false));
+static PROBE_UPSERT_SQL: LazyLock<String> = LazyLock::new(|| upsert_sql(true));
+
+// (host, probe, res), probe is empty for the host metrics
+type PendingKey = (String, String, u64);
+
+pub struct HistoryStore {
+    conn: Mutex<Connection>,
+    cfg: Config,
+    // open buckets, written once closed
+    pending: Mutex<HashMap<PendingKey, Bucket>>,
+}
+
+impl HistoryStore {
+    pub fn open(cfg: &Config) -> Result<Self> {
+        let conn = Connection::open(&cfg.path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        Self::with_conn(conn, cfg)
+    }
+
+    fn with_conn(conn: Connection, cfg: &Config) -> Result<Self> {
+        for probe in [false, true] {
+            let (table, keys, metrics) = table(probe);
+            let cols = metrics
+                .iter()
+                .map(|m| format!("{m}_sum REAL NOT NULL, {m}_min REAL NOT NULL, {m}_max REAL NOT NULL"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let probe_col = if probe { "probe TEXT NOT NULL, " } else { "" };
+            conn.execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS {table} (\
+                 res INTEGER NOT NULL, name TEXT NOT NULL, {probe_col}ts INTEGER NOT NULL, cnt INTEGER NOT NULL, {cols}, \
+                 PRIMARY KEY ({keys})) WITHOUT ROWID;"
+            ))?;
+        }
+        Ok(Self {
+            conn: Mutex::new(conn),
+            cfg: cfg.clone(),
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn write(&self, res: u64, name: &str, probe: &str, bucket: &Bucket) -> Result<()> {
+        if bucket.cnt == 0 {
+            return Ok(());
+        }
+        let mut values: Vec<rusqlite::types::Value> = Vec::with_capacity(5 + bucket.sum.len() * 3);
+        values.push(i64::try_from(res)?.into());
+        values.push(name.to_string().into());
+        if !probe.is_empty() {
+            values.push(probe.to_string().into());
+        }
+        values.push(i64::try_from(bucket.ts)?.into());
+        values.push(i64::try_from(bucket.cnt)?.into());
+        for ((sum, min), max) in bucket.sum.iter().zip(&bucket.min).zip(&bucket.max) {
+            values.push((*sum).into());
+            values.push((*min).into());
+            values.push((*max).into());
+        }
+
+        let sql = if probe.is_empty() {
+            &UPSERT_SQL
+        } else {
+            &PROBE_UPSERT_SQL
+        };
+        let conn = self.conn.lock().unwrap();
+        conn.prepare_cached(sql.as_str())?
+            .execute(rusqlite::params_from_iter(values))?;
+        Ok(())
+    }
+
+    pub fn purge(&self, now: u64) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let mut n = 0;
+        for res in RESOLUTIONS {
+            let expire = now.saturating_sub(self.cfg.retention(res));
+            for table in ["rollup", "probe_rollup"] {
+                n += conn.execute(
+                    &format!("DELETE FROM {table} WHERE res = ?1 AND ts < ?2"),
+                    params![i64::try_from(res)?, i64::try_from(expire)?],
+                )?;
+            }
+        }
+        Ok(n)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Point {
+    pub ts: u64,
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Series {
+    pub name: String,
+    pub from: u64,
+    pub to: u64,
+    pub step: u64,
+    pub res: u64,
+    pub metrics: HashMap<String, Vec<Point>>,
+}
+
+// (ts, cnt, [(sum, min, max)])
+type StepAgg = (u64, u64, Vec<(f64, f64, f64)>);
+
+/// pick the coarsest rollup which still fits into `step`
+pub fn pick_res(step: u64) -> u64 {
+    RESOLUTIONS
+        .iter()
+        .rev()
+        .find(|&&res| res <= step && step.is_multiple_of(res))
+        .copied()
+        .unwrap_or(RESOLUTIONS[0])
+}
+
+/// `cpu` => ("", 0), `time_fra` => ("fra", 1) unless it is one of the fixed carrier metrics
+fn parse_metric(m: &str) -> Option<(&str, usize)> {
+    if let Some(idx) = METRICS.iter().position(|o| *o == m) {
+        return Some(("", idx));
+    }
+    let (kind, probe) = m.split_once('_')?;
+    let idx = PROBE_METRICS.iter().position(|o| *o == kind)?;
+    (!probe.is_empty()).then_some((probe, idx))
+}
+
+impl HistoryStore {
+    pub fn query(&self, name: &str, metrics: &[&str], from: u64, to: u64, step: u64) -> Result<Series> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        self.query_at(name, metrics, from, to, step, now)
+    }
+
+    fn query_at(&self, name: &str, metrics: &[&str], from: u64, to: u64, step: u64, now: u64) -> Result<Series> {
+        // probe => [(idx in the table metrics, requested metric)]
+        let mut groups: Vec<(&str, Vec<(usize, &str)>)> = Vec::new();
+        for m in metrics {
+            let Some((probe, idx)) = parse_metric(m) else {
+                anyhow::bail!("unknown metric `{m}`");
+            };
+            match groups.iter_mut().find(|(p, _)| *p == probe) {
+                Some((_, o)) => o.push((idx, *m)),
+                None => groups.push((probe, vec![(idx, *m)])),
+            }
+        }
+        if from >= to {
+            anyhow::bail!("invalid time range");
+        }
+        let mut res = pick_res(step.max(RESOLUTIONS[0]));
+        // finer rollups are purged first, fall back to the one still covering `from`
+        while res < RESOLUTIONS[RESOLUTIONS.len() - 1] && from < now.saturating_sub(self.cfg.retention(res)) {
+            res = RESOLUTIONS[RESOLUTIONS.iter().position(|&o| o == res).unwrap() + 1];
+        }
+        let step = step.max(res).div_ceil(res) * res;
+        if (to - from) / step > MAX_POINTS {
+            anyhow::bail!("too many points, increase step");
+        }
+
+        let mut series = Series {
+            name: name.to_string(),
+            from,
+            to,
+            step,
+            res,
+            metrics: HashMap::new(),
+        };
+        for (probe, cols) in groups {
+            let idxs = cols.iter().map(|(idx, _)| *idx).collect::<Vec<_>>();
+            let steps = self.query_steps(name, probe, &idxs, from - from % res, to, res, step)?;
+            for (pos, (_, m)) in cols.iter().enumerate() {
+                #[allow(clippy::cast_precision_loss)]
+                let points = steps
+                    .iter()
+                    .map(|(ts, cnt, vals)| Point {
+                        ts: *ts,
+                        min: vals[pos].1,
+                        avg: vals[pos].0 / (*cnt).max(1) as f64,
+                        max: vals[pos].2,
+                    })
+                    .collect();
+                series.metrics.insert((*m).to_string(), points);
+            }
+        }
+        Ok(series)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn query_steps(
+        &self,
+        name: &str,
+        probe: &str,
+        idxs: &[usize],
+        from: u64,
+        to: u64,
+        res: u64,
+        step: u64,
+    ) -> Result<Vec<StepAgg>> {
+        let (table, _, table_metrics) = table(!probe.is_empty());
+        // metric names come from METRICS / PROBE_METRICS
+        let cols = idxs
+            .iter()
+            .map(|&idx| table_metrics[idx])
+            .map(|m| format!("{m}_sum, {m}_min, {m}_max"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!(
+            "SELECT ts, cnt, {cols} FROM {table} WHERE res = ?1 AND name = ?2 AND ts >= ?3 AND ts < ?4{} ORDER BY ts",
+            if probe.is_empty() { "" } else { " AND probe = ?5" }
+        );
+
+        let mut steps: Vec<StepAgg> = Vec::new();
+        {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn.prepare_cached(&sql)?;
+            let mut values: Vec<rusqlite::types::Value> = vec![
+                i64::try_from(res)?.into(),
+                name.to_string().into(),
+                i64::try_from(from)?.into(),
+                i64::try_from(to)?.into(),
+            ];
+            if !probe.is_empty() {
+                values.push(probe.to_string().into());
+            }
+            let mut rows = stmt.query(rusqlite::params_from_iter(values))?;
+            while let Some(row) = rows.next()? {
+                let ts = u64::try_from(row.get::<_, i64>(0)?)?;
+                let cnt = u64::try_from(row.get::<_, i64>(1)?)?;
+
+                let mut vals = Vec::with_capacity(idxs.len());
+                for pos in 0..idxs.len() {
+                    vals.push((
+                        row.get::<_, f64>(2 + pos * 3)?,
+                        row.get::<_, f64>(3 + pos * 3)?,
+                        row.get::<_, f64>(4 + pos * 3)?,
+                    ));
+                }
+                merge_step(&mut steps, ts - ts % step, cnt, vals);
+            }
+        }
+
+        // the open bucket is always the latest one
+        let key = (name.to_string(), probe.to_string(), res);
+        if let Some(bucket) = self.pending.lock().unwrap().get(&key) {
+            if bucket.cnt > 0 && bucket.ts >= from && bucket.ts < to {
+                let vals = idxs
+                    .iter()
+                    .map(|&idx| (bucket.sum[idx], bucket.min[idx], bucket.max[idx]))
+                    .collect();
+                merge_step(&mut steps, bucket.ts - bucket.ts % step, bucket.cnt, vals);
+            }
+        }
+        Ok(steps)
+    }
+}
+
+fn merge_step(steps: &mut Vec<StepAgg>, step_ts: u64, cnt: u64, vals: Vec<(f64, f64, f64)>) {
+    match steps.last_mut() {
+        Some((last_ts, last_cnt, last_vals)) if *last_ts == step_ts => {
+            *last_cnt += cnt;
+            for (o, v) in last_vals.iter_mut().zip(vals) {
+                o.0 += v.0;
+                o.1 = o.1.min(v.1);
+                o.2 = o.2.max(v.2);
+            }
+        }
+        _ => steps.push((step_ts, cnt, vals)),
+    }
+}
+
+// Aggregates incoming samples in memory and writes each bucket once it is closed.
+impl HistoryStore {
+    pub fn record(&self, name: &str, probe: &str, ts: u64, vals: &[f64]) {
+        let mut pending = self.pending.lock().unwrap();
+        for res in RESOLUTIONS {
+            let bucket_ts = ts - ts % res;
+            let key = (name.to_string(), probe.to_string(), res);
+            match pending.get_mut(&key) {
+                Some(bucket) if bucket.ts == bucket_ts => bucket.add(vals),
+                Some(bucket) if bucket.ts > bucket_ts => {
+                    // late sample, merge into the stored bucket
+                    let mut late = Bucket::new(bucket_ts, vals.len());
+                    late.add(vals);
+                    if let Err(err) = self.write(res, name, probe, &late) {
+                        error!("history write error => {err:?}");
+                    }
+                }
+                _ => {
+                    let mut bucket = Bucket::new(bucket_ts, vals.len());
+                    bucket.add(vals);
+                    if let Some(closed) = pending.insert(key, bucket) {
+                        if let Err(err) = self.write(res, name, probe, &closed) {
+                            error!("history write error => {err:?}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// host metrics and one series per named probe
+    pub fn record_stat(&self, stat: &HostStat) {
+        self.record(&stat.name, "", stat.latest_ts, &sample_values(stat));
+        for o in &stat.probes {
+            if !o.name.is_empty() {
+                self.record(&stat.name, &o.name, stat.latest_ts, &[o.lost_rate, o.time]);
+            }
+        }
+    }
+
+    /// write buckets of hosts which stopped reporting
+    pub fn flush_stale(&self, now: u64) {
+        self.pending.lock().unwrap().retain(|(name, probe, res), bucket| {
+            if bucket.ts + *res < now {
+                if let Err(err) = self.write(*res, name, probe, bucket) {
+                    error!("history write error => {err:?}");
+                }
+                return false;
+            }
+            true
+        });
+    }
+
+    pub fn flush_all(&self) {
+        for ((name, probe, res), bucket) in self.pending.lock().unwrap().drain() {
+            if let Err(err) = self.write(res, &name, &probe, &bucket) {
+                error!("history write error => {err:?}");
+            }
+        }
+    }
+}
+
+pub fn start_history_t(store: Arc<HistoryStore>, history_rx: Receiver<Arc<HostStat>>) {
+    std::thread::spawn(move || {
+        let mut latest_purge_ts = 0_u64;
+        loop {
+            match history_rx.recv_timeout(Duration::from_secs(5)) {
+                Ok(stat) => store.record_stat(&stat),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    store.flush_all();
+                    break;
+                }
+            }
+
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+            store.flush_stale(now);
+            if latest_purge_ts + PURGE_INTERVAL < now {
+                latest_purge_ts = now;
+                match store.purge(now) {
+                    Ok(n) => trace!("history purge {n} rows"),
+                    Err(err) => error!("history purge error => {err:?}"),
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> HistoryStore {
+        HistoryStore::with_conn(Connection::open_in_memory().unwrap(), &Config::default()).unwrap()
+    }
+
+    fn vals(cpu: f64) -> [f64; METRIC_NUM] {
+        let mut v = [0.0; METRIC_NUM];
+        v[0] = cpu;
+        v
+    }
+
+    fn row(store: &HistoryStore, res: u64, ts: u64) -> Option<(i64, f64, f64, f64)> {
+        let conn = store.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT cnt, cpu_sum, cpu_min, cpu_max FROM rollup WHERE res = ?1 AND name = 'h1' AND ts = ?2",
+            params![i64::try_from(res).unwrap(), i64::try_from(ts).unwrap()],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)),
+        )
+        .ok()
+    }
+
+    #[test]
+    fn test_bucket_written_on_rollover() {
+        let store = store();
+        store.record("h1", "", 120, &vals(10.0));
+        store.record("h1", "", 150, &vals(30.0));
+        assert!(row(&store, 60, 120).is_none());
+
+        store.record("h1", "", 180, &vals(50.0));
+        assert_eq!(row(&store, 60, 120), Some((2, 40.0, 10.0, 30.0)));
+        // 5m bucket still open
+        assert!(row(&store, 300, 0).is_none());
+    }
+
+    #[test]
+    fn test_late_sample_merged() {
+        let store = store();
+        store.record("h1", "", 120, &vals(10.0));
+        store.record("h1", "", 180, &vals(20.0));
+        store.record("h1", "", 130, &vals(90.0));
+        assert_eq!(row(&store, 60, 120), Some((2, 100.0, 10.0, 90.0)));
+    }
+
+    #[test]
+    fn test_query_step() {
+        let store = store();
+        for (ts, cpu) in [(0, 10.0), (60, 20.0), (120, 30.0), (180, 40.0), (240, 50.0)] {
+            store.record("h1", "", ts, &vals(cpu));
+        }
+        store.flush_all();
+
+        let series = store.query_at("h1", &["cpu"], 0, 300, 120, 300).unwrap();
+        assert_eq!(series.res, 60);
+        let points = &series.metrics["cpu"];
+        assert_eq!(points.len(), 3);
+        assert_eq!((points[0].ts, points[0].min, points[0].avg, points[0].max), (0, 10.0, 15.0, 20.0));
+        assert_eq!((points[2].ts, points[2].avg), (240, 50.0));
+
+        assert_eq!(store.query_at("h1", &["cpu"], 0, 3600, 300, 3600).unwrap().res, 300);
+        assert!(store.query_at("h1", &["nope"], 0, 300, 60, 300).is_err());
+    }
+
+    #[test]
+    fn test_query_open_bucket() {
+        let store = store();
+        store.record("h1", "", 120, &vals(10.0));
+        store.record("h1", "", 130, &vals(30.0));
+
+        let series = store.query_at("h1", &["cpu"], 0, 180, 60, 180).unwrap();
+        let points = &series.metrics["cpu"];
+        assert_eq!(points.len(), 1);
+        assert_eq!((points[0].ts, points[0].avg, points[0].max), (120, 20.0, 30.0));
+    }
+
+    #[test]
+    fn test_query_retention_fallback() {
+        let store = store();
+        store.record("h1", "", 60, &vals(1.0));
+        store.flush_all();
+
+        // 1m rollups live 2 days, 5m 14 days
+        let now = 15 * 24 * 3600;
+        let series = store.query_at("h1", &["cpu"], 0, 3600, 60, now).unwrap();
+        assert_eq!((series.res, series.step), (3600, 3600));
+        assert_eq!(series.metrics["cpu"].len(), 1);
+
+        assert_eq!(
+            store.query_at("h1", &["cpu"], now - 3600, now, 60, now).unwrap().res,
+            60
+        );
+    }
+
+    #[test]
+    fn test_probe_series() {
+        let store = store();
+        let probe = |name: &str, lost_rate: f64, time: f64| stat_common::server_status::ProbeResult {
+            name: name.to_string(),
+            lost_rate,
+            time,
+            ..Default::default()
+        };
+        for (ts, time) in [(0, 20.0), (30, 40.0)] {
+            let stat = HostStat {
+                name: "h1".to_string(),
+                latest_ts: ts,
+                probes: vec![probe("fra", 0.0, time), probe("ams", 10.0, 5.0)],
+                ..Default::default()
+            };
+            store.record_stat(&stat);
+        }
+        store.flush_all();
+
+        let series = store
+            .query_at("h1", &["cpu", "time_fra", "ping_ams"], 0, 60, 60, 60)
+            .unwrap();
+        assert_eq!(series.metrics["cpu"].len(), 1);
+        let p = &series.metrics["time_fra"][0];
+        assert_eq!((p.min, p.avg, p.max), (20.0, 30.0, 40.0));
+        assert_eq!(series.metrics["ping_ams"][0].avg, 10.0);
+        assert!(store.query_at("h1", &["time_"], 0, 60, 60, 60).is_err());
+        assert!(store.query_at("h1", &["p50_fra"], 0, 60, 60, 60).is_err());
+    }
+
+    #[test]
+    fn test_purge() {
+        let store = store();
+        store.record("h1", "", 60, &vals(1.0));
+        store.flush_all();
+        assert!(row(&store, 60, 60).is_some());
+
+        store.purge(60 + 3 * 24 * 3600).unwrap();
+        assert!(row(&store, 60, 60).is_none());
+        assert!(row(&store, 3600, 0).is_some());
+    }
+}
@@ -3,21 +3,34 @@ use axum::extract::{Path, Query};
 use axum::{
     body::Bytes,
     http::{header, header::HeaderMap, StatusCode, Uri},
+    response::sse::{Event as SseEvent, KeepAlive, Sse},
     response::{IntoResponse, Response},
     Json,
 };
+use futures_util::stream::{self, Stream};
 use minijinja::context;
 use prettytable::Table;
 use prost::Message;
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
 use std::collections::HashMap;
+use std::convert::Infallible;
 use std::fmt::Write as _;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use stat_common::{server_status::StatRequest, utils::bytes2human};
+use stat_common::{
+    server_status::{DiskIo, StatRequest},
+    utils::bytes2human,
+};
+use tokio::sync::broadcast::error::RecvError;
 
+use crate::alert::match_target;
 use crate::auth;
+use crate::client_config;
 use crate::jinja;
 use crate::jwt;
+use crate::metrics;
+use crate::payload::HostStat;
+use crate::silence::Silence;
 use crate::G_CONFIG;
 use crate::G_STATS_MGR;
 
@@ -30,6 +43,166 @@ pub async fn get_stats_json() -> impl IntoResponse {
     )
 }
 
+// name / gid / label 过滤, 逗号分隔
+struct LiveFilter {
+    names: Vec<String>,
+    gids: Vec<String>,
+    labels: Vec<String>,
+}
+
+impl LiveFilter {
+    fn matches(&self, stat: &HostStat) -> bool {
+        match_target(stat, &self.names, &self.gids, &self.labels)
+    }
+
+    /// also resets `sent` to the hosts in the snapshot
+    fn snapshot(&self, sent: &mut HashMap<String, Map<String, Value>>) -> SseEvent {
+        let resp = G_STATS_MGR.get().unwrap().get_stats();
+        let resp = resp.lock().unwrap();
+        sent.clear();
+        let mut servers = Vec::new();
+        for o in resp.servers.iter().filter(|o| self.matches(o)) {
+            if let Ok(Value::Object(map)) = serde_json::to_value(o) {
+                sent.insert(o.name.clone(), map.clone());
+                servers.push(Value::Object(map));
+            }
+        }
+        SseEvent::default()
+            .event("snapshot")
+            .json_data(json!({ "updated": resp.updated, "servers": servers }))
+            .unwrap_or_default()
+    }
+}
+
+/// `name` + the top level fields changed since the last event for that host, None if nothing changed
+fn host_delta(sent: &mut HashMap<String, Map<String, Value>>, stat: &HostStat) -> Option<Value> {
+    let Ok(Value::Object(cur)) = serde_json::to_value(stat) else {
+        return None;
+    };
+    let prev = sent.get(&stat.name);
+    let mut o = cur
+        .iter()
+        .filter(|(k, v)| prev.and_then(|p| p.get(*k)) != Some(*v))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect::<Map<_, _>>();
+    if o.is_empty() {
+        return None;
+    }
+    o.insert("name".to_string(), Value::from(stat.name.as_str()));
+    sent.insert(stat.name.clone(), cur);
+    Some(Value::Object(o))
+}
+
+// /sse/stats?gid=g1,g2&label=os=pi&name=h1
+// `snapshot` first (and again after lagging), then `host` on every report or offline,
+// `host` only carries `name` & the fields changed since the previous event for that host, merge it client side.
+// public like /json/stats.json, same data as the dashboard
+pub async fn sse_stats(
+    Query(params): Query<HashMap<String, String>>,
+) -> Sse<impl Stream<Item = Result<SseEvent, Infallible>>> {
+    let list = |k: &str| {
+        params
+            .get(k)
+            .map(|v| {
+                v.split(',')
+                    .map(str::trim)
+                    .filter(|o| !o.is_empty())
+                    .map(ToString::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let filter = LiveFilter {
+        names: list("name"),
+        gids: list("gid"),
+        labels: list("label"),
+    };
+    let rx = G_STATS_MGR.get().unwrap().subscribe();
+
+    let init = (rx, filter, HashMap::new(), true);
+    let stream = stream::unfold(init, |(mut rx, filter, mut sent, snapshot)| async move {
+        if snapshot {
+            let e = filter.snapshot(&mut sent);
+            return Some((Ok(e), (rx, filter, sent, false)));
+        }
+        loop {
+            match rx.recv().await {
+                Ok(stat) if filter.matches(&stat) => {
+                    let Some(delta) = host_delta(&mut sent, &stat) else {
+                        continue;
+                    };
+                    let e = SseEvent::default().event("host").json_data(delta).unwrap_or_default();
+                    return Some((Ok(e), (rx, filter, sent, false)));
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(n)) => {
+                    warn!("sse lagged {n}, resend snapshot");
+                    let e = filter.snapshot(&mut sent);
+                    return Some((Ok(e), (rx, filter, sent, false)));
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+pub async fn get_metrics() -> impl IntoResponse {
+    let offline_threshold = G_CONFIG.get().unwrap().offline_threshold;
+    let resp = G_STATS_MGR.get().unwrap().get_stats();
+    let body = metrics::render(&resp.lock().unwrap(), offline_threshold);
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        body,
+    )
+}
+
+// /api/history/{name}?metric=cpu,load_1,ping_fra,time_fra&from=..&to=..&step=..
+pub async fn get_history(Path(name): Path<String>, Query(params): Query<HashMap<String, String>>) -> Response {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let to = params.get("to").and_then(|p| p.parse::<u64>().ok()).unwrap_or(now);
+    let from = params
+        .get("from")
+        .and_then(|p| p.parse::<u64>().ok())
+        .unwrap_or_else(|| to.saturating_sub(3600));
+    let step = params.get("step").and_then(|p| p.parse::<u64>().ok()).unwrap_or(60);
+    let metrics = params
+        .get("metric")
+        .map_or("cpu", String::as_str)
+        .split(',')
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+
+    let res = tokio::task::spawn_blocking(move || {
+        let metrics = metrics.iter().map(String::as_str).collect::<Vec<_>>();
+        G_STATS_MGR
+            .get()
+            .unwrap()
+            .query_history(&name, &metrics, from, to, step)
+    })
+    .await;
+
+    match res {
+        Ok(Ok(series)) => Json(series).into_response(),
+        Ok(Err(err)) => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "code": 1, "message": err.to_string() })),
+        )
+            .into_response(),
+        Err(err) => {
+            error!("query history error => {err:?}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::INTERNAL_SERVER_ERROR.to_string(),
+            )
+                .into_response()
+        }
+    }
+}
+
 #[allow(unused)]
 pub fn get_site_config_json() -> impl IntoResponse {
     // TODO
@@ -54,6 +227,34 @@ pub async fn admin_api(_claims: jwt::Claims, Path(path): Path<String>) -> Json<V
     Json(json!({ "code": 0, "message": "ok" }))
 }
 
+pub async fn list_silences(_claims: jwt::Claims) -> Json<Value> {
+    let silences = G_STATS_MGR.get().unwrap().get_silences().list();
+    Json(json!({ "code": 0, "message": "ok", "data": silences }))
+}
+
+pub async fn add_silence(_claims: jwt::Claims, Json(silence): Json<Silence>) -> Response {
+    match G_STATS_MGR.get().unwrap().get_silences().add(silence) {
+        Ok(o) => Json(json!({ "code": 0, "message": "ok", "data": o })).into_response(),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "code": 1, "message": err.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn del_silence(_claims: jwt::Claims, Path(id): Path<String>) -> Response {
+    if G_STATS_MGR.get().unwrap().get_silences().remove(&id) {
+        Json(json!({ "code": 0, "message": "ok" })).into_response()
+    } else {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "code": 1, "message": format!("silence `{id}` not found") })),
+        )
+            .into_response()
+    }
+}
+
 #[allow(clippy::unnecessary_wraps)]
 pub fn init_jinja_tpl() -> Result<(), anyhow::Error> {
     let detail_data = Asset::get("/jinja/detail.jinja.html").expect("detail.jinja.html not found");
@@ -307,7 +508,12 @@ pub async fn get_detail(
         "IP",
         "系统信息",
         "IP信息",
-        "磁盘信息"
+        "磁盘信息",
+        "网卡",
+        "探测",
+        "服务",
+        "容器",
+        "传感器"
     ]);
     for (idx, host) in o.servers.iter().enumerate() {
         let sys_info = host
@@ -330,10 +536,31 @@ pub async fn get_detail(
             .unwrap_or_default();
 
         let mut di: String = String::new();
-        if !host.disks.is_empty() {
+        if !host.disks.is_empty() || !host.disk_io.is_empty() {
+            let io_cols = |io: Option<&DiskIo>| match io {
+                Some(o) => [
+                    format!(
+                        "{}/s / {}/s",
+                        bytes2human(o.read_rate, 2, host.si),
+                        bytes2human(o.write_rate, 2, host.si)
+                    ),
+                    format!("{} / {}", o.read_iops, o.write_iops),
+                    format!("{}%", o.util),
+                ],
+                None => Default::default(),
+            };
+            // `/dev/vda1` => `vda1`
+            let find_io = |name: &str| {
+                host.disk_io
+                    .iter()
+                    .find(|o| o.name == name || name.strip_prefix("/dev/") == Some(o.name.as_str()))
+            };
             let mut t = Table::new();
-            t.set_titles(row!["name", "mp", "fs", "total", "used", "free"]);
+            t.set_titles(row![
+                "name", "mp", "fs", "total", "used", "free", "inodes", "r/w rate", "r/w iops", "util"
+            ]);
             for disk in &host.disks {
+                let [rate, iops, util] = io_cols(find_io(&disk.name));
                 t.add_row(row![
                     disk.name,
                     disk.mount_point,
@@ -341,11 +568,145 @@ pub async fn get_detail(
                     bytes2human(disk.total, 2, host.si),
                     bytes2human(disk.used, 2, host.si),
                     bytes2human(disk.free, 2, host.si),
+                    if disk.inodes_total > 0 {
+                        format!(
+                            "{}/{} {:.0}%",
+                            disk.inodes_used,
+                            disk.inodes_total,
+                            disk.inodes_used as f64 * 100.0 / disk.inodes_total as f64
+                        )
+                    } else {
+                        String::new()
+                    },
+                    rate,
+                    iops,
+                    util,
                 ]);
             }
+            // devices without a listed mount point
+            for o in &host.disk_io {
+                if host
+                    .disks
+                    .iter()
+                    .any(|d| find_io(&d.name).is_some_and(|io| io.name == o.name))
+                {
+                    continue;
+                }
+                let [rate, iops, util] = io_cols(Some(o));
+                t.add_row(row![o.name, "", "", "", "", "", "", rate, iops, util]);
+            }
             di = t.to_string();
         }
 
+        let mut ni: String = String::new();
+        if !host.ifaces.is_empty() {
+            let mut t = Table::new();
+            t.set_titles(row![
+                "name",
+                "rx/tx rate",
+                "rx/tx total",
+                "rx/tx packets",
+                "rx/tx errs",
+                "rx/tx drop"
+            ]);
+            for o in &host.ifaces {
+                t.add_row(row![
+                    o.name,
+                    format!(
+                        "{}/s / {}/s",
+                        bytes2human(o.rx_rate, 2, host.si),
+                        bytes2human(o.tx_rate, 2, host.si)
+                    ),
+                    format!(
+                        "{} / {}",
+                        bytes2human(o.rx_bytes, 2, host.si),
+                        bytes2human(o.tx_bytes, 2, host.si)
+                    ),
+                    format!("{} / {}", o.rx_packets, o.tx_packets),
+                    format!("{} / {}", o.rx_errors, o.tx_errors),
+                    format!("{} / {}", o.rx_drop, o.tx_drop),
+                ]);
+            }
+            ni = t.to_string();
+        }
+
+        let mut pi: String = String::new();
+        if !host.probes.is_empty() {
+            let mut t = Table::new();
+            t.set_titles(row!["name", "kind", "target", "lost", "time", "p50/p90/p99", "error"]);
+            for probe in &host.probes {
+                t.add_row(row![
+                    probe.name,
+                    probe.kind,
+                    probe.target,
+                    format!("{}%", probe.lost_rate),
+                    format!("{}ms", probe.time),
+                    format!("{:.1}/{:.1}/{:.1}ms", probe.p50, probe.p90, probe.p99),
+                    probe.error,
+                ]);
+            }
+            pi = t.to_string();
+        }
+
+        let mut si: String = String::new();
+        if !host.services.is_empty() {
+            let mut t = Table::new();
+            t.set_titles(row!["name", "kind", "state", "pids", "cpu", "rss"]);
+            for o in &host.services {
+                t.add_row(row![
+                    o.name,
+                    o.kind,
+                    o.state,
+                    o.pids,
+                    format!("{}%", o.cpu),
+                    bytes2human(o.rss, 2, host.si),
+                ]);
+            }
+            si = t.to_string();
+        }
+
+        let mut ci: String = String::new();
+        if !host.containers.is_empty() {
+            let mut t = Table::new();
+            t.set_titles(row!["name", "image", "state", "restarts", "cpu", "memory", "rx / tx"]);
+            for o in &host.containers {
+                let mut memory = bytes2human(o.memory, 2, host.si);
+                if o.memory_limit > 0 {
+                    memory = format!("{memory} / {}", bytes2human(o.memory_limit, 2, host.si));
+                }
+                t.add_row(row![
+                    o.name,
+                    o.image,
+                    o.state,
+                    o.restart_count,
+                    format!("{}%", o.cpu),
+                    memory,
+                    format!(
+                        "{} / {}",
+                        bytes2human(o.net_rx, 2, host.si),
+                        bytes2human(o.net_tx, 2, host.si)
+                    ),
+                ]);
+            }
+            ci = t.to_string();
+        }
+
+        let mut ti: String = String::new();
+        if !host.sensors.is_empty() {
+            let mut t = Table::new();
+            t.set_titles(row!["name", "value", "crit"]);
+            for o in &host.sensors {
+                let value = match (o.temp, o.fan) {
+                    (Some(temp), _) => format!("{temp:.1}°C"),
+                    (None, Some(fan)) => format!("{fan} rpm"),
+                    (None, None) => String::new(),
+                };
+                let crit = o.temp_crit.map(|v| format!("{v:.0}°C")).unwrap_or_default();
+                t.add_row(row![o.name, value, crit]);
+            }
+            ti = t.to_string();
+        }
+
         if let Some(ip_info) = &host.ip_info {
             let addrs = [
                 ip_info.continent.as_str(),
@@ -380,7 +741,12 @@ pub async fn get_detail(
                 ip_info.query,
                 sys_info,
                 format!("{addrs}\n{isp}"),
-                di
+                di,
+                ni,
+                pi,
+                si,
+                ci,
+                ti
             ]);
         } else {
             table.add_row(row![
@@ -392,7 +758,12 @@ pub async fn get_detail(
                 "xx.xx.xx.xx".to_string(),
                 sys_info,
                 String::new(),
-                di
+                di,
+                ni,
+                pi,
+                si,
+                ci,
+                ti
             ]);
         }
     }
@@ -412,6 +783,29 @@ pub async fn get_detail(
         )
 }
 
+// /client/config?name=.., name is required for group clients
+pub async fn get_client_config(
+    auth::HostAuth(auth): auth::HostAuth,
+    req_header: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let group = req_header.get("ssr-auth").is_some_and(|v| v.as_bytes().eq(b"group"));
+    let (name, gid) = if group {
+        (params.get("name").map_or("", String::as_str), auth.username.as_str())
+    } else {
+        (auth.username.as_str(), "")
+    };
+
+    match client_config::resolve(&G_CONFIG.get().unwrap(), name, gid) {
+        Some(o) => Json(o).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"code": 1, "message": "host not found"})),
+        )
+            .into_response(),
+    }
+}
+
 // report
 pub async fn report(_auth: auth::HostAuth, req_header: HeaderMap, body: Bytes) -> impl IntoResponse {
     let mut json_data: Option<serde_json::Value> = None;
@@ -450,7 +844,7 @@ pub async fn report(_auth: auth::HostAuth, req_header: HeaderMap, body: Bytes) -
     }
 
     if let Some(mgr) = G_STATS_MGR.get() {
-        if mgr.report(json_data.unwrap()).is_err() {
+        if mgr.report(json_data.unwrap(), 0).is_err() {
             return StatusCode::BAD_REQUEST;
         }
     }
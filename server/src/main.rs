@@ -8,6 +8,7 @@ extern crate prettytable;
 
 use clap::Parser;
 use once_cell::sync::OnceCell;
+use std::fs;
 use std::process;
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -20,23 +21,33 @@ use tokio::signal;
 use axum::{
     http::{Method, Uri},
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post, put},
     Router,
 };
 use tower_http::cors::{Any, CorsLayer};
 
+use crate::config::{Host, HostGroup};
+
+mod admin;
+mod alert;
 mod assets;
 mod auth;
+mod client_config;
 mod config;
+mod credential;
 mod grpc;
+mod history;
 mod http;
 mod jinja;
 mod jwt;
+mod metrics;
+mod monitor;
 mod notifier;
 mod payload;
+mod silence;
 mod stats;
 
-static G_CONFIG: OnceCell<crate::config::Config> = OnceCell::new();
+static G_CONFIG: crate::config::ConfigCell = crate::config::ConfigCell::new();
 static G_STATS_MGR: OnceCell<crate::stats::StatsMgr> = OnceCell::new();
 
 #[derive(Parser, Debug)]
@@ -50,18 +61,57 @@ struct Args {
     notify_test: bool,
     #[arg(long = "cloud", help = "cloud mode, load cfg from env var: SRV_CONF")]
     cloud: bool,
+    #[arg(
+        long = "hash-password",
+        help = "print the argon2 hash of a password for config.toml, `-` read from stdin"
+    )]
+    hash_password: Option<String>,
 }
 
 fn create_app_router() -> Router {
     let cors_layer = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST])
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
         .allow_origin(Any);
 
     Router::new()
         .route("/report", post(http::report))
+        .route("/client/config", get(http::get_client_config))
         .route("/json/stats.json", get(http::get_stats_json)) // 兼容就旧主题
+        .route("/sse/stats", get(http::sse_stats))
+        .route("/api/history/{name}", get(http::get_history))
+        .route("/metrics", get(http::get_metrics))
         // .route("/config.pub.json", get(http::get_site_config_json)) // TODO
         .route("/api/admin/authorize", post(jwt::authorize))
+        .route("/api/admin/silences", get(http::list_silences).post(http::add_silence))
+        .route("/api/admin/silences/{id}", delete(http::del_silence))
+        .route("/api/admin/hosts", get(admin::list::<Host>).post(admin::create::<Host>))
+        .route(
+            "/api/admin/hosts/{name}",
+            put(admin::update::<Host>).delete(admin::delete::<Host>),
+        )
+        .route("/api/admin/hosts/{name}/password", post(admin::rotate_password::<Host>))
+        .route("/api/admin/hosts/{name}/tokens", post(admin::create_token::<Host>))
+        .route(
+            "/api/admin/hosts/{name}/tokens/{id}",
+            delete(admin::revoke_token::<Host>),
+        )
+        .route(
+            "/api/admin/groups",
+            get(admin::list::<HostGroup>).post(admin::create::<HostGroup>),
+        )
+        .route(
+            "/api/admin/groups/{gid}",
+            put(admin::update::<HostGroup>).delete(admin::delete::<HostGroup>),
+        )
+        .route(
+            "/api/admin/groups/{gid}/password",
+            post(admin::rotate_password::<HostGroup>),
+        )
+        .route("/api/admin/groups/{gid}/tokens", post(admin::create_token::<HostGroup>))
+        .route(
+            "/api/admin/groups/{gid}/tokens/{id}",
+            delete(admin::revoke_token::<HostGroup>),
+        )
         .route("/api/admin/{path}", get(http::admin_api)) // stats.json || config.json
         // .route("/admin", get(assets::admin_index_handler))
         .route("/detail", get(http::get_detail))
@@ -102,6 +152,39 @@ pub async fn shutdown_signal() {
     println!("signal received, starting graceful shutdown");
 }
 
+/// reload on SIGHUP, or on mtime change when `auto_reload` is on
+async fn watch_config(path: String, auto_reload: bool) {
+    #[cfg(unix)]
+    let mut hup = signal::unix::signal(signal::unix::SignalKind::hangup()).expect("failed to install signal handler");
+    let modified = || fs::metadata(&path).and_then(|m| m.modified()).ok();
+    let mut mtime = modified();
+    let mut ticker = tokio::time::interval(Duration::from_secs(5));
+
+    loop {
+        let hangup = async {
+            #[cfg(unix)]
+            hup.recv().await;
+            #[cfg(not(unix))]
+            std::future::pending::<()>().await;
+        };
+        tokio::select! {
+            () = hangup => {},
+            _ = ticker.tick() => {
+                if !auto_reload || modified() == mtime {
+                    continue;
+                }
+            },
+        }
+        mtime = modified();
+
+        match tokio::task::spawn_blocking(config::reload).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => error!("reload config `{path}` fail, keep the old one => {err:?}"),
+            Err(err) => error!("reload config `{path}` panic => {err:?}"),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
     pretty_env_logger::init();
@@ -109,6 +192,17 @@ async fn main() -> Result<(), anyhow::Error> {
 
     eprintln!("✨ {} {}", env!("CARGO_BIN_NAME"), env!("APP_VERSION"));
 
+    // hash password
+    if let Some(mut pass) = args.hash_password {
+        if pass == "-" {
+            pass = String::new();
+            std::io::stdin().read_line(&mut pass)?;
+            pass = pass.trim_end_matches(['\r', '\n']).to_string();
+        }
+        println!("{}", credential::hash_password(&pass)?);
+        process::exit(0);
+    }
+
     // config test
     if args.config_test {
         config::test_from_file(&args.config).unwrap();
@@ -118,7 +212,7 @@ async fn main() -> Result<(), anyhow::Error> {
     }
 
     // config load
-    if let Some(cfg) = if args.cloud {
+    match if args.cloud {
         // export SRV_CONF=$(cat config.toml)
         // echo "$SRV_CONF"
         eprintln!("✨ run in cloud mode, load config from env");
@@ -127,11 +221,14 @@ async fn main() -> Result<(), anyhow::Error> {
         eprintln!("✨ run in normal mode, load conf from local file `{}", &args.config);
         config::from_file(&args.config)
     } {
-        debug!("{}", serde_json::to_string_pretty(&cfg).unwrap());
-        G_CONFIG.set(cfg).unwrap();
-    } else {
-        error!("can't parse config");
-        process::exit(1);
+        Ok(cfg) => {
+            debug!("{}", serde_json::to_string_pretty(&cfg).unwrap());
+            G_CONFIG.set(cfg);
+        }
+        Err(err) => {
+            error!("can't parse config => {err:?}");
+            process::exit(1);
+        }
     }
 
     // init tpl
@@ -140,27 +237,8 @@ async fn main() -> Result<(), anyhow::Error> {
     // init notifier
     *notifier::NOTIFIER_HANDLE.lock().unwrap() = Some(Handle::current());
     let cfg = G_CONFIG.get().unwrap();
-    let notifies: Arc<Mutex<Vec<Box<dyn notifier::Notifier + Send>>>> = Arc::new(Mutex::new(Vec::new()));
-    if cfg.tgbot.enabled {
-        let o = Box::new(notifier::tgbot::TGBot::new(&cfg.tgbot));
-        notifies.lock().unwrap().push(o);
-    }
-    if cfg.wechat.enabled {
-        let o = Box::new(notifier::wechat::WeChat::new(&cfg.wechat));
-        notifies.lock().unwrap().push(o);
-    }
-    if cfg.email.enabled {
-        let o = Box::new(notifier::email::Email::new(&cfg.email));
-        notifies.lock().unwrap().push(o);
-    }
-    if cfg.log.enabled {
-        let o = Box::new(notifier::log::Log::new(&cfg.log));
-        notifies.lock().unwrap().push(o);
-    }
-    if cfg.webhook.enabled {
-        let o = Box::new(notifier::webhook::Webhook::new(&cfg.webhook));
-        notifies.lock().unwrap().push(o);
-    }
+    let notifies: Arc<Mutex<Vec<Box<dyn notifier::Notifier + Send>>>> =
+        Arc::new(Mutex::new(notifier::init_notifiers(&cfg)));
     // init notifier end
 
     // notify test
@@ -176,21 +254,44 @@ async fn main() -> Result<(), anyhow::Error> {
 
     // init mgr
     let mut mgr = crate::stats::StatsMgr::new();
-    mgr.init(G_CONFIG.get().unwrap(), notifies)?;
+    mgr.init(&cfg, notifies)?;
     if G_STATS_MGR.set(mgr).is_err() {
         error!("can't set G_STATS_MGR");
         process::exit(1);
     }
 
     // serv grpc
-    tokio::spawn(async move { grpc::serv_grpc(cfg).await });
+    tokio::spawn({
+        let cfg = Arc::clone(&cfg);
+        async move { grpc::serv_grpc(&cfg).await }
+    });
+
+    // server side checks
+    tokio::spawn(monitor::serv_monitor());
+
+    // config reload
+    if cfg.config_file.is_empty() {
+        // SRV_CONF can't change under a running process, admin api edits to `hosts_file` still reload
+        warn!("run in cloud mode, SIGHUP & `auto_reload` are unavailable, restart to apply `SRV_CONF` changes");
+    } else {
+        tokio::spawn(watch_config(cfg.config_file.clone(), cfg.auto_reload));
+    }
 
     let http_addr = cfg.http_addr.clone();
+    // don't pin the startup snapshot, reloads replace it
+    drop(cfg);
     eprintln!("🚀 listening on http://{http_addr}");
 
     let listener = TcpListener::bind(&http_addr).await.unwrap();
     axum::serve(listener, create_app_router())
-        .with_graceful_shutdown(shutdown_signal())
+        .with_graceful_shutdown(async {
+            shutdown_signal().await;
+            // open 1m/5m/1h buckets, sse clients may hold the graceful shutdown
+            let flush = tokio::task::spawn_blocking(|| G_STATS_MGR.get().unwrap().flush_history());
+            if let Err(err) = flush.await {
+                error!("flush history fail => {err:?}");
+            }
+        })
         .await
         .unwrap();
 
@@ -0,0 +1,619 @@
+#![deny(warnings)]
+#![allow(clippy::cast_precision_loss)]
+use stat_common::server_status::{ContainerInfo, DiskInfo, Psi, SensorInfo, ServiceInfo};
+use std::fmt::Write as _;
+
+use crate::payload::{HostStat, StatsResp};
+
+const BASE_LABELS: [&str; 5] = ["name", "alias", "gid", "location", "type"];
+
+fn escape(v: &str) -> String {
+    v.replace('\\', r"\\").replace('"', r#"\""#).replace('\n', r"\n")
+}
+
+// prometheus label name: [a-zA-Z_][a-zA-Z0-9_]*
+fn sanitize(k: &str) -> String {
+    let mut s = k
+        .trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if s.starts_with(|c: char| c.is_ascii_digit()) {
+        s.insert(0, '_');
+    }
+    s
+}
+
+/// `name`, `alias`, `gid`, `location`, `type` and the `k=v;` pairs of host labels
+pub fn host_labels(stat: &HostStat) -> String {
+    let mut pairs = vec![
+        ("name".to_string(), stat.name.as_str()),
+        ("alias".to_string(), stat.alias.as_str()),
+        ("gid".to_string(), stat.gid.as_str()),
+        ("location".to_string(), stat.location.as_str()),
+        ("type".to_string(), stat.host_type.as_str()),
+    ];
+    for kv in stat.labels.split(';') {
+        if let Some((k, v)) = kv.split_once('=') {
+            let mut k = sanitize(k);
+            if k.is_empty() || k.starts_with("__") {
+                continue;
+            }
+            if BASE_LABELS.contains(&k.as_str()) {
+                k = format!("label_{k}");
+            }
+            if pairs.iter().any(|(o, _)| o.eq(&k)) {
+                continue;
+            }
+            pairs.push((k, v.trim()));
+        }
+    }
+
+    pairs
+        .iter()
+        .map(|(k, v)| format!(r#"{k}="{}""#, escape(v)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+struct Family<'a> {
+    out: &'a mut String,
+    hosts: &'a [(String, &'a HostStat)],
+}
+
+impl Family<'_> {
+    fn write<F>(&mut self, name: &str, kind: &str, help: &str, f: F)
+    where
+        F: Fn(&HostStat) -> Vec<(String, f64)>,
+    {
+        let _ = writeln!(self.out, "# HELP {name} {help}");
+        let _ = writeln!(self.out, "# TYPE {name} {kind}");
+        for (labels, stat) in self.hosts {
+            for (extra, v) in f(stat) {
+                if extra.is_empty() {
+                    let _ = writeln!(self.out, "{name}{{{labels}}} {v}");
+                } else {
+                    let _ = writeln!(self.out, "{name}{{{labels},{extra}}} {v}");
+                }
+            }
+        }
+    }
+
+    fn gauge<F>(&mut self, name: &str, help: &str, f: F)
+    where
+        F: Fn(&HostStat) -> f64,
+    {
+        self.write(name, "gauge", help, |o| vec![(String::new(), f(o))]);
+    }
+
+    fn counter<F>(&mut self, name: &str, help: &str, f: F)
+    where
+        F: Fn(&HostStat) -> f64,
+    {
+        self.write(name, "counter", help, |o| vec![(String::new(), f(o))]);
+    }
+}
+
+fn hdd_bytes(stat: &HostStat, v: u64) -> f64 {
+    // MB (si) or MiB
+    let unit: u64 = if stat.si { 1000 * 1000 } else { 1024 * 1024 };
+    (v * unit) as f64
+}
+
+fn disk_labels(d: &DiskInfo) -> String {
+    format!(
+        r#"device="{}",mountpoint="{}",fstype="{}""#,
+        escape(&d.name),
+        escape(&d.mount_point),
+        escape(&d.file_system)
+    )
+}
+
+// (label, lost rate %, time ms), clients without `probes` only report the carrier probes
+fn probes(o: &HostStat) -> Vec<(String, f64, f64)> {
+    if o.probes.is_empty() {
+        return vec![
+            (r#"probe="10010""#.to_string(), o.ping_10010, o.time_10010),
+            (r#"probe="189""#.to_string(), o.ping_189, o.time_189),
+            (r#"probe="10086""#.to_string(), o.ping_10086, o.time_10086),
+        ];
+    }
+    o.probes
+        .iter()
+        .map(|p| (format!(r#"probe="{}""#, escape(&p.name)), p.lost_rate, p.time))
+        .collect()
+}
+
+fn service_labels(s: &ServiceInfo) -> String {
+    format!(r#"service="{}",kind="{}""#, escape(&s.name), escape(&s.kind))
+}
+
+fn container_labels(c: &ContainerInfo) -> String {
+    format!(r#"container="{}",image="{}""#, escape(&c.name), escape(&c.image))
+}
+
+fn sensors(o: &HostStat, value: impl Fn(&SensorInfo) -> Option<f64>) -> Vec<(String, f64)> {
+    o.sensors
+        .iter()
+        .filter_map(|s| Some((format!(r#"sensor="{}""#, escape(&s.name)), value(s)?)))
+        .collect()
+}
+
+fn psi(o: &HostStat) -> impl Iterator<Item = (&'static str, &Psi)> {
+    [("cpu", &o.psi_cpu), ("memory", &o.psi_memory), ("io", &o.psi_io)]
+        .into_iter()
+        .filter_map(|(resource, p)| Some((resource, p.as_ref()?)))
+}
+
+fn b2f(b: bool) -> f64 {
+    if b {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+pub fn render(resp: &StatsResp, offline_threshold: u64) -> String {
+    let now = resp.updated;
+    let hosts = resp
+        .servers
+        .iter()
+        .map(|o| (host_labels(o), o.as_ref()))
+        .collect::<Vec<_>>();
+
+    let mut out = String::new();
+    let mut fam = Family {
+        out: &mut out,
+        hosts: &hosts,
+    };
+
+    fam.gauge(
+        "ssr_online",
+        "Whether the host reported within offline_threshold",
+        |o| b2f(o.latest_ts + offline_threshold >= now && (o.online4 || o.online6)),
+    );
+    fam.gauge("ssr_online4", "IPv4 online", |o| b2f(o.online4));
+    fam.gauge("ssr_online6", "IPv6 online", |o| b2f(o.online6));
+    fam.gauge("ssr_notify", "Whether notifications are enabled", |o| b2f(o.notify));
+    fam.gauge("ssr_vnstat", "Whether traffic comes from vnstat", |o| b2f(o.vnstat));
+    fam.gauge("ssr_weight", "Rank weight", |o| o.weight as f64);
+    fam.gauge(
+        "ssr_last_report_timestamp_seconds",
+        "Unix time of the latest report",
+        |o| o.latest_ts as f64,
+    );
+    fam.gauge("ssr_uptime_seconds", "Host uptime", |o| o.uptime as f64);
+
+    fam.gauge("ssr_load1", "1m load average", |o| o.load_1);
+    fam.gauge("ssr_load5", "5m load average", |o| o.load_5);
+    fam.gauge("ssr_load15", "15m load average", |o| o.load_15);
+    fam.gauge("ssr_cpu_percent", "CPU usage percent", |o| o.cpu);
+    fam.write("ssr_cpu_core_percent", "gauge", "CPU usage percent of a core", |o| {
+        o.cpu_cores
+            .iter()
+            .enumerate()
+            .map(|(idx, v)| (format!(r#"core="{idx}""#), *v))
+            .collect()
+    });
+    fam.write("ssr_cpu_mode_percent", "gauge", "CPU time percent by mode", |o| {
+        o.cpu_times
+            .map(|t| {
+                [
+                    ("user", t.user),
+                    ("nice", t.nice),
+                    ("system", t.system),
+                    ("idle", t.idle),
+                    ("iowait", t.iowait),
+                    ("irq", t.irq),
+                    ("softirq", t.softirq),
+                    ("steal", t.steal),
+                ]
+                .map(|(mode, v)| (format!(r#"mode="{mode}""#), v))
+                .to_vec()
+            })
+            .unwrap_or_default()
+    });
+    fam.write(
+        "ssr_pressure_percent",
+        "gauge",
+        "Pressure stall information, percent of time some or all tasks stalled",
+        |o| {
+            psi(o)
+                .flat_map(|(resource, p)| {
+                    [
+                        ("some", "10", p.some_avg10),
+                        ("some", "60", p.some_avg60),
+                        ("some", "300", p.some_avg300),
+                        ("full", "10", p.full_avg10),
+                        ("full", "60", p.full_avg60),
+                        ("full", "300", p.full_avg300),
+                    ]
+                    .map(|(kind, window, v)| (format!(r#"resource="{resource}",kind="{kind}",window="{window}s""#), v))
+                })
+                .collect()
+        },
+    );
+    fam.write(
+        "ssr_pressure_stalled_seconds_total",
+        "counter",
+        "Total time some or all tasks stalled",
+        |o| {
+            psi(o)
+                .flat_map(|(resource, p)| {
+                    [("some", p.some_total), ("full", p.full_total)]
+                        .map(|(kind, v)| (format!(r#"resource="{resource}",kind="{kind}""#), v as f64 / 1e6))
+                })
+                .collect()
+        },
+    );
+    fam.write(
+        "ssr_oom_kills_total",
+        "counter",
+        "OOM killer invocations since boot",
+        |o| o.oom_kill.map(|v| vec![(String::new(), v as f64)]).unwrap_or_default(),
+    );
+
+    fam.gauge("ssr_memory_total_bytes", "Total memory", |o| {
+        (o.memory_total * 1024) as f64
+    });
+    fam.gauge("ssr_memory_used_bytes", "Used memory", |o| {
+        (o.memory_used * 1024) as f64
+    });
+    fam.gauge("ssr_swap_total_bytes", "Total swap", |o| (o.swap_total * 1024) as f64);
+    fam.gauge("ssr_swap_used_bytes", "Used swap", |o| (o.swap_used * 1024) as f64);
+    fam.gauge("ssr_disk_total_bytes", "Total disk", |o| hdd_bytes(o, o.hdd_total));
+    fam.gauge("ssr_disk_used_bytes", "Used disk", |o| hdd_bytes(o, o.hdd_used));
+    fam.write("ssr_mount_total_bytes", "gauge", "Total size of a mount point", |o| {
+        o.disks.iter().map(|d| (disk_labels(d), d.total as f64)).collect()
+    });
+    fam.write("ssr_mount_used_bytes", "gauge", "Used size of a mount point", |o| {
+        o.disks.iter().map(|d| (disk_labels(d), d.used as f64)).collect()
+    });
+    fam.write(
+        "ssr_mount_inodes_total",
+        "gauge",
+        "Total inodes of a mount point",
+        |o| {
+            o.disks
+                .iter()
+                .filter(|d| d.inodes_total > 0)
+                .map(|d| (disk_labels(d), d.inodes_total as f64))
+                .collect()
+        },
+    );
+    fam.write("ssr_mount_inodes_used", "gauge", "Used inodes of a mount point", |o| {
+        o.disks
+            .iter()
+            .filter(|d| d.inodes_total > 0)
+            .map(|d| (disk_labels(d), d.inodes_used as f64))
+            .collect()
+    });
+
+    fam.counter("ssr_network_in_bytes_total", "Received bytes since boot", |o| {
+        o.network_in as f64
+    });
+    fam.counter("ssr_network_out_bytes_total", "Transmitted bytes since boot", |o| {
+        o.network_out as f64
+    });
+    fam.gauge(
+        "ssr_network_month_in_bytes",
+        "Received bytes of the current month",
+        |o| o.network_in.saturating_sub(o.last_network_in) as f64,
+    );
+    fam.gauge(
+        "ssr_network_month_out_bytes",
+        "Transmitted bytes of the current month",
+        |o| o.network_out.saturating_sub(o.last_network_out) as f64,
+    );
+    fam.gauge("ssr_network_rx_bytes_per_second", "Receive rate", |o| {
+        o.network_rx as f64
+    });
+    fam.gauge("ssr_network_tx_bytes_per_second", "Transmit rate", |o| {
+        o.network_tx as f64
+    });
+
+    fam.gauge("ssr_tcp_connections", "TCP connections", |o| f64::from(o.tcp_count));
+    fam.write("ssr_tcp_sockets", "gauge", "TCP sockets by state", |o| {
+        o.tcp_states
+            .map(|t| {
+                [
+                    ("established", t.established),
+                    ("syn_sent", t.syn_sent),
+                    ("syn_recv", t.syn_recv),
+                    ("fin_wait1", t.fin_wait1),
+                    ("fin_wait2", t.fin_wait2),
+                    ("time_wait", t.time_wait),
+                    ("close", t.close),
+                    ("close_wait", t.close_wait),
+                    ("last_ack", t.last_ack),
+                    ("listen", t.listen),
+                    ("closing", t.closing),
+                ]
+                .map(|(state, v)| (format!(r#"state="{state}""#), f64::from(v)))
+                .to_vec()
+            })
+            .unwrap_or_default()
+    });
+    fam.gauge("ssr_udp_connections", "UDP connections", |o| f64::from(o.udp_count));
+    fam.gauge("ssr_processes", "Processes", |o| f64::from(o.process_count));
+    fam.gauge("ssr_threads", "Threads", |o| f64::from(o.thread_count));
+
+    fam.write("ssr_ping_loss_ratio", "gauge", "Probe packet loss ratio", |o| {
+        probes(o)
+            .into_iter()
+            .map(|(label, lost, _)| (label, lost / 100.0))
+            .collect()
+    });
+    fam.write("ssr_ping_latency_seconds", "gauge", "Probe latency", |o| {
+        probes(o)
+            .into_iter()
+            .map(|(label, _, time)| (label, time / 1000.0))
+            .collect()
+    });
+    fam.write(
+        "ssr_probe_latency_quantile_seconds",
+        "gauge",
+        "Probe latency percentiles of the last 100 rounds",
+        |o| {
+            o.probes
+                .iter()
+                .flat_map(|p| {
+                    let label = format!(r#"probe="{}",kind="{}""#, escape(&p.name), escape(&p.kind));
+                    [("0.5", p.p50), ("0.9", p.p90), ("0.99", p.p99)]
+                        .map(|(q, v)| (format!(r#"{label},quantile="{q}""#), v / 1000.0))
+                })
+                .collect()
+        },
+    );
+    fam.write(
+        "ssr_probe_tls_handshake_seconds",
+        "gauge",
+        "Probe tls handshake time",
+        |o| {
+            o.probes
+                .iter()
+                .filter(|p| p.tls_time > 0.0)
+                .map(|p| (format!(r#"probe="{}""#, escape(&p.name)), p.tls_time / 1000.0))
+                .collect()
+        },
+    );
+
+    fam.write("ssr_service_up", "gauge", "Whether a watched service is running", |o| {
+        o.services.iter().map(|s| (service_labels(s), b2f(s.running))).collect()
+    });
+    fam.write("ssr_service_pids", "gauge", "Processes of a watched service", |o| {
+        o.services
+            .iter()
+            .map(|s| (service_labels(s), f64::from(s.pids)))
+            .collect()
+    });
+    fam.write(
+        "ssr_service_cpu_percent",
+        "gauge",
+        "CPU usage percent of a watched service",
+        |o| o.services.iter().map(|s| (service_labels(s), s.cpu)).collect(),
+    );
+    fam.write("ssr_service_memory_bytes", "gauge", "RSS of a watched service", |o| {
+        o.services.iter().map(|s| (service_labels(s), s.rss as f64)).collect()
+    });
+
+    fam.write("ssr_container_up", "gauge", "Whether a container is running", |o| {
+        o.containers
+            .iter()
+            .map(|c| (container_labels(c), b2f(c.running)))
+            .collect()
+    });
+    fam.write(
+        "ssr_container_cpu_percent",
+        "gauge",
+        "CPU usage percent of a container, 100 per core",
+        |o| o.containers.iter().map(|c| (container_labels(c), c.cpu)).collect(),
+    );
+    fam.write(
+        "ssr_container_memory_bytes",
+        "gauge",
+        "Memory usage of a container, page cache excluded",
+        |o| {
+            o.containers
+                .iter()
+                .map(|c| (container_labels(c), c.memory as f64))
+                .collect()
+        },
+    );
+    fam.write(
+        "ssr_container_memory_limit_bytes",
+        "gauge",
+        "Memory limit of a container",
+        |o| {
+            o.containers
+                .iter()
+                .filter(|c| c.memory_limit > 0)
+                .map(|c| (container_labels(c), c.memory_limit as f64))
+                .collect()
+        },
+    );
+    fam.write(
+        "ssr_container_network_receive_bytes_total",
+        "counter",
+        "Bytes received by a container",
+        |o| {
+            o.containers
+                .iter()
+                .map(|c| (container_labels(c), c.net_rx as f64))
+                .collect()
+        },
+    );
+    fam.write(
+        "ssr_container_network_transmit_bytes_total",
+        "counter",
+        "Bytes sent by a container",
+        |o| {
+            o.containers
+                .iter()
+                .map(|c| (container_labels(c), c.net_tx as f64))
+                .collect()
+        },
+    );
+    fam.write(
+        "ssr_container_restarts_total",
+        "counter",
+        "Restarts of a container",
+        |o| {
+            o.containers
+                .iter()
+                .map(|c| (container_labels(c), f64::from(c.restart_count)))
+                .collect()
+        },
+    );
+
+    fam.write(
+        "ssr_sensor_temperature_celsius",
+        "gauge",
+        "Hardware sensor temperature",
+        |o| sensors(o, |s| s.temp),
+    );
+    fam.write(
+        "ssr_sensor_temperature_critical_celsius",
+        "gauge",
+        "Critical temperature of a hardware sensor",
+        |o| sensors(o, |s| s.temp_crit),
+    );
+    fam.write("ssr_sensor_fan_rpm", "gauge", "Fan speed", |o| {
+        sensors(o, |s| s.fan.map(f64::from))
+    });
+
+    fam.write("ssr_host_info", "gauge", "Host system info", |o| {
+        o.sys_info
+            .as_ref()
+            .map(|s| {
+                vec![(
+                    format!(
+                        r#"version="{}",os_name="{}",os_arch="{}",os_release="{}",kernel_version="{}",cpu_brand="{}",cpu_num="{}""#,
+                        escape(&s.version),
+                        escape(&s.os_name),
+                        escape(&s.os_arch),
+                        escape(&s.os_release),
+                        escape(&s.kernel_version),
+                        escape(&s.cpu_brand),
+                        s.cpu_num
+                    ),
+                    1.0,
+                )]
+            })
+            .unwrap_or_default()
+    });
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stat_common::server_status::{CpuTimes, ProbeResult, Psi, SensorInfo, TcpStates};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_host_labels() {
+        let stat = HostStat {
+            name: "h1".to_string(),
+            alias: r#"n"1"#.to_string(),
+            labels: "os=pi;ndd=2022/11/25;spec=2C/4G;name=x;1k=v;".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            host_labels(&stat),
+            r#"name="h1",alias="n\"1",gid="",location="",type="",os="pi",ndd="2022/11/25",spec="2C/4G",label_name="x",_1k="v""#
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let mut resp = StatsResp::new();
+        resp.servers.push(Arc::new(HostStat {
+            name: "h1".to_string(),
+            online4: true,
+            latest_ts: resp.updated,
+            cpu: 12.0,
+            cpu_cores: vec![10.0, 14.0],
+            cpu_times: Some(CpuTimes {
+                steal: 3.5,
+                ..Default::default()
+            }),
+            ping_10010: 5.0,
+            psi_memory: Some(Psi {
+                full_avg10: 12.5,
+                full_total: 2_500_000,
+                ..Default::default()
+            }),
+            oom_kill: Some(3),
+            tcp_states: Some(TcpStates {
+                time_wait: 120,
+                ..Default::default()
+            }),
+            sensors: vec![
+                SensorInfo {
+                    name: "coretemp/Package id 0".to_string(),
+                    temp: Some(45.5),
+                    ..Default::default()
+                },
+                SensorInfo {
+                    name: "nct6775/fan2".to_string(),
+                    fan: Some(1200),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }));
+        let out = render(&resp, 30);
+        assert!(out.contains("# TYPE ssr_network_in_bytes_total counter\n"));
+        assert!(out.contains(r#"ssr_online{name="h1",alias="",gid="",location="",type=""} 1"#));
+        assert!(out.contains(r#"ssr_cpu_percent{name="h1",alias="",gid="",location="",type=""} 12"#));
+        assert!(out.contains(r#"ssr_cpu_core_percent{name="h1",alias="",gid="",location="",type="",core="1"} 14"#));
+        assert!(out.contains(r#"ssr_cpu_mode_percent{name="h1",alias="",gid="",location="",type="",mode="steal"} 3.5"#));
+        assert!(out.contains(
+            r#"ssr_pressure_percent{name="h1",alias="",gid="",location="",type="",resource="memory",kind="full",window="10s"} 12.5"#
+        ));
+        assert!(out.contains(
+            r#"ssr_pressure_stalled_seconds_total{name="h1",alias="",gid="",location="",type="",resource="memory",kind="full"} 2.5"#
+        ));
+        assert!(!out.contains(r#"resource="cpu""#));
+        assert!(out.contains(r#"ssr_tcp_sockets{name="h1",alias="",gid="",location="",type="",state="time_wait"} 120"#));
+        assert!(out.contains(r#"ssr_oom_kills_total{name="h1",alias="",gid="",location="",type=""} 3"#));
+        assert!(out.contains(
+            r#"ssr_sensor_temperature_celsius{name="h1",alias="",gid="",location="",type="",sensor="coretemp/Package id 0"} 45.5"#
+        ));
+        assert!(out.contains(
+            r#"ssr_sensor_fan_rpm{name="h1",alias="",gid="",location="",type="",sensor="nct6775/fan2"} 1200"#
+        ));
+        assert!(!out.contains(
+            r#"ssr_sensor_temperature_celsius{name="h1",alias="",gid="",location="",type="",sensor="nct6775"#
+        ));
+        assert!(
+            out.contains(r#"ssr_ping_loss_ratio{name="h1",alias="",gid="",location="",type="",probe="10010"} 0.05"#)
+        );
+
+        resp.servers.push(Arc::new(HostStat {
+            name: "h2".to_string(),
+            probes: vec![ProbeResult {
+                name: "fra".to_string(),
+                kind: "http".to_string(),
+                time: 25.0,
+                p90: 30.0,
+                tls_time: 5.0,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }));
+        let out = render(&resp, 30);
+        assert!(out
+            .contains(r#"ssr_ping_latency_seconds{name="h2",alias="",gid="",location="",type="",probe="fra"} 0.025"#));
+        assert!(!out.contains(r#"name="h2",alias="",gid="",location="",type="",probe="10010""#));
+        assert!(out.contains(
+            r#"ssr_probe_latency_quantile_seconds{name="h2",alias="",gid="",location="",type="",probe="fra",kind="http",quantile="0.9"} 0.03"#
+        ));
+        assert!(out.contains(
+            r#"ssr_probe_tls_handshake_seconds{name="h2",alias="",gid="",location="",type="",probe="fra"} 0.005"#
+        ));
+    }
+}
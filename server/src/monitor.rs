@@ -0,0 +1,336 @@
+#![deny(warnings)]
+// `[[monitor]]`, http/tcp endpoints checked by the server itself, shown as pseudo hosts
+// a failed check reports the host offline, NodeDown/NodeUp go through the usual notifiers
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time;
+use url::Url;
+
+use stat_common::server_status::ProbeResult;
+
+use crate::config::{Config, Host};
+use crate::payload::HostStat;
+
+const WINDOW: usize = 100;
+// same cap as the client http probe
+const MAX_BODY: usize = 256 * 1024;
+
+fn default_as_true() -> bool {
+    true
+}
+fn default_interval() -> u64 {
+    10
+}
+fn default_timeout() -> u64 {
+    5
+}
+fn default_type() -> String {
+    "monitor".to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Monitor {
+    pub name: String,
+    // http(s)://host/path#status=200&body=ok, tcp://host:port
+    pub target: String,
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    #[serde(default = "Default::default")]
+    pub alias: String,
+    #[serde(default = "Default::default")]
+    pub location: String,
+    #[serde(default = "default_type")]
+    pub r#type: String,
+    #[serde(default = "Default::default")]
+    pub labels: String,
+    #[serde(default = "default_as_true")]
+    pub notify: bool,
+    #[serde(default = "Default::default")]
+    pub disabled: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Check {
+    Http {
+        url: Url,
+        status: Option<u16>,
+        body: Option<String>,
+    },
+    Tcp {
+        addr: String,
+    },
+}
+
+impl Check {
+    fn parse(target: &str) -> Result<Self> {
+        if let Some(addr) = target.strip_prefix("tcp://") {
+            if addr
+                .rsplit_once(':')
+                .is_none_or(|(_, port)| port.parse::<u16>().is_err())
+            {
+                bail!("expect tcp://host:port");
+            }
+            return Ok(Self::Tcp { addr: addr.to_string() });
+        }
+        let mut url = Url::parse(target)?;
+        if !matches!(url.scheme(), "http" | "https") {
+            bail!("unsupported scheme `{}`", url.scheme());
+        }
+        let (mut status, mut body) = (None, None);
+        for (k, v) in url::form_urlencoded::parse(url.fragment().unwrap_or_default().as_bytes()) {
+            match k.as_ref() {
+                "status" => status = Some(v.parse()?),
+                "body" if v.is_empty() => bail!("empty http body expectation"),
+                "body" => body = Some(v.to_string()),
+                _ => bail!("unknown http expectation `{k}`"),
+            }
+        }
+        url.set_fragment(None);
+        Ok(Self::Http { url, status, body })
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Http { .. } => "http",
+            Self::Tcp { .. } => "tcp",
+        }
+    }
+
+    async fn run(&self, client: &reqwest::Client, timeout: Duration) -> Result<()> {
+        match self {
+            Self::Tcp { addr } => {
+                time::timeout(timeout, TcpStream::connect(addr))
+                    .await
+                    .map_err(|_| anyhow!("tcp connect timeout"))??;
+            }
+            Self::Http { url, status, body } => {
+                let resp = client.get(url.clone()).timeout(timeout).send().await?;
+                let code = resp.status().as_u16();
+                match status {
+                    Some(expect) if code != *expect => bail!("http status {code}, expect {expect}"),
+                    None if !(200..400).contains(&code) => bail!("http status {code}"),
+                    _ => {}
+                }
+                if let Some(expect) = body {
+                    let mut resp = resp;
+                    let mut buf = Vec::new();
+                    while let Some(chunk) = resp.chunk().await? {
+                        buf.extend_from_slice(&chunk);
+                        if buf.len() >= MAX_BODY {
+                            buf.truncate(MAX_BODY);
+                            break;
+                        }
+                    }
+                    if !buf.windows(expect.len()).any(|w| w == expect.as_bytes()) {
+                        bail!("http body does not contain `{expect}`");
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn validate(cfg: &Config) -> Result<()> {
+    for (idx, o) in cfg.monitor.iter().enumerate() {
+        if o.name.is_empty() {
+            bail!("monitor #{idx} => empty name");
+        }
+        if cfg.hosts.iter().any(|h| h.name == o.name) || cfg.monitor[..idx].iter().any(|m| m.name == o.name) {
+            bail!("monitor `{}` => duplicate name", o.name);
+        }
+        Check::parse(&o.target).map_err(|err| anyhow!("monitor `{}` => invalid target, {err}", o.name))?;
+        // samples must arrive before the host is considered offline
+        if o.interval == 0 || o.interval >= cfg.offline_threshold {
+            bail!(
+                "monitor `{}` => interval must be in 1..{}(offline_threshold)",
+                o.name,
+                cfg.offline_threshold
+            );
+        }
+    }
+    Ok(())
+}
+
+impl Monitor {
+    /// pseudo host, accepts no reports from clients
+    pub fn inst_host(&self, pos: usize) -> Host {
+        Host {
+            name: self.name.clone(),
+            alias: if self.alias.is_empty() {
+                self.name.clone()
+            } else {
+                self.alias.clone()
+            },
+            location: self.location.clone(),
+            r#type: self.r#type.clone(),
+            monthstart: 1,
+            notify: self.notify,
+            disabled: self.disabled,
+            labels: self.labels.clone(),
+            pos,
+            weight: 10000_u64 - pos as u64,
+            monitor: true,
+            ..Default::default()
+        }
+    }
+}
+
+async fn run(o: Monitor) {
+    let check = Check::parse(&o.target).unwrap();
+    let client = reqwest::Client::new();
+    let timeout = Duration::from_secs(o.timeout.max(1));
+    let mut window = VecDeque::with_capacity(WINDOW);
+    let mut ticker = time::interval(Duration::from_secs(o.interval));
+    ticker.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let instant = Instant::now();
+        let res = check.run(&client, timeout).await;
+        let elapsed = instant.elapsed();
+
+        if window.len() >= WINDOW {
+            window.pop_front();
+        }
+        window.push_back(res.is_ok());
+        let lost = window.iter().filter(|ok| !**ok).count();
+
+        let probe = ProbeResult {
+            name: o.name.clone(),
+            target: o.target.clone(),
+            lost_rate: (lost * 100 / window.len()) as f64,
+            time: elapsed.as_secs_f64() * 1000.0,
+            kind: check.kind().to_string(),
+            error: res.as_ref().err().map(ToString::to_string).unwrap_or_default(),
+            ..Default::default()
+        };
+        if let Err(err) = &res {
+            warn!("monitor `{}` => {err}", o.name);
+        }
+        let stat = HostStat {
+            name: o.name.clone(),
+            online4: res.is_ok(),
+            online6: false,
+            notify: true,
+            probes: vec![probe],
+            ..Default::default()
+        };
+        crate::G_STATS_MGR.get().unwrap().submit(stat);
+    }
+}
+
+/// one task per `[[monitor]]`, restarted when its config changes
+pub async fn serv_monitor() {
+    let mut running: HashMap<String, (Monitor, JoinHandle<()>)> = HashMap::new();
+    let mut ticker = time::interval(Duration::from_secs(1));
+    loop {
+        ticker.tick().await;
+        let cfg = crate::G_CONFIG.get().unwrap();
+        let want: Vec<&Monitor> = cfg.monitor.iter().filter(|o| !o.disabled).collect();
+
+        running.retain(|name, (o, handle)| {
+            let keep = want.iter().any(|w| **w == *o);
+            if !keep {
+                info!("monitor `{name}` stopped");
+                handle.abort();
+            }
+            keep
+        });
+        for o in want {
+            if !running.contains_key(&o.name) {
+                info!("monitor `{}` => {}", o.name, o.target);
+                running.insert(o.name.clone(), (o.clone(), tokio::spawn(run(o.clone()))));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_monitor() {
+        let cfg = crate::config::try_from_str(
+            r#"
+hosts_file = ""
+hosts = [{name = "h1", password = "p1"}]
+
+[[monitor]]
+name = "api"
+target = "https://api.example.com/health#status=204&body=ok"
+
+[[monitor]]
+name = "db"
+target = "tcp://10.0.0.1:5432"
+interval = 5
+"#,
+        )
+        .unwrap();
+
+        let api = &cfg.hosts_map["api"];
+        assert!(api.monitor && api.r#type == "monitor" && api.alias == "api");
+        assert_eq!(api.pos, 1);
+        assert!(!cfg.auth("api", &api.password));
+        assert_eq!(
+            Check::parse(&cfg.monitor[0].target).unwrap(),
+            Check::Http {
+                url: Url::parse("https://api.example.com/health").unwrap(),
+                status: Some(204),
+                body: Some("ok".to_string())
+            }
+        );
+        assert_eq!(Check::parse(&cfg.monitor[1].target).unwrap().kind(), "tcp");
+
+        for (target, extra) in [
+            ("tcp://10.0.0.1", ""),
+            ("ftp://example.com", ""),
+            ("https://example.com", "interval = 30"),
+            ("https://example.com#code=200", ""),
+            ("https://example.com#body=", ""),
+        ] {
+            let conf = format!("hosts_file = \"\"\n[[monitor]]\nname = \"m\"\ntarget = \"{target}\"\n{extra}");
+            assert!(crate::config::try_from_str(&conf).is_err(), "{target}");
+        }
+        let conf = "hosts_file = \"\"\nhosts = [{name = \"m\", password = \"p\"}]\n[[monitor]]\nname = \"m\"\ntarget = \"tcp://a:1\"";
+        assert!(crate::config::try_from_str(conf).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = reqwest::Client::new();
+        let timeout = Duration::from_secs(1);
+
+        let check = Check::parse(&format!("tcp://{addr}")).unwrap();
+        assert!(check.run(&client, timeout).await.is_ok());
+        drop(listener);
+        assert!(check.run(&client, timeout).await.is_err());
+
+        // the match is limited to the first MAX_BODY bytes
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf).await;
+                let body = format!("{}tail", "x".repeat(MAX_BODY));
+                let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len());
+                let _ = stream.write_all(head.as_bytes()).await;
+                let _ = stream.write_all(body.as_bytes()).await;
+            }
+        });
+        let check = Check::parse(&format!("http://{addr}/#body=xxx")).unwrap();
+        assert!(check.run(&client, timeout).await.is_ok());
+        let check = Check::parse(&format!("http://{addr}/#body=tail")).unwrap();
+        assert!(check.run(&client, timeout).await.is_err());
+    }
+}
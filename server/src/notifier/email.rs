@@ -10,11 +10,13 @@ use minijinja::context;
 use serde::{Deserialize, Serialize};
 
 use crate::jinja::{add_template, render_template};
-use crate::notifier::{get_tag, Event, HostStat, NOTIFIER_HANDLE};
+use crate::notifier::{
+    default_alert_tpl, default_resolved_tpl, get_tag, Event, HostStat, ALERT_FIRING, ALERT_RESOLVED, NOTIFIER_HANDLE,
+};
 
 const KIND: &str = "email";
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct Config {
     pub enabled: bool,
     pub server: String,
@@ -26,18 +28,24 @@ pub struct Config {
     pub online_tpl: String,
     pub offline_tpl: String,
     pub custom_tpl: String,
+    #[serde(default = "default_alert_tpl")]
+    pub alert_tpl: String,
+    #[serde(default = "default_resolved_tpl")]
+    pub resolved_tpl: String,
 }
 
 pub struct Email {
-    config: &'static Config,
+    config: Config,
 }
 
 impl Email {
-    pub fn new(cfg: &'static Config) -> Self {
-        let o = Self { config: cfg };
+    pub fn new(cfg: &Config) -> Self {
+        let o = Self { config: cfg.clone() };
         add_template(KIND, get_tag(&Event::NodeUp), o.config.online_tpl.clone());
         add_template(KIND, get_tag(&Event::NodeDown), o.config.offline_tpl.clone());
         add_template(KIND, get_tag(&Event::Custom), o.config.custom_tpl.clone());
+        add_template(KIND, ALERT_FIRING, o.config.alert_tpl.clone());
+        add_template(KIND, ALERT_RESOLVED, o.config.resolved_tpl.clone());
         o
     }
 }
@@ -96,11 +104,18 @@ impl crate::notifier::Notifier for Email {
         render_template(
             self.kind(),
             get_tag(e),
-            context!(host => stat, config => self.config, ip_info => stat.ip_info, sys_info => stat.sys_info),
+            context!(host => stat.detail(), alert => e.alert(), config => self.config, ip_info => stat.ip_info, sys_info => stat.sys_info),
             true,
         )
         .map(|content| match *e {
             Event::NodeUp | Event::NodeDown => self.send_notify(content).unwrap(),
+            Event::AlertFiring(_) | Event::AlertResolved(_) => {
+                if !content.is_empty() {
+                    self.send_notify(content).unwrap_or_else(|err| {
+                        error!("send_msg err => {err:?}");
+                    });
+                }
+            }
             Event::Custom => {
                 info!("render.custom.tpl => {content}");
                 if !content.is_empty() {
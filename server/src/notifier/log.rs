@@ -9,11 +9,11 @@ use tokio::fs::OpenOptions;
 use tokio::io::AsyncWriteExt;
 
 use crate::jinja::{add_template, render_template};
-use crate::notifier::{Event, HostStat, NOTIFIER_HANDLE};
+use crate::notifier::{get_tag, Event, HostStat, NOTIFIER_HANDLE};
 
 const KIND: &str = "log";
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct Config {
     pub enabled: bool,
     pub log_dir: String,
@@ -21,12 +21,12 @@ pub struct Config {
 }
 
 pub struct Log {
-    config: &'static Config,
+    config: Config,
 }
 
 impl Log {
-    pub fn new(cfg: &'static Config) -> Self {
-        let o = Self { config: cfg };
+    pub fn new(cfg: &Config) -> Self {
+        let o = Self { config: cfg.clone() };
 
         add_template(KIND, "tpl", o.config.tpl.clone());
 
@@ -86,7 +86,7 @@ impl crate::notifier::Notifier for Log {
         render_template(
             self.kind(),
             "tpl",
-            context!(event => e, host => stat, config => self.config, ip_info => stat.ip_info, sys_info => stat.sys_info),
+            context!(event => get_tag(e), alert => e.alert(), host => stat.detail(), config => self.config, ip_info => stat.ip_info, sys_info => stat.sys_info),
             true,
         )
         .map(|content| self.send_notify(content).unwrap())
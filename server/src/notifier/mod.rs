@@ -4,6 +4,8 @@ use serde::Serialize;
 use std::sync::Mutex;
 use tokio::runtime::Handle;
 
+use crate::alert::Alert;
+use crate::config::Config;
 use crate::payload::HostStat;
 
 pub mod email;
@@ -14,11 +16,25 @@ pub mod wechat;
 
 pub static NOTIFIER_HANDLE: Lazy<Mutex<Option<Handle>>> = Lazy::new(Default::default);
 
+pub const ALERT_FIRING: &str = "AlertFiring";
+pub const ALERT_RESOLVED: &str = "AlertResolved";
+
 #[derive(Debug, Serialize, Clone)]
 pub enum Event {
     NodeUp,
     NodeDown,
     Custom,
+    AlertFiring(Alert),
+    AlertResolved(Alert),
+}
+
+impl Event {
+    pub fn alert(&self) -> Option<&Alert> {
+        match self {
+            Event::AlertFiring(a) | Event::AlertResolved(a) => Some(a),
+            _ => None,
+        }
+    }
 }
 
 fn get_tag(e: &Event) -> &'static str {
@@ -26,9 +42,20 @@ fn get_tag(e: &Event) -> &'static str {
         Event::NodeUp => "NodeUp",
         Event::NodeDown => "NodeDown",
         Event::Custom => "Custom",
+        Event::AlertFiring(_) => ALERT_FIRING,
+        Event::AlertResolved(_) => ALERT_RESOLVED,
     }
 }
 
+pub fn default_alert_tpl() -> String {
+    "{{config.title}} \n🔥 [{{alert.severity}}] {{host.location}} {{host.name}} {{alert.rule}} {{alert.summary}}"
+        .to_string()
+}
+
+pub fn default_resolved_tpl() -> String {
+    "{{config.title}} \n✅ [{{alert.severity}}] {{host.location}} {{host.name}} {{alert.rule}} 已恢复".to_string()
+}
+
 pub trait Notifier {
     fn kind(&self) -> &'static str;
     fn notify(&self, e: &Event, stat: &HostStat) -> Result<()>;
@@ -38,3 +65,70 @@ pub trait Notifier {
         self.send_notify("❗ServerStatus test msg".to_string())
     }
 }
+
+pub fn init_notifiers(cfg: &Config) -> Vec<Box<dyn Notifier + Send>> {
+    let mut notifies: Vec<Box<dyn Notifier + Send>> = Vec::new();
+    if cfg.tgbot.enabled {
+        notifies.push(Box::new(tgbot::TGBot::new(&cfg.tgbot)));
+    }
+    if cfg.wechat.enabled {
+        notifies.push(Box::new(wechat::WeChat::new(&cfg.wechat)));
+    }
+    if cfg.email.enabled {
+        notifies.push(Box::new(email::Email::new(&cfg.email)));
+    }
+    if cfg.log.enabled {
+        notifies.push(Box::new(log::Log::new(&cfg.log)));
+    }
+    if cfg.webhook.enabled {
+        notifies.push(Box::new(webhook::Webhook::new(&cfg.webhook)));
+    }
+    notifies
+}
+
+/// check templates & scripts before they are registered, `new` panics on them
+pub fn validate(cfg: &Config) -> Result<()> {
+    let mut tpls: Vec<&String> = Vec::new();
+    if cfg.tgbot.enabled {
+        let o = &cfg.tgbot;
+        tpls.extend([
+            &o.online_tpl,
+            &o.offline_tpl,
+            &o.custom_tpl,
+            &o.alert_tpl,
+            &o.resolved_tpl,
+        ]);
+    }
+    if cfg.wechat.enabled {
+        let o = &cfg.wechat;
+        tpls.extend([
+            &o.online_tpl,
+            &o.offline_tpl,
+            &o.custom_tpl,
+            &o.alert_tpl,
+            &o.resolved_tpl,
+        ]);
+    }
+    if cfg.email.enabled {
+        let o = &cfg.email;
+        tpls.extend([
+            &o.online_tpl,
+            &o.offline_tpl,
+            &o.custom_tpl,
+            &o.alert_tpl,
+            &o.resolved_tpl,
+        ]);
+    }
+    if cfg.log.enabled {
+        tpls.push(&cfg.log.tpl);
+    }
+
+    let env = minijinja::Environment::new();
+    for tpl in tpls {
+        env.template_from_str(tpl)?;
+    }
+    if cfg.webhook.enabled {
+        webhook::validate(&cfg.webhook)?;
+    }
+    Ok(())
+}
@@ -8,11 +8,13 @@ use std::collections::HashMap;
 use tokio::time::Duration;
 
 use crate::jinja::{add_template, render_template};
-use crate::notifier::{get_tag, Event, HostStat, NOTIFIER_HANDLE};
+use crate::notifier::{
+    default_alert_tpl, default_resolved_tpl, get_tag, Event, HostStat, ALERT_FIRING, ALERT_RESOLVED, NOTIFIER_HANDLE,
+};
 
 const KIND: &str = "tgbot";
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct Config {
     pub enabled: bool,
     pub bot_token: String,
@@ -21,18 +23,22 @@ pub struct Config {
     pub online_tpl: String,
     pub offline_tpl: String,
     pub custom_tpl: String,
+    #[serde(default = "default_alert_tpl")]
+    pub alert_tpl: String,
+    #[serde(default = "default_resolved_tpl")]
+    pub resolved_tpl: String,
 }
 
 pub struct TGBot {
-    config: &'static Config,
+    config: Config,
     tg_url: String,
     http_client: reqwest::Client,
 }
 
 impl TGBot {
-    pub fn new(cfg: &'static Config) -> Self {
+    pub fn new(cfg: &Config) -> Self {
         let o = Self {
-            config: cfg,
+            config: cfg.clone(),
             tg_url: format!("https://api.telegram.org/bot{}/sendMessage", &cfg.bot_token),
             http_client: reqwest::Client::new(),
         };
@@ -40,6 +46,8 @@ impl TGBot {
         add_template(KIND, get_tag(&Event::NodeUp), o.config.online_tpl.clone());
         add_template(KIND, get_tag(&Event::NodeDown), o.config.offline_tpl.clone());
         add_template(KIND, get_tag(&Event::Custom), o.config.custom_tpl.clone());
+        add_template(KIND, ALERT_FIRING, o.config.alert_tpl.clone());
+        add_template(KIND, ALERT_RESOLVED, o.config.resolved_tpl.clone());
 
         o
     }
@@ -83,11 +91,18 @@ impl crate::notifier::Notifier for TGBot {
         render_template(
             self.kind(),
             get_tag(e),
-            context!(host => stat, config => self.config, ip_info => stat.ip_info, sys_info => stat.sys_info),
+            context!(host => stat.detail(), alert => e.alert(), config => self.config, ip_info => stat.ip_info, sys_info => stat.sys_info),
             true,
         )
         .map(|content| match *e {
             Event::NodeUp | Event::NodeDown => self.send_notify(content).unwrap(),
+            Event::AlertFiring(_) | Event::AlertResolved(_) => {
+                if !content.is_empty() {
+                    self.send_notify(content).unwrap_or_else(|err| {
+                        error!("send_msg err => {err:?}");
+                    });
+                }
+            }
             Event::Custom => {
                 info!("render.custom.tpl => {content}");
                 if !content.is_empty() {
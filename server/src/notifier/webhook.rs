@@ -31,7 +31,7 @@ pub struct Config {
 }
 
 pub struct Webhook {
-    config: &'static Config,
+    config: Config,
     http_client: reqwest::Client,
     engine: Engine,
     ast_list: Vec<Option<AST>>,
@@ -52,13 +52,27 @@ fn now_str() -> ImmutableString {
 
 #[allow(clippy::needless_pass_by_value)]
 fn to_json(o: Dynamic) -> ImmutableString {
-    serde_json::to_string(&o).map(std::convert::Into::into).unwrap_or_default()
+    serde_json::to_string(&o)
+        .map(std::convert::Into::into)
+        .unwrap_or_default()
+}
+
+pub fn validate(cfg: &Config) -> Result<()> {
+    let engine = Engine::new();
+    for r in &cfg.receiver {
+        if r.enabled {
+            engine
+                .compile(&r.script)
+                .map_err(|err| anyhow::anyhow!("invalid webhook script `{}` => {err}", r.url))?;
+        }
+    }
+    Ok(())
 }
 
 impl Webhook {
-    pub fn new(cfg: &'static Config) -> Self {
+    pub fn new(cfg: &Config) -> Self {
         let mut o = Self {
-            config: cfg,
+            config: cfg.clone(),
             http_client: reqwest::Client::new(),
             engine: Engine::new(),
             ast_list: Vec::new(),
@@ -79,10 +93,11 @@ impl Webhook {
 
         o
     }
-    fn call_webhook(&self, r: &'static Receiver, content: String) {
+    fn call_webhook(&self, r: &Receiver, content: String) {
         if content.is_empty() {
             return;
         }
+        let r = r.clone();
 
         let handle = NOTIFIER_HANDLE.lock().unwrap().as_ref().unwrap().clone();
         let http_client = self.http_client.clone();
@@ -142,7 +157,8 @@ impl crate::notifier::Notifier for Webhook {
 
             let mut scope = Scope::new();
             scope.push("event", get_tag(e));
-            scope.push("host", to_dynamic(stat)?);
+            scope.push("alert", to_dynamic(e.alert())?);
+            scope.push("host", to_dynamic(stat.detail())?);
             scope.push("config", to_dynamic(r)?);
             scope.push("ip_info", to_dynamic(stat.ip_info.as_ref())?);
             scope.push("sys_info", to_dynamic(stat.sys_info.as_ref())?);
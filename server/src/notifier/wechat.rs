@@ -9,14 +9,16 @@ use std::collections::HashMap;
 use tokio::time::Duration;
 
 use crate::jinja::{add_template, render_template};
-use crate::notifier::{get_tag, Event, HostStat, NOTIFIER_HANDLE};
+use crate::notifier::{
+    default_alert_tpl, default_resolved_tpl, get_tag, Event, HostStat, ALERT_FIRING, ALERT_RESOLVED, NOTIFIER_HANDLE,
+};
 
 // https://qydev.weixin.qq.com/wiki/index.php?title=%E4%B8%BB%E5%8A%A8%E8%B0%83%E7%94%A8
 // https://qydev.weixin.qq.com/wiki/index.php?title=%E5%8F%91%E9%80%81%E6%8E%A5%E5%8F%A3%E8%AF%B4%E6%98%8E
 static TOKEN_URL: &str = "https://qyapi.weixin.qq.com/cgi-bin/gettoken";
 const KIND: &str = "wechat";
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct Config {
     pub enabled: bool,
     pub corp_id: String,
@@ -26,22 +28,28 @@ pub struct Config {
     pub online_tpl: String,
     pub offline_tpl: String,
     pub custom_tpl: String,
+    #[serde(default = "default_alert_tpl")]
+    pub alert_tpl: String,
+    #[serde(default = "default_resolved_tpl")]
+    pub resolved_tpl: String,
 }
 
 pub struct WeChat {
-    config: &'static Config,
+    config: Config,
     http_client: reqwest::Client,
 }
 
 impl WeChat {
-    pub fn new(cfg: &'static Config) -> Self {
+    pub fn new(cfg: &Config) -> Self {
         let o = Self {
-            config: cfg,
+            config: cfg.clone(),
             http_client: reqwest::Client::new(),
         };
         add_template(KIND, get_tag(&Event::NodeUp), o.config.online_tpl.clone());
         add_template(KIND, get_tag(&Event::NodeDown), o.config.offline_tpl.clone());
         add_template(KIND, get_tag(&Event::Custom), o.config.custom_tpl.clone());
+        add_template(KIND, ALERT_FIRING, o.config.alert_tpl.clone());
+        add_template(KIND, ALERT_RESOLVED, o.config.resolved_tpl.clone());
 
         o
     }
@@ -118,11 +126,18 @@ impl crate::notifier::Notifier for WeChat {
         render_template(
             self.kind(),
             get_tag(e),
-            context!(host => stat, config => self.config, ip_info => stat.ip_info, sys_info => stat.sys_info),
+            context!(host => stat.detail(), alert => e.alert(), config => self.config, ip_info => stat.ip_info, sys_info => stat.sys_info),
             true,
         )
         .map(|content| match *e {
             Event::NodeUp | Event::NodeDown => self.send_notify(content).unwrap(),
+            Event::AlertFiring(_) | Event::AlertResolved(_) => {
+                if !content.is_empty() {
+                    self.send_notify(content).unwrap_or_else(|err| {
+                        error!("send_msg err => {err:?}");
+                    });
+                }
+            }
             Event::Custom => {
                 info!("render.custom.tpl => {content}");
                 if !content.is_empty() {
@@ -1,7 +1,10 @@
 #![deny(warnings)]
 #![allow(clippy::struct_excessive_bools)]
 use serde::{Deserialize, Serialize};
-use stat_common::server_status::{DiskInfo, IpInfo, SysInfo};
+use stat_common::server_status::{
+    ContainerInfo, CpuTimes, DiskInfo, DiskIo, IpInfo, NetIfInfo, ProbeResult, Psi, SensorInfo, ServiceInfo, SysInfo,
+    TcpStates,
+};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -43,6 +46,8 @@ pub struct HostStat {
     pub time_10010: f64,
     pub time_189: f64,
     pub time_10086: f64,
+    #[serde(default = "Default::default")]
+    pub probes: Vec<ProbeResult>,
 
     #[serde(rename(deserialize = "tcp"))]
     pub tcp_count: u32,
@@ -64,6 +69,10 @@ pub struct HostStat {
     pub last_network_out: u64,
 
     pub cpu: f64,
+    #[serde(default = "Default::default")]
+    pub cpu_cores: Vec<f64>,
+    #[serde(default = "Default::default")]
+    pub cpu_times: Option<CpuTimes>,
     pub memory_total: u64,
     pub memory_used: u64,
     pub swap_total: u64,
@@ -87,20 +96,93 @@ pub struct HostStat {
     #[serde(default = "Default::default")]
     pub weight: u64,
 
-    // user data
-    #[serde(skip_deserializing)]
+    // user data, overwritten by the server unless backfill
+    #[serde(default = "Default::default")]
     pub latest_ts: u64,
+    #[serde(default = "Default::default", skip_serializing)]
+    pub backfill: bool,
 
     #[serde(skip_serializing, skip_deserializing)]
     pub pos: usize,
     #[serde(skip_serializing, skip_deserializing)]
     pub disabled: bool,
+    // grpc stream closed
+    #[serde(skip_serializing, skip_deserializing)]
+    pub disconnected: bool,
+    // grpc stream of the sample, 0 for unary & http reports
+    #[serde(skip_serializing, skip_deserializing)]
+    pub stream: u64,
 
     // false: KiB (1024), true: KB (1000)
     #[serde(default = "Default::default")]
     pub si: bool,
+    // detail fields below are kept out of the public json, see `HostStat::detail`
+    // per mount point, `host.disks.max_pct("used", "total")` in alert rules
     #[serde(default = "Default::default", skip_serializing)]
     pub disks: Vec<DiskInfo>,
+    // usable in alert rules & templates, `host.disk_io`
+    #[serde(default = "Default::default", skip_serializing)]
+    pub disk_io: Vec<DiskIo>,
+    // `--watch` processes & systemd units
+    #[serde(default = "Default::default", skip_serializing)]
+    pub services: Vec<ServiceInfo>,
+    // `--containers` docker / podman
+    #[serde(default = "Default::default", skip_serializing)]
+    pub containers: Vec<ContainerInfo>,
+    // `host.sensors.max_of("temp")` in alert rules
+    #[serde(default = "Default::default", skip_serializing)]
+    pub sensors: Vec<SensorInfo>,
+    // null without psi, guard with `host.psi_memory != () && host.psi_memory.full_avg10 > 10.0`
+    #[serde(default = "Default::default", skip_serializing)]
+    pub psi_cpu: Option<Psi>,
+    #[serde(default = "Default::default", skip_serializing)]
+    pub psi_memory: Option<Psi>,
+    #[serde(default = "Default::default", skip_serializing)]
+    pub psi_io: Option<Psi>,
+    #[serde(default = "Default::default", skip_serializing)]
+    pub oom_kill: Option<u64>,
+    // `host.tcp_states.time_wait`, null on non linux hosts
+    #[serde(default = "Default::default", skip_serializing)]
+    pub tcp_states: Option<TcpStates>,
+    #[serde(default = "Default::default", skip_serializing)]
+    pub ifaces: Vec<NetIfInfo>,
+}
+
+/// `HostStat` with the detail fields, for alert rules, notifier templates, webhooks and the admin api
+#[derive(Debug, Serialize)]
+pub struct HostDetail<'a> {
+    #[serde(flatten)]
+    pub stat: &'a HostStat,
+    pub disks: &'a [DiskInfo],
+    pub disk_io: &'a [DiskIo],
+    pub services: &'a [ServiceInfo],
+    pub containers: &'a [ContainerInfo],
+    pub sensors: &'a [SensorInfo],
+    pub psi_cpu: Option<&'a Psi>,
+    pub psi_memory: Option<&'a Psi>,
+    pub psi_io: Option<&'a Psi>,
+    pub oom_kill: Option<u64>,
+    pub tcp_states: Option<&'a TcpStates>,
+    pub ifaces: &'a [NetIfInfo],
+}
+
+impl HostStat {
+    pub fn detail(&self) -> HostDetail<'_> {
+        HostDetail {
+            stat: self,
+            disks: &self.disks,
+            disk_io: &self.disk_io,
+            services: &self.services,
+            containers: &self.containers,
+            sensors: &self.sensors,
+            psi_cpu: self.psi_cpu.as_ref(),
+            psi_memory: self.psi_memory.as_ref(),
+            psi_io: self.psi_io.as_ref(),
+            oom_kill: self.oom_kill,
+            tcp_states: self.tcp_states.as_ref(),
+            ifaces: &self.ifaces,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -0,0 +1,488 @@
+#![deny(warnings)]
+use anyhow::{bail, Result};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use crate::alert::match_target;
+use crate::payload::HostStat;
+
+// 运行时添加的静默规则
+pub const SILENCE_FILE: &str = "silences.json";
+const MAX_DURATION: u64 = 31 * 24 * 3600;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Silence {
+    #[serde(default = "Default::default")]
+    pub id: String,
+    #[serde(default = "Default::default")]
+    pub comment: String,
+    // targets, empty means all
+    #[serde(default = "Default::default")]
+    pub hosts: Vec<String>,
+    #[serde(default = "Default::default")]
+    pub gids: Vec<String>,
+    #[serde(default = "Default::default")]
+    pub labels: Vec<String>,
+    // one-shot window, `2024-06-01 02:00`, rfc3339 or unix ts
+    #[serde(default = "Default::default")]
+    pub start: String,
+    #[serde(default = "Default::default")]
+    pub end: String,
+    // recurring window, `min hour dom mon dow` (local time) + duration secs
+    #[serde(default = "Default::default")]
+    pub cron: String,
+    #[serde(default = "Default::default")]
+    pub duration: u64,
+    // config entries can't be deleted via api
+    #[serde(default = "Default::default", skip_deserializing)]
+    pub readonly: bool,
+}
+
+fn parse_time(s: &str) -> Result<Option<i64>> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(None);
+    }
+    if let Ok(ts) = s.parse::<i64>() {
+        return Ok(Some(ts));
+    }
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Ok(Some(dt.timestamp()));
+    }
+    for fmt in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(s, fmt) {
+            if let Some(dt) = Local.from_local_datetime(&dt).earliest() {
+                return Ok(Some(dt.timestamp()));
+            }
+        }
+    }
+    bail!("invalid time `{s}`")
+}
+
+// field bitmask, bit n => value n
+fn parse_field(field: &str, lo: u32, hi: u32) -> Result<u64> {
+    let mut mask = 0_u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((r, s)) => (r, s.parse::<u32>()?),
+            None => (part, 1),
+        };
+        let (from, to) = if range == "*" {
+            (lo, hi)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (a.parse::<u32>()?, b.parse::<u32>()?)
+        } else {
+            let v = range.parse::<u32>()?;
+            // `5/10` => 5-hi/10
+            (v, if part.contains('/') { hi } else { v })
+        };
+        if step == 0 || from < lo || to > hi || from > to {
+            bail!("invalid cron field `{field}`");
+        }
+        let mut v = from;
+        while v <= to {
+            mask |= 1 << v;
+            v += step;
+        }
+    }
+    Ok(mask)
+}
+
+#[derive(Debug, Clone)]
+struct Cron {
+    minute: u64,
+    hour: u64,
+    dom: u64,
+    month: u64,
+    dow: u64,
+    dom_any: bool,
+    dow_any: bool,
+}
+
+impl Cron {
+    fn parse(expr: &str) -> Result<Self> {
+        let fields = expr.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            bail!("invalid cron `{expr}`, want `min hour dom mon dow`");
+        }
+        let mut dow = parse_field(fields[4], 0, 7)?;
+        // 7 => sunday
+        if dow & (1 << 7) != 0 {
+            dow |= 1;
+        }
+        Ok(Self {
+            minute: parse_field(fields[0], 0, 59)?,
+            hour: parse_field(fields[1], 0, 23)?,
+            dom: parse_field(fields[2], 1, 31)?,
+            month: parse_field(fields[3], 1, 12)?,
+            dow,
+            dom_any: fields[2] == "*",
+            dow_any: fields[4] == "*",
+        })
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let bit = |mask: u64, v: u32| mask & (1 << v) != 0;
+        let dom = bit(self.dom, date.day());
+        let dow = bit(self.dow, date.weekday().num_days_from_sunday());
+        // vixie cron: dom or dow when both are restricted
+        let day = if self.dom_any || self.dow_any {
+            dom && dow
+        } else {
+            dom || dow
+        };
+        bit(self.month, date.month()) && day
+    }
+
+    /// latest matching minute at or before `dt`, skips whole days & hours, None once before `floor`
+    fn last_match(&self, dt: NaiveDateTime, floor: NaiveDateTime) -> Option<NaiveDateTime> {
+        // highest set bit <= v
+        let below = |mask: u64, v: u32| {
+            let m = mask & ((2_u64 << v) - 1);
+            (m != 0).then(|| 63 - m.leading_zeros())
+        };
+        let mut t = dt.with_second(0)?.with_nanosecond(0)?;
+        while t >= floor {
+            let date = t.date();
+            let day_start = date.and_hms_opt(0, 0, 0)?;
+            if !self.day_matches(date) {
+                t = day_start - Duration::minutes(1);
+                continue;
+            }
+            let Some(hour) = below(self.hour, t.hour()) else {
+                t = day_start - Duration::minutes(1);
+                continue;
+            };
+            let minute = below(self.minute, if hour == t.hour() { t.minute() } else { 59 });
+            match minute {
+                Some(minute) => return date.and_hms_opt(hour, minute, 0).filter(|o| *o >= floor),
+                None if hour == 0 => t = day_start - Duration::minutes(1),
+                None => t = date.and_hms_opt(hour - 1, 59, 0)?,
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Window {
+    Range(Option<i64>, Option<i64>),
+    Cron(Cron, u64),
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    silence: Silence,
+    window: Window,
+}
+
+impl Entry {
+    fn new(silence: Silence) -> Result<Self> {
+        let window = if silence.cron.trim().is_empty() {
+            let (start, end) = (parse_time(&silence.start)?, parse_time(&silence.end)?);
+            if start.is_none() && end.is_none() {
+                bail!("silence needs start/end or cron");
+            }
+            Window::Range(start, end)
+        } else {
+            if silence.duration == 0 || silence.duration > MAX_DURATION {
+                bail!("silence duration must be in 1..={MAX_DURATION}s");
+            }
+            Window::Cron(Cron::parse(&silence.cron)?, silence.duration)
+        };
+        Ok(Self { silence, window })
+    }
+
+    fn expired(&self, now: i64) -> bool {
+        matches!(self.window, Window::Range(_, Some(end)) if end <= now)
+    }
+
+    fn active(&self, now: i64) -> bool {
+        match &self.window {
+            Window::Range(start, end) => start.is_none_or(|v| v <= now) && end.is_none_or(|v| now < v),
+            Window::Cron(cron, duration) => {
+                // latest matching minute within (now - duration, now]
+                let from = now - *duration as i64;
+                let Some(dt) = Local.timestamp_opt(now, 0).single() else {
+                    return false;
+                };
+                let mut cur = dt.naive_local();
+                // an hour of slack for dst shifts, the real ts is checked below
+                let floor = cur - Duration::seconds(*duration as i64 + 3600);
+                while let Some(t) = cron.last_match(cur, floor) {
+                    let local = Local.from_local_datetime(&t);
+                    let ts = local
+                        .latest()
+                        .filter(|o| o.timestamp() <= now)
+                        .or_else(|| local.earliest())
+                        .map(|o| o.timestamp());
+                    match ts {
+                        Some(ts) if ts <= from => return false,
+                        Some(ts) if ts <= now => return true,
+                        // skipped by dst
+                        _ => {}
+                    }
+                    cur = t - Duration::minutes(1);
+                }
+                false
+            }
+        }
+    }
+
+    fn matches(&self, stat: &HostStat) -> bool {
+        let o = &self.silence;
+        match_target(stat, &o.hosts, &o.gids, &o.labels)
+    }
+}
+
+pub fn validate(cfg: &[Silence]) -> Result<()> {
+    for s in cfg {
+        Entry::new(s.clone())?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    config: Vec<Entry>,
+    runtime: Vec<Entry>,
+}
+
+#[derive(Debug)]
+pub struct Silences {
+    path: String,
+    inner: Mutex<Inner>,
+}
+
+impl Silences {
+    fn config_entries(cfg: &[Silence]) -> Result<Vec<Entry>> {
+        cfg.iter()
+            .enumerate()
+            .map(|(idx, s)| {
+                let mut s = s.clone();
+                if s.id.is_empty() {
+                    s.id = format!("config-{idx}");
+                }
+                s.readonly = true;
+                Entry::new(s)
+            })
+            .collect()
+    }
+
+    pub fn new(cfg: &[Silence], path: &str) -> Result<Self> {
+        let mut inner = Inner {
+            config: Self::config_entries(cfg)?,
+            ..Default::default()
+        };
+
+        let now = Local::now().timestamp();
+        let contents = fs::read_to_string(path).unwrap_or_default();
+        if !contents.is_empty() {
+            match serde_json::from_str::<Vec<Silence>>(&contents) {
+                Ok(list) => {
+                    for s in list {
+                        match Entry::new(s) {
+                            Ok(e) if !e.expired(now) => inner.runtime.push(e),
+                            Ok(_) => {}
+                            Err(err) => warn!("ignore invalid silence => {err:?}"),
+                        }
+                    }
+                }
+                Err(err) => warn!("ignore invalid {path} => {err:?}"),
+            }
+        }
+
+        Ok(Self {
+            path: path.to_string(),
+            inner: Mutex::new(inner),
+        })
+    }
+
+    pub fn set_config(&self, cfg: &[Silence]) -> Result<()> {
+        let entries = Self::config_entries(cfg)?;
+        self.inner.lock().unwrap().config = entries;
+        Ok(())
+    }
+
+    fn save(&self, inner: &Inner) {
+        let list = inner.runtime.iter().map(|e| &e.silence).collect::<Vec<_>>();
+        if let Err(err) = serde_json::to_string_pretty(&list)
+            .map_err(anyhow::Error::new)
+            .and_then(|s| fs::write(&self.path, s).map_err(anyhow::Error::new))
+        {
+            error!("save {} error => {err:?}", self.path);
+        }
+    }
+
+    /// id of the first active silence matching the host
+    pub fn silenced_by(&self, stat: &HostStat, now: i64) -> Option<String> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .config
+            .iter()
+            .chain(inner.runtime.iter())
+            .find(|e| e.matches(stat) && e.active(now))
+            .map(|e| e.silence.id.clone())
+    }
+
+    pub fn list(&self) -> Vec<Silence> {
+        let now = Local::now().timestamp();
+        let mut inner = self.inner.lock().unwrap();
+        let n = inner.runtime.len();
+        inner.runtime.retain(|e| !e.expired(now));
+        if n != inner.runtime.len() {
+            self.save(&inner);
+        }
+        inner
+            .config
+            .iter()
+            .chain(inner.runtime.iter())
+            .map(|e| e.silence.clone())
+            .collect()
+    }
+
+    pub fn add(&self, mut silence: Silence) -> Result<Silence> {
+        silence.id = Uuid::new_v4().to_string();
+        silence.readonly = false;
+        let entry = Entry::new(silence.clone())?;
+        if entry.expired(Local::now().timestamp()) {
+            bail!("silence already ended");
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.runtime.push(entry);
+        self.save(&inner);
+        Ok(silence)
+    }
+
+    pub fn remove(&self, id: &str) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let n = inner.runtime.len();
+        inner.runtime.retain(|e| !e.silence.id.eq(id));
+        if n == inner.runtime.len() {
+            return false;
+        }
+        self.save(&inner);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local_ts(s: &str) -> i64 {
+        parse_time(s).unwrap().unwrap()
+    }
+
+    fn stat(name: &str, gid: &str) -> HostStat {
+        HostStat {
+            name: name.to_string(),
+            gid: gid.to_string(),
+            labels: "os=pi;".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_cron_field() {
+        assert_eq!(parse_field("*", 0, 3).unwrap(), 0b1111);
+        assert_eq!(parse_field("1-3", 0, 7).unwrap(), 0b1110);
+        assert_eq!(parse_field("*/2", 0, 5).unwrap(), 0b10101);
+        assert_eq!(parse_field("1,5/2", 0, 8).unwrap(), 0b1010_0010);
+        assert!(parse_field("60", 0, 59).is_err());
+        assert!(parse_field("*/0", 0, 59).is_err());
+        assert!(Cron::parse("0 3 * *").is_err());
+    }
+
+    #[test]
+    fn test_cron_window() {
+        // 每周日 03:00 起 2 小时
+        let s = Silence {
+            cron: "0 3 * * 7".to_string(),
+            duration: 7200,
+            ..Default::default()
+        };
+        let e = Entry::new(s).unwrap();
+        // 2024-06-02 is a sunday
+        assert!(!e.active(local_ts("2024-06-02 02:59")));
+        assert!(e.active(local_ts("2024-06-02 03:00")));
+        assert!(e.active(local_ts("2024-06-02 04:59:59")));
+        assert!(!e.active(local_ts("2024-06-02 05:00")));
+        assert!(!e.active(local_ts("2024-06-03 03:30")));
+
+        // monthly window of the max duration, found without walking every minute
+        let s = Silence {
+            cron: "30 22 1 * *".to_string(),
+            duration: MAX_DURATION,
+            ..Default::default()
+        };
+        let e = Entry::new(s).unwrap();
+        assert!(e.active(local_ts("2024-06-01 22:30")));
+        assert!(e.active(local_ts("2024-06-30 23:59")));
+        let s = Silence {
+            cron: "30 22 1 1 *".to_string(),
+            duration: MAX_DURATION,
+            ..Default::default()
+        };
+        let e = Entry::new(s).unwrap();
+        assert!(e.active(local_ts("2024-01-20 08:00")));
+        assert!(!e.active(local_ts("2024-01-01 22:29")));
+        assert!(!e.active(local_ts("2024-02-01 22:31")));
+    }
+
+    #[test]
+    fn test_range_and_targets() {
+        let s = Silence {
+            gids: vec!["g1".to_string()],
+            labels: vec!["os=pi".to_string()],
+            start: "2024-06-01 02:00".to_string(),
+            end: "2024-06-01 03:00".to_string(),
+            ..Default::default()
+        };
+        let e = Entry::new(s).unwrap();
+        assert!(e.matches(&stat("h1", "g1")));
+        assert!(!e.matches(&stat("h1", "g2")));
+        assert!(e.active(local_ts("2024-06-01 02:30")));
+        assert!(!e.active(local_ts("2024-06-01 03:00")));
+        assert!(e.expired(local_ts("2024-06-01 03:00")));
+
+        assert!(Entry::new(Silence::default()).is_err());
+    }
+
+    #[test]
+    fn test_runtime_persist() {
+        let path = std::env::temp_dir().join(format!("silences-{}.json", Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        let cfg = vec![Silence {
+            hosts: vec!["h9".to_string()],
+            cron: "* * * * *".to_string(),
+            duration: 60,
+            ..Default::default()
+        }];
+
+        let silences = Silences::new(&cfg, path).unwrap();
+        let now = Local::now().timestamp();
+        assert_eq!(silences.silenced_by(&stat("h9", ""), now).as_deref(), Some("config-0"));
+        assert!(silences.silenced_by(&stat("h1", ""), now).is_none());
+
+        let added = silences
+            .add(Silence {
+                hosts: vec!["h1".to_string()],
+                end: (now + 3600).to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(silences.silenced_by(&stat("h1", ""), now), Some(added.id.clone()));
+
+        // reload from file
+        let silences = Silences::new(&cfg, path).unwrap();
+        assert_eq!(silences.list().len(), 2);
+        assert!(!silences.remove("config-0"));
+        assert!(silences.remove(&added.id));
+        assert!(silences.silenced_by(&stat("h1", ""), now).is_none());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -3,7 +3,7 @@ use anyhow::Result;
 use chrono::{Datelike, Local, Timelike};
 use once_cell::sync::OnceCell;
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Write as _;
 use std::fs;
 use std::fs::File;
@@ -14,12 +14,22 @@ use std::sync::{Arc, LazyLock, Mutex};
 use std::thread;
 use std::time::Duration;
 use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
 
+use stat_common::server_status::Control;
+
+use crate::alert::{self, AlertEngine};
+use crate::client_config;
 use crate::config::Host;
-use crate::notifier::{Event, Notifier};
+use crate::history::{self, HistoryStore, Series};
+use crate::notifier::{self, Event, Notifier};
 use crate::payload::{HostStat, StatsResp};
+use crate::silence::{self, Silence, Silences};
 
 const SAVE_INTERVAL: u64 = 60;
+const LIVE_CAPACITY: usize = 1024;
+// client sample times kept per host to skip replays of samples already received
+const RECENT_TS: usize = 64;
 const OS_LIST: [&str; 10] = [
     "centos", "debian", "ubuntu", "arch", "windows", "macos", "pi", "android", "linux", "freebsd",
 ];
@@ -29,6 +39,17 @@ static STAT_SENDER: OnceCell<SyncSender<Cow<HostStat>>> = OnceCell::new();
 pub struct StatsMgr {
     resp_json: Arc<Mutex<String>>,
     stats_data: Arc<Mutex<StatsResp>>,
+    history: Option<Arc<HistoryStore>>,
+    history_tx: Option<SyncSender<Arc<HostStat>>>,
+    silences: Option<Arc<Silences>>,
+    // for reload
+    hosts_map: Arc<Mutex<HashMap<String, Host>>>,
+    stat_map: Arc<Mutex<HashMap<String, Arc<HostStat>>>>,
+    recent_ts: Arc<Mutex<HashMap<String, VecDeque<u64>>>>,
+    alert_engine: Arc<Mutex<AlertEngine>>,
+    notifies: Arc<Mutex<Vec<Box<dyn Notifier + Send>>>>,
+    // 实时推送
+    live_tx: broadcast::Sender<Arc<HostStat>>,
 }
 
 impl StatsMgr {
@@ -36,6 +57,15 @@ impl StatsMgr {
         Self {
             resp_json: Arc::new(Mutex::new("{}".to_string())),
             stats_data: Arc::new(Mutex::new(StatsResp::new())),
+            history: None,
+            history_tx: None,
+            silences: None,
+            hosts_map: Arc::default(),
+            stat_map: Arc::default(),
+            recent_ts: Arc::default(),
+            alert_engine: Arc::default(),
+            notifies: Arc::default(),
+            live_tx: broadcast::channel(LIVE_CAPACITY).0,
         }
     }
 
@@ -74,10 +104,12 @@ impl StatsMgr {
     #[allow(clippy::unnecessary_wraps)]
     pub fn init(
         &mut self,
-        cfg: &'static crate::config::Config,
+        cfg: &crate::config::Config,
         notifies: Arc<Mutex<Vec<Box<dyn Notifier + Send>>>>,
     ) -> Result<()> {
-        let hosts_map_base = Arc::new(Mutex::new(cfg.hosts_map.clone()));
+        let hosts_map_base = self.hosts_map.clone();
+        *hosts_map_base.lock().unwrap() = cfg.hosts_map.clone();
+        self.notifies = notifies.clone();
 
         // load last_network_in/out
         if let Ok(mut hosts_map_guard) = hosts_map_base.lock() {
@@ -88,17 +120,57 @@ impl StatsMgr {
         STAT_SENDER.set(stat_tx).unwrap();
         let (notifier_tx, notifier_rx) = sync_channel(512);
 
-        let stat_map: Arc<Mutex<HashMap<String, Arc<HostStat>>>> = Arc::new(Mutex::new(HashMap::new()));
+        let stat_map = self.stat_map.clone();
+
+        // history
+        let mut history_tx: Option<SyncSender<Arc<HostStat>>> = None;
+        if cfg.history.enabled {
+            match HistoryStore::open(&cfg.history) {
+                Ok(store) => {
+                    let store = Arc::new(store);
+                    let (tx, rx) = sync_channel(512);
+                    history::start_history_t(store.clone(), rx);
+                    history_tx = Some(tx);
+                    self.history = Some(store);
+                    self.history_tx.clone_from(&history_tx);
+                    eprintln!("✨ history enabled, path `{}", cfg.history.path);
+                }
+                Err(err) => {
+                    error!("can't open history db `{}` => {err:?}", cfg.history.path);
+                }
+            }
+        }
+
+        *self.alert_engine.lock().unwrap() = AlertEngine::new(&cfg.alert_rule)?;
+        let silences = Arc::new(Silences::new(&cfg.silence, silence::SILENCE_FILE)?);
+        self.silences = Some(silences.clone());
 
         // stat_rx thread
         thread::spawn({
             let hosts_map = hosts_map_base.clone();
             let stat_map = stat_map.clone();
             let notifier_tx = notifier_tx.clone();
+            let alert_engine = self.alert_engine.clone();
+            let live_tx = self.live_tx.clone();
 
             move || loop {
                 while let Ok(mut stat) = stat_rx.recv() {
                     trace!("recv stat `{stat:?}");
+                    let cfg = crate::G_CONFIG.get().unwrap();
+
+                    // grpc stream closed, offline now unless already reporting on a newer stream
+                    if stat.disconnected {
+                        if let Some(o) = stat_map.lock().unwrap().get_mut(&stat.name) {
+                            if o.stream == stat.stream && (o.online4 || o.online6) {
+                                let s = Arc::make_mut(o);
+                                s.online4 = false;
+                                s.online6 = false;
+                                s.disconnected = true;
+                                live_tx.send(Arc::clone(o));
+                            }
+                        }
+                        continue;
+                    }
 
                     let mut stat_t = stat.to_mut();
 
@@ -190,23 +262,75 @@ impl StatsMgr {
                             );
                         }
 
+                        // stream clients only send sys_info on change
+                        if stat_t.sys_info.is_none() {
+                            if let Some(pre_stat) = stat_map.lock().unwrap().get(&stat_t.name) {
+                                stat_t.sys_info.clone_from(&pre_stat.sys_info);
+                            }
+                        }
+
+                        // labels
+                        if !stat_t.labels.contains("os=") {
+                            if let Some(sys_info) = &stat_t.sys_info {
+                                let os_r = sys_info.os_release.to_lowercase();
+                                for s in &OS_LIST {
+                                    if os_r.contains(s) {
+                                        if stat_t.labels.is_empty() {
+                                            write!(stat_t.labels, "os={s}");
+                                        } else {
+                                            write!(stat_t.labels, ";os={s}");
+                                        }
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+
                         info!("update stat `{stat_t:?}");
                         if let Ok(mut host_stat_map) = stat_map.lock() {
                             let mut notify_up = false;
+                            let mut service_events = Vec::new();
                             if let Some(pre_stat) = host_stat_map.get(&stat_t.name) {
+                                if stat_t.notify {
+                                    service_events = alert::service_events(pre_stat, stat_t);
+                                }
                                 if stat_t.ip_info.is_none() {
                                     stat_t.ip_info = pre_stat.ip_info.clone();
                                 }
 
-                                if stat_t.notify && (pre_stat.latest_ts + cfg.offline_threshold < stat_t.latest_ts) {
+                                // NodeDown already sent, `disabled` is set by the timer thread
+                                let notified_down = pre_stat.disabled;
+                                let online = stat_t.online4 || stat_t.online6;
+                                if stat_t.notify
+                                    && (pre_stat.latest_ts + cfg.offline_threshold < stat_t.latest_ts
+                                        || (notified_down && online))
+                                {
                                     notify_up = true;
                                 }
+                                // still down (eg. failed monitor check), don't repeat NodeDown
+                                if notified_down && !online {
+                                    stat_t.disabled = true;
+                                }
                             }
                             let arc_stat = Arc::new(stat.into_owned());
+                            if let Some(tx) = &history_tx {
+                                if tx.try_send(Arc::clone(&arc_stat)).is_err() {
+                                    warn!("history queue full, drop sample of `{}", arc_stat.name);
+                                }
+                            }
                             if notify_up {
                                 // node up notify
                                 notifier_tx.send((Event::NodeUp, Arc::clone(&arc_stat)));
                             }
+                            if arc_stat.notify {
+                                for e in alert_engine.lock().unwrap().eval(&arc_stat, arc_stat.latest_ts) {
+                                    notifier_tx.send((e, Arc::clone(&arc_stat)));
+                                }
+                            }
+                            for e in service_events {
+                                notifier_tx.send((e, Arc::clone(&arc_stat)));
+                            }
+                            live_tx.send(Arc::clone(&arc_stat));
                             host_stat_map.insert(arc_stat.name.clone(), arc_stat);
                             //trace!("{:?}", host_stat_map);
                         }
@@ -221,13 +345,18 @@ impl StatsMgr {
             let stats_data = self.stats_data.clone();
             let hosts_map = hosts_map_base.clone();
             let stat_map = stat_map.clone();
+            let recent_ts = self.recent_ts.clone();
             let notifier_tx = notifier_tx.clone();
             let mut latest_notify_ts = 0_u64;
             let mut latest_save_ts = 0_u64;
             let mut latest_group_gc = 0_u64;
             let mut latest_alert_check_ts = 0_u64;
+            let live_tx = self.live_tx.clone();
+            let alert_engine = self.alert_engine.clone();
+            let silences = silences.clone();
             move || loop {
                 thread::sleep(Duration::from_millis(500));
+                let cfg = crate::G_CONFIG.get().unwrap();
 
                 let mut resp = StatsResp::new();
                 let now = resp.updated;
@@ -244,6 +373,9 @@ impl StatsMgr {
                     if let Ok(mut sm) = stat_map.lock() {
                         sm.retain(|_, o| o.gid.is_empty() || o.latest_ts + cfg.group_gc >= now);
                     }
+                    if let Ok(hm) = hosts_map.lock() {
+                        recent_ts.lock().unwrap().retain(|name, _| hm.contains_key(name));
+                    }
                 }
 
                 if let Ok(mut host_stat_map) = stat_map.lock() {
@@ -252,35 +384,29 @@ impl StatsMgr {
                             resp.servers.push(Arc::clone(stat));
                             continue;
                         }
+                        let mut offline = false;
                         let notify_event = {
                             let o = Arc::make_mut(stat);
                             // 30s 下线
-                            if o.latest_ts + cfg.offline_threshold < now {
+                            if o.latest_ts + cfg.offline_threshold < now && (o.online4 || o.online6) {
                                 o.online4 = false;
                                 o.online6 = false;
-                            }
-
-                            // labels
-                            if !o.labels.contains("os=") {
-                                if let Some(sys_info) = &o.sys_info {
-                                    let os_r = sys_info.os_release.to_lowercase();
-                                    for s in &OS_LIST {
-                                        if os_r.contains(s) {
-                                            if o.labels.is_empty() {
-                                                write!(o.labels, "os={s}");
-                                            } else {
-                                                write!(o.labels, ";os={s}");
-                                            }
-                                            break;
-                                        }
-                                    }
-                                }
+                                offline = true;
                             }
 
                             // determine notify event (o is dropped after this block)
                             if o.notify && latest_notify_ts + cfg.notify_interval < now {
                                 if o.online4 || o.online6 {
                                     Some(Event::Custom)
+                                } else if o.disconnected && o.latest_ts + cfg.offline_threshold >= now {
+                                    // stream closed, give the client offline_threshold to reconnect
+                                    None
+                                } else if silences
+                                    .silenced_by(o, i64::try_from(now).unwrap_or(i64::MAX))
+                                    .is_some()
+                                {
+                                    // muted, retry NodeDown once the silence ends
+                                    None
                                 } else {
                                     o.disabled = true;
                                     Some(Event::NodeDown)
@@ -290,6 +416,10 @@ impl StatsMgr {
                             }
                         };
 
+                        if offline {
+                            live_tx.send(Arc::clone(stat));
+                        }
+
                         // client notify — Arc::clone is O(1), no HostStat copy
                         if let Some(event) = notify_event {
                             notifier_tx.send((event, Arc::clone(stat)));
@@ -301,6 +431,15 @@ impl StatsMgr {
                     if any_notified {
                         latest_notify_ts = now;
                     }
+
+                    // alerts are only evaluated on reports, resolve offline & removed hosts here
+                    for msg in alert_engine
+                        .lock()
+                        .unwrap()
+                        .expire(&host_stat_map, now, cfg.offline_threshold)
+                    {
+                        notifier_tx.send(msg);
+                    }
                 }
 
                 resp.servers.sort_by(|a, b| {
@@ -341,6 +480,11 @@ impl StatsMgr {
         thread::spawn(move || loop {
             while let Ok(msg) = notifier_rx.recv() {
                 let (e, stat) = msg;
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+                if let Some(id) = silences.silenced_by(&stat, i64::try_from(now).unwrap_or(i64::MAX)) {
+                    info!("silence `{id}` mute {e:?} of `{}", stat.name);
+                    continue;
+                }
                 let notify_list = &*notifies.lock().unwrap();
                 trace!("recv notify => {e:?}, {stat:?}");
                 for n in notify_list {
@@ -353,22 +497,133 @@ impl StatsMgr {
         Ok(())
     }
 
+    /// apply a reloaded config, keep current stats & last_network_in/out
+    pub fn reload(&self, cfg: &crate::config::Config) -> Result<()> {
+        self.alert_engine.lock().unwrap().set_rules(&cfg.alert_rule)?;
+        if let Some(silences) = &self.silences {
+            silences.set_config(&cfg.silence)?;
+        }
+        *self.notifies.lock().unwrap() = notifier::init_notifiers(cfg);
+
+        let mut hosts_map = self.hosts_map.lock().unwrap();
+        let old = std::mem::replace(&mut *hosts_map, cfg.hosts_map.clone());
+        for (name, o) in old {
+            if let Some(host) = hosts_map.get_mut(&name) {
+                host.last_network_in = o.last_network_in;
+                host.last_network_out = o.last_network_out;
+                host.latest_ts = o.latest_ts;
+            } else if let Some(group) = cfg.hosts_group_map.get(&o.gid) {
+                // group mode, 按新的组配置重新生成
+                let mut inst = group.inst_host(&name);
+                inst.last_network_in = o.last_network_in;
+                inst.last_network_out = o.last_network_out;
+                inst.latest_ts = o.latest_ts;
+                hosts_map.insert(name, inst);
+            }
+        }
+        // removed or disabled
+        self.stat_map
+            .lock()
+            .unwrap()
+            .retain(|name, _| hosts_map.get(name).is_some_and(|o| !o.disabled));
+
+        Ok(())
+    }
+
+    /// grpc stream closed, mark offline in order with the samples already queued
+    pub fn disconnect(&self, name: &str, stream: u64) {
+        let stat = HostStat {
+            name: name.to_string(),
+            disconnected: true,
+            stream,
+            ..Default::default()
+        };
+        // called from the grpc tasks, never block a worker, the offline check catches it later
+        if let Some(tx) = STAT_SENDER.get() {
+            if tx.try_send(Cow::Owned(stat)).is_err() {
+                warn!("stat queue full, drop disconnect of `{name}");
+            }
+        }
+    }
+
+    /// samples produced by the server itself, eg. `[[monitor]]`
+    pub fn submit(&self, stat: HostStat) {
+        if let Some(tx) = STAT_SENDER.get() {
+            if tx.try_send(Cow::Owned(stat)).is_err() {
+                warn!("stat queue full, drop server sample");
+            }
+        }
+    }
+
+    /// control msg for grpc stream clients
+    pub fn control(&self, name: &str, gid: &str) -> Control {
+        let mut o = Control::default();
+        if let Some(host) = self.hosts_map.lock().unwrap().get(name) {
+            o.disabled = host.disabled;
+        }
+        if let Some(cfg) = client_config::resolve(&crate::G_CONFIG.get().unwrap(), name, gid) {
+            o.interval = cfg.interval.unwrap_or_default();
+            o.config_version = cfg.version;
+        }
+        o.request_sys_info = !o.disabled
+            && self
+                .stat_map
+                .lock()
+                .unwrap()
+                .get(name)
+                .is_some_and(|s| s.sys_info.is_none());
+        o
+    }
+
+    /// host stat on every report & offline
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<HostStat>> {
+        self.live_tx.subscribe()
+    }
+
     pub fn get_stats(&self) -> Arc<Mutex<StatsResp>> {
         self.stats_data.clone()
     }
 
+    pub fn query_history(&self, name: &str, metrics: &[&str], from: u64, to: u64, step: u64) -> Result<Series> {
+        match self.history.as_ref() {
+            Some(store) => store.query(name, metrics, from, to, step),
+            None => anyhow::bail!("history disabled"),
+        }
+    }
+
+    /// write the open buckets, on shutdown
+    pub fn flush_history(&self) {
+        if let Some(store) = self.history.as_ref() {
+            store.flush_all();
+        }
+    }
+
+    pub fn get_silences(&self) -> Arc<Silences> {
+        self.silences.clone().unwrap()
+    }
+
     pub fn get_stats_json(&self) -> String {
         self.resp_json.lock().unwrap().to_string()
     }
 
-    #[allow(clippy::unused_self)]
-    #[allow(clippy::unnecessary_wraps)]
-    pub fn report(&self, data: serde_json::Value) -> Result<()> {
+    /// `stream`: grpc stream of the sample, 0 for unary & http reports
+    pub fn report(&self, data: serde_json::Value, stream: u64) -> Result<()> {
         static SENDER: LazyLock<SyncSender<Cow<'static, HostStat>>> =
             LazyLock::new(|| STAT_SENDER.get().unwrap().clone());
 
-        match serde_json::from_value(data) {
-            Ok(stat) => {
+        match serde_json::from_value::<HostStat>(data) {
+            Ok(stat) if stat.backfill => return self.backfill(stat),
+            Ok(mut stat) => {
+                stat.stream = stream;
+                // latest_ts is still the client sample time here
+                if stat.latest_ts > 0 {
+                    let mut recent_ts = self.recent_ts.lock().unwrap();
+                    let q = recent_ts.entry(stat.name.clone()).or_default();
+                    if q.len() >= RECENT_TS {
+                        q.pop_front();
+                    }
+                    q.push_back(stat.latest_ts);
+                }
                 trace!("send stat => {stat:?} ");
                 SENDER.send(Cow::Owned(stat));
             }
@@ -379,12 +634,47 @@ impl StatsMgr {
         Ok(())
     }
 
+    /// backfilled reports go to history only, bypassing the live queue
+    /// any error fails the report, the client keeps it buffered and retries later
+    fn backfill(&self, mut stat: HostStat) -> Result<()> {
+        let Some(tx) = &self.history_tx else {
+            anyhow::bail!("history disabled, backfill needs `[history]`");
+        };
+        let cfg = crate::G_CONFIG.get().unwrap();
+        let known = match self.hosts_map.lock().unwrap().get(&stat.name) {
+            Some(o) => !o.disabled,
+            None => !stat.gid.is_empty() && cfg.hosts_group_map.contains_key(&stat.gid),
+        };
+        if !known {
+            anyhow::bail!("invalid backfill stat `{}", stat.name);
+        }
+        // received live but unacked when the stream broke, already in history
+        if self
+            .recent_ts
+            .lock()
+            .unwrap()
+            .get(&stat.name)
+            .is_some_and(|q| q.contains(&stat.latest_ts))
+        {
+            trace!("skip backfill of `{}@{}, already received", stat.name, stat.latest_ts);
+            return Ok(());
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        if stat.latest_ts == 0 || stat.latest_ts > now {
+            stat.latest_ts = now;
+        }
+        tx.try_send(Arc::new(stat))
+            .map_err(|_| anyhow::anyhow!("history queue full, retry later"))
+    }
+
     pub fn get_all_info(&self) -> Result<serde_json::Value> {
         let data = self.stats_data.lock().unwrap();
         let mut resp_json = serde_json::to_value(&*data)?;
         // for skip_serializing
         if let Some(srv_list) = resp_json["servers"].as_array_mut() {
             for (idx, stat) in data.servers.iter().enumerate() {
+                srv_list[idx] = serde_json::to_value(stat.detail())?;
                 if let Some(srv) = srv_list[idx].as_object_mut() {
                     srv.insert("ip_info".into(), serde_json::to_value(stat.ip_info.as_ref())?);
                     srv.insert("sys_info".into(), serde_json::to_value(stat.sys_info.as_ref())?);